@@ -32,6 +32,7 @@ use ffmpeg_dev::sys::{
     AVCodecID_AV_CODEC_ID_H264 as AV_CODEC_ID_H264,
     AV_INPUT_BUFFER_PADDING_SIZE,
 };
+use ffmpeg_dev::extra::parser::Parser;
 
 // TODO: Use defs from `ffmpeg_dev::extra::defs` over hardcoded values.
 pub const NOPTS_VALUE: i64 = -9223372036854775808;
@@ -168,63 +169,31 @@ unsafe fn decode_h264_video(
     // I/O
     let mut f = source;
     // MISC
-    const INBUF_SIZE: u32 = 4096;
+    const INBUF_SIZE: usize = 4096;
     // SETUP AV STATE
     let mut codec: *mut AVCodec = sys::avcodec_find_decoder(AV_CODEC_ID_H264);
     assert!(!codec.is_null());
-    let mut parser: *mut AVCodecParserContext = sys::av_parser_init((*codec).id as i32);
+    let mut parser = Parser::new(AV_CODEC_ID_H264).expect("h264 parser");
     let mut c: *mut AVCodecContext = sys::avcodec_alloc_context3(codec);
     assert!(!c.is_null());
     let mut frame: *mut AVFrame = sys::av_frame_alloc();
-    let mut inbuf: Vec<u8> = vec![0; (INBUF_SIZE + AV_INPUT_BUFFER_PADDING_SIZE) as usize];
-    let mut pkt: *mut AVPacket = sys::av_packet_alloc();
-    assert!(!pkt.is_null());
     // OPEN
     assert!(sys::avcodec_open2(c, codec, std::ptr::null_mut()) >= 0);
     let mut output = Vec::<RawYuv420p>::new();
-    let mut eof = false;
-    while eof == false {
-        let inbuf_size = {
-            if f.len() < INBUF_SIZE as usize {
-                f.len()
-            } else {
-                INBUF_SIZE as usize
-            }
-        };
-        let inbuf = f
-            .drain(0..inbuf_size)
-            .collect::<Vec<u8>>();
-        let mut inbuf_size = inbuf_size as isize;
-        if inbuf.is_empty() {
-            eof = true;
-            break;
-        }
-        while inbuf_size > 0 {
-            let ret = sys::av_parser_parse2(
-                parser,
-                c,
-                &mut (*pkt).data,
-                &mut (*pkt).size,
-                inbuf.as_ptr(),
-                inbuf.len() as i32,
-                NOPTS_VALUE,
-                NOPTS_VALUE,
-                0,
-            );
-            assert!(ret >= 0);
-            inbuf_size = inbuf_size - (ret as isize);
-            if (*pkt).size > 0 {
-                decode(c, frame, pkt, &mut output);
-            }
+    // THE PARSER TAKES CHUNKS OF ANY SIZE AND HANDLES THE INPUT PADDING
+    for inbuf in f.chunks(INBUF_SIZE) {
+        for mut pkt in parser.parse(inbuf).expect("parse h264") {
+            decode(c, frame, pkt.as_mut_ptr(), &mut output);
         }
     }
+    if let Some(mut pkt) = parser.flush().expect("parse h264") {
+        decode(c, frame, pkt.as_mut_ptr(), &mut output);
+    }
     // FLUSH THE DECODER
     decode(c, frame, std::ptr::null_mut(), &mut output);
     // CLEANUP
-    sys::av_parser_close(parser);
     sys::avcodec_free_context(&mut c);
     sys::av_frame_free(&mut frame);
-    sys::av_packet_free(&mut pkt);
     // DONE
     output
 }
@@ -28,6 +28,11 @@ use ffmpeg_dev::sys::{
     AVRounding_AV_ROUND_PASS_MINMAX as AV_ROUND_PASS_MINMAX,
     AVCodecID_AV_CODEC_ID_H264 as AV_CODEC_ID_H264,
 };
+use ffmpeg_dev::extra::{
+    bsf::{self, BitstreamFilter},
+    codec::CodecParameters,
+    packet::Packet,
+};
 
 
 fn c_str(s: &str) -> CString {
@@ -72,10 +77,13 @@ unsafe fn remux(
     ) >= 0);
     // OUTPUT META
     let mut ofmt: *mut sys::AVOutputFormat = (*ofmt_ctx).oformat;
+    let ofmt_name = CStr::from_ptr((*ofmt).name).to_str().expect("muxer name");
 
     // STREAM TRACKER
     let stream_mapping_size: u32 = (*ifmt_ctx).nb_streams;
     let mut stream_mapping: Vec<i32> = vec![0; stream_mapping_size as usize];
+    // E.G. MP4 -> MPEG-TS NEEDS H264 IN ANNEX B FORM
+    let mut stream_filters: Vec<Option<BitstreamFilter>> = Vec::new();
 
     // SOURCE TO DEST STREAMS
     let input_streams = {
@@ -93,15 +101,26 @@ unsafe fn remux(
         };
         if skip {
             stream_mapping[index] = -1;
+            stream_filters.push(None);
         } else {
+            let in_par = CodecParameters::copy_from(in_stream.codecpar).expect("codec parameters");
+            let filter = bsf::required_filter(&in_par, ofmt_name).map(|name| {
+                BitstreamFilter::new(name, &in_par, in_stream.time_base).expect("bitstream filter")
+            });
+            let out_par = match &filter {
+                Some(filter) => filter.output_parameters().expect("codec parameters"),
+                None => in_par,
+            };
             out_stream = sys::avformat_new_stream(ofmt_ctx, std::ptr::null());
             assert!(!out_stream.is_null());
             let status = sys::avcodec_parameters_copy(
                 (*out_stream).codecpar,
-                in_stream.codecpar,
+                out_par.as_ptr(),
             );
             assert!(status >= 0);
             (*(*out_stream).codecpar).codec_tag = 0;
+            stream_mapping[index] = (*out_stream).index;
+            stream_filters.push(filter);
         }
     }
 
@@ -127,15 +146,36 @@ unsafe fn remux(
     );
     assert!(sys::avformat_write_header(ofmt_ctx,  &mut opts) >= 0);
     let mut status = 0;
+    // WRITE A PACKET THAT IS STILL IN THE INPUT STREAM'S TIME BASE
+    let write = |pkt: &mut Packet, in_stream: *mut sys::AVStream, out_index: i32| {
+        let out_stream: *mut sys::AVStream = *(*ofmt_ctx).streams.offset(out_index as isize);
+        // COPY PACKET
+        pkt.set_stream_index(out_index as usize);
+        pkt.set_pts(sys::av_rescale_q_rnd(
+            pkt.pts(),
+            (*in_stream).time_base,
+            (*out_stream).time_base,
+            AV_ROUND_NEAR_INF|AV_ROUND_PASS_MINMAX,
+        ));
+        pkt.set_dts(sys::av_rescale_q_rnd(
+            pkt.dts(),
+            (*in_stream).time_base,
+            (*out_stream).time_base,
+            AV_ROUND_NEAR_INF|AV_ROUND_PASS_MINMAX
+        ));
+        pkt.set_duration(sys::av_rescale_q(
+            pkt.duration(),
+            (*in_stream).time_base,
+            (*out_stream).time_base,
+        ));
+        pkt.set_pos(-1);
+        // WRITE THE COMPRESSED FRAME TO THE MEDIA FILE
+        assert!(sys::av_interleaved_write_frame(ofmt_ctx, pkt.as_mut_ptr()) >= 0);
+    };
     loop {
         if sys::av_read_frame(ifmt_ctx, &mut pkt) != 0 {
             break;
         }
-        // SOURCE
-        let in_stream: *mut sys::AVStream = (*(*ifmt_ctx).streams).offset(pkt.stream_index as isize);
-        assert!(!in_stream.is_null());
-        // DEST
-        let mut out_stream: *mut sys::AVStream = std::ptr::null_mut();
         // ???
         let skip = {
             pkt.stream_index >= stream_mapping.len() as i32 ||
@@ -145,37 +185,31 @@ unsafe fn remux(
             sys::av_packet_unref(&mut pkt);
             continue;
         }
-        pkt.stream_index = stream_mapping[pkt.stream_index as usize];
-        out_stream = (*(*ofmt_ctx).streams).offset(pkt.stream_index as isize);
-        // COPY PACKET
-        pkt.pts = sys::av_rescale_q_rnd(
-            pkt.pts,
-            (*in_stream).time_base,
-            (*out_stream).time_base,
-            AV_ROUND_NEAR_INF|AV_ROUND_PASS_MINMAX,
-        );
-        pkt.dts = sys::av_rescale_q_rnd(
-            pkt.dts,
-            (*in_stream).time_base,
-            (*out_stream).time_base,
-            AV_ROUND_NEAR_INF|AV_ROUND_PASS_MINMAX
-        );
-        pkt.duration = sys::av_rescale_q(
-            pkt.duration,
-            (*in_stream).time_base,
-            (*out_stream).time_base,
-        );
-        pkt.pos = -1;
-        // WRITE
-        
-        // RESCALE OUTPUT PACKET TIMESTAMP VALUES FROM CODEC TO STREAM TIMEBASE
-        // av_packet_rescale_ts(pkt, *time_base, st->time_base);
-        // pkt->stream_index = st->index;
-        
-        // WRITE THE COMPRESSED FRAME TO THE MEDIA FILE
-        assert!(sys::av_interleaved_write_frame(ofmt_ctx, &mut pkt) >= 0);
-        // assert!(av_write_frame(ofmt_ctx, &mut pkt) >= 0);
-        sys::av_packet_unref(&mut pkt);
+        // SOURCE
+        let in_index = pkt.stream_index as usize;
+        let in_stream: *mut sys::AVStream = *(*ifmt_ctx).streams.add(in_index);
+        assert!(!in_stream.is_null());
+        // DEST
+        let out_index = stream_mapping[in_index];
+        let mut packet = Packet::take_from(&mut pkt).expect("packet");
+        match stream_filters[in_index].as_mut() {
+            Some(filter) => {
+                for mut packet in filter.filter(packet).expect("bitstream filter") {
+                    write(&mut packet, in_stream, out_index);
+                }
+            }
+            None => write(&mut packet, in_stream, out_index),
+        }
+    }
+
+    // DRAIN BITSTREAM FILTERS
+    for (in_index, filter) in stream_filters.iter_mut().enumerate() {
+        if let Some(filter) = filter {
+            let in_stream: *mut sys::AVStream = *(*ifmt_ctx).streams.add(in_index);
+            for mut packet in filter.finish().expect("bitstream filter") {
+                write(&mut packet, in_stream, stream_mapping[in_index]);
+            }
+        }
     }
 
     sys::av_write_trailer(ofmt_ctx);
@@ -1,10 +1,41 @@
 //! extra stuff
 pub mod defs;
+pub mod audio;
+pub mod audio_fifo;
+pub mod bsf;
+pub mod capabilities;
+pub mod error;
+pub mod codec;
+pub mod decode;
+pub mod detect;
+pub mod filter;
+pub mod format;
+pub mod image;
+pub mod io;
+pub mod mem;
+pub mod metadata;
+pub mod frame;
+pub mod hash;
+pub mod option;
+pub mod packet;
+pub mod parser;
+pub mod probe;
+pub mod remux;
+pub mod resample;
+pub mod scale;
+pub mod side_data;
+pub mod subtitle;
+pub mod thumbnail;
+pub mod transcode;
+pub mod waveform;
+pub(crate) mod util;
 
 use std::ffi::{CString, c_void};
 use std::os::raw::{c_char, c_int};
 use libc::{size_t, c_float};
 
+pub use error::{Error, Result};
+
 
 // #[link(name = "cbits")]
 // extern "C" {
@@ -0,0 +1,286 @@
+//! Decoding audio into plain sample vectors.
+//!
+//! Encoder delay and padding (`AV_PKT_DATA_SKIP_SAMPLES`, e.g. from MP4
+//! edit lists or LAME/iTunSMPB headers) are trimmed by libavcodec itself,
+//! so the output has the stream's exact duration.
+pub mod analysis;
+
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::decode::StreamDecoder;
+use crate::extra::error::{Error, Result};
+use crate::extra::format::InputContext;
+use crate::extra::frame::Frame;
+use crate::extra::resample::{AudioFormat, Resampler, ResamplerOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleType {
+    /// -1.0 to 1.0.
+    F32,
+    I16,
+}
+
+impl SampleType {
+    fn sample_fmt(self, planar: bool) -> sys::AVSampleFormat {
+        match (self, planar) {
+            (SampleType::F32, false) => sys::AVSampleFormat_AV_SAMPLE_FMT_FLT,
+            (SampleType::F32, true) => sys::AVSampleFormat_AV_SAMPLE_FMT_FLTP,
+            (SampleType::I16, false) => sys::AVSampleFormat_AV_SAMPLE_FMT_S16,
+            (SampleType::I16, true) => sys::AVSampleFormat_AV_SAMPLE_FMT_S16P,
+        }
+    }
+}
+
+/// What `decode_audio` converts to.
+#[derive(Debug, Clone)]
+pub struct AudioTarget {
+    /// `None` keeps the rate of the first frame, even if the source changes
+    /// it later.
+    pub sample_rate: Option<i32>,
+    /// `AV_CH_LAYOUT_*` bits, e.g. `AV_CH_LAYOUT_MONO`; `None` keeps the
+    /// layout of the first frame.
+    pub channel_layout: Option<u64>,
+    pub sample_type: SampleType,
+    /// One channel after the other rather than interleaved.
+    pub planar: bool,
+    pub resampler: ResamplerOptions,
+}
+
+impl Default for AudioTarget {
+    fn default() -> Self {
+        AudioTarget {
+            sample_rate: None,
+            channel_layout: None,
+            sample_type: SampleType::F32,
+            planar: false,
+            resampler: ResamplerOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Samples {
+    F32(Vec<f32>),
+    I16(Vec<i16>),
+}
+
+impl Samples {
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::F32(x) => x.len(),
+            Samples::I16(x) => x.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Decoded samples, `nb_samples() * channels` values in total.
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    pub sample_rate: i32,
+    pub channel_layout: u64,
+    pub channels: usize,
+    /// Whether `samples` holds one channel after the other, rather than
+    /// interleaved samples.
+    pub planar: bool,
+    pub samples: Samples,
+    /// Time of the first sample in seconds, from the start of the output.
+    pub start_time: f64,
+}
+
+impl AudioBuffer {
+    /// Samples per channel.
+    pub fn nb_samples(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+    /// In seconds.
+    pub fn duration(&self) -> f64 {
+        self.nb_samples() as f64 / self.sample_rate as f64
+    }
+    fn from_frame(frame: &Frame, format: AudioFormat, sample_type: SampleType, planar: bool) -> Self {
+        let channels = format.channels().max(0) as usize;
+        let nb_samples = frame.nb_samples().max(0) as usize;
+        let samples = unsafe {
+            let data = (*frame.as_ptr()).extended_data;
+            match sample_type {
+                SampleType::F32 => Samples::F32(copy_samples(data as *const *const f32, channels, nb_samples, planar)),
+                SampleType::I16 => Samples::I16(copy_samples(data as *const *const i16, channels, nb_samples, planar)),
+            }
+        };
+        AudioBuffer {
+            sample_rate: format.sample_rate,
+            channel_layout: format.channel_layout,
+            channels,
+            planar,
+            samples,
+            start_time: frame.pts() as f64 / format.sample_rate as f64,
+        }
+    }
+}
+
+unsafe fn copy_samples<T: Copy>(data: *const *const T, channels: usize, nb_samples: usize, planar: bool) -> Vec<T> {
+    if planar {
+        let mut output = Vec::with_capacity(channels * nb_samples);
+        for channel in 0..channels {
+            output.extend_from_slice(std::slice::from_raw_parts(*data.add(channel), nb_samples));
+        }
+        output
+    } else {
+        std::slice::from_raw_parts(*data, channels * nb_samples).to_vec()
+    }
+}
+
+/// The samples `decode_audio` collected so far, one vector per channel when
+/// planar so that appending never moves what's already there.
+enum Lanes {
+    F32(Vec<Vec<f32>>),
+    I16(Vec<Vec<i16>>),
+}
+
+impl Lanes {
+    fn new(sample_type: SampleType, lanes: usize) -> Self {
+        match sample_type {
+            SampleType::F32 => Lanes::F32(vec![Vec::new(); lanes]),
+            SampleType::I16 => Lanes::I16(vec![Vec::new(); lanes]),
+        }
+    }
+    fn push(&mut self, samples: &Samples) {
+        match (self, samples) {
+            (Lanes::F32(x), Samples::F32(y)) => push_lanes(x, y),
+            (Lanes::I16(x), Samples::I16(y)) => push_lanes(x, y),
+            _ => unreachable!("mismatched sample types"),
+        }
+    }
+    fn join(self) -> Samples {
+        match self {
+            Lanes::F32(x) => Samples::F32(x.concat()),
+            Lanes::I16(x) => Samples::I16(x.concat()),
+        }
+    }
+}
+
+fn push_lanes<T: Copy>(lanes: &mut [Vec<T>], samples: &[T]) {
+    let len = samples.len() / lanes.len();
+    for (index, lane) in lanes.iter_mut().enumerate() {
+        lane.extend_from_slice(&samples[index * len..(index + 1) * len]);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DECODING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Yields the decoded audio in chunks of about one codec frame, see
+/// `decode_audio_chunks`.
+pub struct AudioChunks {
+    decoder: StreamDecoder,
+    resampler: Option<Resampler>,
+    target: AudioTarget,
+    /// Held back while the previous resampler drains.
+    pending: Option<Frame>,
+    /// Samples per channel returned so far.
+    samples_out: i64,
+    finished: bool,
+}
+
+impl AudioChunks {
+    fn to_buffer(&mut self, frame: &Frame) -> AudioBuffer {
+        let dst = self.resampler.as_ref().expect("resampler").dst_format();
+        let mut buffer = AudioBuffer::from_frame(frame, dst, self.target.sample_type, self.target.planar);
+        buffer.start_time = self.samples_out as f64 / dst.sample_rate as f64;
+        self.samples_out += buffer.nb_samples() as i64;
+        buffer
+    }
+    /// Drains the current resampler, if any.
+    fn flush(&mut self) -> Result<Option<AudioBuffer>> {
+        let flushed = match self.resampler.as_mut() {
+            Some(resampler) => resampler.flush()?,
+            None => None,
+        };
+        Ok(flushed.map(|frame| self.to_buffer(&frame)))
+    }
+    fn next_buffer(&mut self) -> Result<Option<AudioBuffer>> {
+        while !self.finished {
+            let frame = match self.pending.take() {
+                Some(frame) => frame,
+                None => match self.decoder.next_frame()? {
+                    Some(frame) => frame,
+                    None => {
+                        if let Some(buffer) = self.flush()? {
+                            return Ok(Some(buffer));
+                        }
+                        self.finished = true;
+                        break;
+                    }
+                },
+            };
+            let src = AudioFormat::of_frame(&frame);
+            let reusable = self.resampler.as_ref().map(|x| x.src_format() == src).unwrap_or(false);
+            if !reusable {
+                // THE SOURCE FORMAT CHANGED, DRAIN THE OLD RESAMPLER FIRST
+                if let Some(buffer) = self.flush()? {
+                    self.pending = Some(frame);
+                    return Ok(Some(buffer));
+                }
+                // PINNED ON THE FIRST FRAME, SO ALL CHUNKS SHARE ONE FORMAT
+                let dst = AudioFormat {
+                    sample_fmt: self.target.sample_type.sample_fmt(self.target.planar),
+                    sample_rate: *self.target.sample_rate.get_or_insert(src.sample_rate),
+                    channel_layout: *self.target.channel_layout.get_or_insert(src.channel_layout),
+                };
+                self.resampler = Some(Resampler::new(src, dst, self.target.resampler.clone())?);
+            }
+            let converted = self.resampler.as_mut().expect("resampler").convert(&frame)?;
+            if converted.nb_samples() > 0 {
+                return Ok(Some(self.to_buffer(&converted)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for AudioChunks {
+    type Item = Result<AudioBuffer>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_buffer() {
+            Ok(Some(buffer)) => Some(Ok(buffer)),
+            Ok(None) => None,
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Streams the main audio stream of `input`, converted to `target`.
+pub fn decode_audio_chunks(input: InputContext, target: &AudioTarget) -> Result<AudioChunks> {
+    Ok(AudioChunks {
+        decoder: StreamDecoder::best(input, MediaType::Audio, &[])?,
+        resampler: None,
+        target: target.clone(),
+        pending: None,
+        samples_out: 0,
+        finished: false,
+    })
+}
+
+/// Decodes the whole main audio stream of `input` into one buffer.
+pub fn decode_audio(input: InputContext, target: &AudioTarget) -> Result<AudioBuffer> {
+    let mut output: Option<AudioBuffer> = None;
+    let mut lanes = Lanes::new(target.sample_type, 1);
+    for buffer in decode_audio_chunks(input, target)? {
+        let buffer = buffer?;
+        if output.is_none() {
+            let count = if buffer.planar { buffer.channels.max(1) } else { 1 };
+            lanes = Lanes::new(target.sample_type, count);
+        }
+        lanes.push(&buffer.samples);
+        output.get_or_insert(buffer);
+    }
+    let mut output = output.ok_or_else(|| Error::Invalid(String::from("no audio could be decoded")))?;
+    output.samples = lanes.join();
+    Ok(output)
+}
@@ -0,0 +1,195 @@
+//! Loudness, level and silence measurements, read from the frame metadata
+//! of the `ebur128`, `astats` and `silencedetect` filters.
+//!
+//! `volumedetect` and `loudnorm` only report through the log, so their
+//! figures come from `astats` and `ebur128` instead: `AudioAnalysis::rms_level`
+//! and `peak_level` are `volumedetect`'s mean and max volume, and
+//! `normalization_gain` is what a linear `loudnorm` pass would apply.
+use std::collections::HashMap;
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::decode::StreamDecoder;
+use crate::extra::defs;
+use crate::extra::error::Result;
+use crate::extra::filter::{AudioInput, AudioOutput, FilterGraph};
+use crate::extra::format::InputContext;
+use crate::extra::frame::Frame;
+
+#[derive(Debug, Clone)]
+pub struct AnalyzeOptions {
+    /// Level below which audio counts as silence, in dBFS.
+    pub silence_threshold: f64,
+    /// Shortest silence reported, in seconds.
+    pub silence_min_duration: f64,
+}
+
+impl Default for AnalyzeOptions {
+    fn default() -> Self {
+        AnalyzeOptions {
+            silence_threshold: -50.0,
+            silence_min_duration: 0.5,
+        }
+    }
+}
+
+/// In dBFS; `-inf` for digital silence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevels {
+    pub rms_level: f64,
+    pub peak_level: f64,
+}
+
+/// From the start of the input, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Silence {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Silence {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioAnalysis {
+    /// EBU R128 integrated loudness, in LUFS.
+    pub integrated_loudness: Option<f64>,
+    /// EBU R128 loudness range, in LU.
+    pub loudness_range: Option<f64>,
+    /// Highest true peak of all channels, in dBTP.
+    pub true_peak: Option<f64>,
+    /// Over all channels, in dBFS.
+    pub rms_level: Option<f64>,
+    pub peak_level: Option<f64>,
+    pub channels: Vec<ChannelLevels>,
+    pub silences: Vec<Silence>,
+    /// Of the analysed audio, in seconds.
+    pub duration: f64,
+}
+
+impl AudioAnalysis {
+    /// Gain in dB that brings the integrated loudness to `target` LUFS
+    /// (e.g. -23 for EBU R128, -14 for streaming services), lowered as far
+    /// as needed to keep the true peak under `max_true_peak` dBTP.
+    pub fn normalization_gain(&self, target: f64, max_true_peak: f64) -> Option<f64> {
+        let gain = target - self.integrated_loudness?;
+        match self.true_peak {
+            Some(peak) if peak.is_finite() => Some(gain.min(max_true_peak - peak)),
+            _ => Some(gain),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// METADATA
+///////////////////////////////////////////////////////////////////////////////
+
+/// Latest value of each metadata key seen so far, plus the silence events
+/// in order.
+#[derive(Default)]
+struct Collected {
+    values: HashMap<String, String>,
+    silence_start: Option<f64>,
+    silences: Vec<Silence>,
+}
+
+impl Collected {
+    fn add(&mut self, frame: &Frame, start_time: f64) {
+        for (key, value) in frame.metadata() {
+            match key.as_str() {
+                "lavfi.silence_start" => {
+                    self.silence_start = value.parse::<f64>().ok().map(|x| x - start_time);
+                }
+                "lavfi.silence_end" => {
+                    if let (Some(start), Ok(end)) = (self.silence_start.take(), value.parse::<f64>()) {
+                        self.silences.push(Silence {start, end: end - start_time});
+                    }
+                }
+                _ => {
+                    self.values.insert(key, value);
+                }
+            }
+        }
+    }
+    fn get(&self, key: &str) -> Option<f64> {
+        self.values.get(key).and_then(|x| x.trim().parse().ok())
+    }
+    fn into_analysis(mut self, channels: usize, duration: f64) -> AudioAnalysis {
+        // SILENCE RUNNING UNTIL THE END IS ONLY LOGGED, NOT TAGGED
+        if let Some(start) = self.silence_start.take() {
+            self.silences.push(Silence {start, end: duration});
+        }
+        // `ebur128` REPORTS LINEAR PEAKS
+        let true_peak = self.values
+            .iter()
+            .filter(|(key, _)| key.starts_with("lavfi.r128.true_peaks_ch"))
+            .filter_map(|(_, value)| value.trim().parse::<f64>().ok())
+            .fold(None, |max: Option<f64>, x| Some(max.map_or(x, |max| max.max(x))))
+            .map(|x| 20.0 * x.log10());
+        let channels = (1..=channels)
+            .filter_map(|channel| {
+                Some(ChannelLevels {
+                    rms_level: self.get(&format!("lavfi.astats.{}.RMS_level", channel))?,
+                    peak_level: self.get(&format!("lavfi.astats.{}.Peak_level", channel))?,
+                })
+            })
+            .collect();
+        AudioAnalysis {
+            integrated_loudness: self.get("lavfi.r128.I"),
+            loudness_range: self.get("lavfi.r128.LRA"),
+            true_peak,
+            rms_level: self.get("lavfi.astats.Overall.RMS_level"),
+            peak_level: self.get("lavfi.astats.Overall.Peak_level"),
+            channels,
+            silences: self.silences,
+            duration,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// ANALYSIS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Decodes the main audio stream of `input` and measures its loudness,
+/// levels and silences in one pass.
+pub fn analyze_audio(input: InputContext, options: &AnalyzeOptions) -> Result<AudioAnalysis> {
+    let mut decoder = StreamDecoder::best(input, MediaType::Audio, &[])?;
+    let format = decoder.audio_format();
+    let start_time = decoder.input().start_time() as f64 / sys::AV_TIME_BASE as f64;
+    let description = format!(
+        "ebur128=metadata=1:peak=true,silencedetect=noise={}dB:duration={},astats=metadata=1",
+        options.silence_threshold,
+        options.silence_min_duration,
+    );
+    let mut graph = FilterGraph::new_audio(
+        &description,
+        AudioInput {format, time_base: decoder.time_base()},
+        AudioOutput::default(),
+    )?;
+    let time_base = graph.output_time_base("out")?;
+    let mut collected = Collected::default();
+    let mut end: Option<f64> = None;
+    let mut on_frame = |frame: Frame| {
+        collected.add(&frame, start_time);
+        if frame.pts() != unsafe { defs::av_nopts_value() } {
+            let pts = frame.pts() as f64 * time_base.num as f64 / time_base.den as f64;
+            let frame_end = pts + frame.nb_samples() as f64 / frame.sample_rate() as f64;
+            end = Some(end.map_or(frame_end, |x: f64| x.max(frame_end)));
+        }
+    };
+    while let Some(frame) = decoder.next_frame()? {
+        graph.push("in", &frame)?;
+        while let Some(frame) = graph.pull("out")? {
+            on_frame(frame);
+        }
+    }
+    graph.push_eof("in")?;
+    while let Some(frame) = graph.pull("out")? {
+        on_frame(frame);
+    }
+    let duration = end.map(|x| x - start_time).unwrap_or(0.0).max(0.0);
+    Ok(collected.into_analysis(format.channels().max(0) as usize, duration))
+}
@@ -0,0 +1,84 @@
+//! Sample queue for re-chunking audio, see `libavutil/audio_fifo.h`.
+use std::os::raw::{c_int, c_void};
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::Frame;
+use crate::extra::resample::AudioFormat;
+
+/// Buffers samples of one `AudioFormat` so they can be read back in chunks
+/// of a different size, e.g. the fixed `frame_size` of an AAC encoder.
+pub struct AudioFifo {
+    ptr: *mut sys::AVAudioFifo,
+    format: AudioFormat,
+}
+
+impl AudioFifo {
+    pub fn new(format: AudioFormat) -> Result<Self> {
+        // GROWS ON DEMAND
+        let ptr = unsafe { sys::av_audio_fifo_alloc(format.sample_fmt, format.channels(), 1024) };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVAudioFifo"));
+        }
+        Ok(AudioFifo {ptr, format})
+    }
+    pub fn format(&self) -> AudioFormat {
+        self.format
+    }
+    /// Number of queued samples per channel.
+    pub fn len(&self) -> usize {
+        unsafe { sys::av_audio_fifo_size(self.ptr) as usize }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Queues every sample of `frame`, which must be in `format()`.
+    pub fn write(&mut self, frame: &Frame) -> Result<()> {
+        if AudioFormat::of_frame(frame) != self.format {
+            return Err(Error::Invalid(String::from("frame doesn't match the FIFO's audio format")));
+        }
+        unsafe {
+            let frame = &*frame.as_ptr();
+            let data = frame.extended_data as *mut *mut c_void;
+            let written = check(sys::av_audio_fifo_write(self.ptr, data, frame.nb_samples))?;
+            if written < frame.nb_samples {
+                return Err(Error::Alloc("AVAudioFifo samples"));
+            }
+        }
+        Ok(())
+    }
+    /// Dequeues up to `nb_samples` samples into a new frame; `None` when
+    /// the FIFO is empty. The frame's `pts` is left unset.
+    pub fn read(&mut self, nb_samples: usize) -> Result<Option<Frame>> {
+        let nb_samples = nb_samples.min(self.len());
+        if nb_samples == 0 {
+            return Ok(None);
+        }
+        let mut frame = Frame::new_audio(
+            nb_samples as c_int,
+            self.format.sample_fmt,
+            self.format.channel_layout,
+            self.format.sample_rate,
+        )?;
+        unsafe {
+            let data = (*frame.as_mut_ptr()).extended_data as *mut *mut c_void;
+            check(sys::av_audio_fifo_read(self.ptr, data, nb_samples as c_int))?;
+        }
+        Ok(Some(frame))
+    }
+    /// Drops every queued sample.
+    pub fn clear(&mut self) {
+        unsafe {
+            sys::av_audio_fifo_reset(self.ptr);
+        }
+    }
+}
+
+impl Drop for AudioFifo {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_audio_fifo_free(self.ptr);
+        }
+    }
+}
+
+unsafe impl Send for AudioFifo {}
@@ -0,0 +1,143 @@
+//! Bitstream filters, e.g. `h264_mp4toannexb` or `aac_adtstoasc`.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::codec::CodecParameters;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::packet::Packet;
+use crate::extra::util::c_str;
+
+pub struct BitstreamFilter {
+    ptr: *mut sys::AVBSFContext,
+}
+
+impl BitstreamFilter {
+    /// A single filter by name, e.g. `h264_mp4toannexb`.
+    pub fn new(name: &str, par: &CodecParameters, time_base: sys::AVRational) -> Result<Self> {
+        let name_cstr = c_str(name)?;
+        unsafe {
+            let filter = sys::av_bsf_get_by_name(name_cstr.as_ptr());
+            if filter.is_null() {
+                return Err(Error::NotFound(format!("bitstream filter {:?}", name)));
+            }
+            let mut ptr: *mut sys::AVBSFContext = std::ptr::null_mut();
+            check(sys::av_bsf_alloc(filter, &mut ptr))?;
+            BitstreamFilter::init(BitstreamFilter {ptr}, par, time_base)
+        }
+    }
+    /// A chain such as `h264_metadata=level=4.1,h264_mp4toannexb`; an empty
+    /// string is the `null` filter.
+    pub fn from_chain(chain: &str, par: &CodecParameters, time_base: sys::AVRational) -> Result<Self> {
+        let chain = c_str(chain)?;
+        unsafe {
+            let mut ptr: *mut sys::AVBSFContext = std::ptr::null_mut();
+            check(sys::av_bsf_list_parse_str(chain.as_ptr(), &mut ptr))?;
+            BitstreamFilter::init(BitstreamFilter {ptr}, par, time_base)
+        }
+    }
+    unsafe fn init(self, par: &CodecParameters, time_base: sys::AVRational) -> Result<Self> {
+        check(sys::avcodec_parameters_copy((*self.ptr).par_in, par.as_ptr()))?;
+        (*self.ptr).time_base_in = time_base;
+        check(sys::av_bsf_init(self.ptr))?;
+        Ok(self)
+    }
+    /// Stream parameters after filtering, e.g. with rewritten extradata.
+    pub fn output_parameters(&self) -> Result<CodecParameters> {
+        unsafe { CodecParameters::copy_from((*self.ptr).par_out) }
+    }
+    pub fn output_time_base(&self) -> sys::AVRational {
+        unsafe { (*self.ptr).time_base_out }
+    }
+    /// Submits a packet; its references move into the filter.
+    pub fn send(&mut self, pkt: &mut Packet) -> Result<()> {
+        unsafe {
+            check(sys::av_bsf_send_packet(self.ptr, pkt.as_mut_ptr()))?;
+        }
+        Ok(())
+    }
+    /// Signals end of stream, so `receive` returns whatever is buffered.
+    pub fn send_eof(&mut self) -> Result<()> {
+        unsafe {
+            check(sys::av_bsf_send_packet(self.ptr, std::ptr::null_mut()))?;
+        }
+        Ok(())
+    }
+    /// `None` when the filter needs more input or is drained.
+    pub fn receive(&mut self) -> Result<Option<Packet>> {
+        let mut pkt = Packet::new()?;
+        match check(unsafe { sys::av_bsf_receive_packet(self.ptr, pkt.as_mut_ptr()) }) {
+            Ok(_) => Ok(Some(pkt)),
+            Err(ref err) if err.is_again() || err.is_eof() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+    /// Sends `pkt` and collects every packet that comes out.
+    pub fn filter(&mut self, mut pkt: Packet) -> Result<Vec<Packet>> {
+        self.send(&mut pkt)?;
+        let mut output = Vec::new();
+        while let Some(x) = self.receive()? {
+            output.push(x);
+        }
+        Ok(output)
+    }
+    /// Drains the filter at end of stream.
+    pub fn finish(&mut self) -> Result<Vec<Packet>> {
+        self.send_eof()?;
+        let mut output = Vec::new();
+        while let Some(x) = self.receive()? {
+            output.push(x);
+        }
+        Ok(output)
+    }
+    /// Resets the internal state, e.g. after seeking.
+    pub fn flush(&mut self) {
+        unsafe {
+            sys::av_bsf_flush(self.ptr);
+        };
+    }
+    pub fn as_ptr(&self) -> *const sys::AVBSFContext {
+        self.ptr
+    }
+}
+
+impl Drop for BitstreamFilter {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_bsf_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for BitstreamFilter {}
+
+fn starts_with_start_code(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1])
+}
+
+/// The bitstream filter needed to stream copy a stream with `par` into the
+/// `output_format` muxer (e.g. `mpegts`, `mp4`), if any.
+///
+/// Some muxers insert these on their own when writing through
+/// `av_interleaved_write_frame`; applying them up front also gives the
+/// muxer the right extradata before the header is written.
+pub fn required_filter(par: &CodecParameters, output_format: &str) -> Option<&'static str> {
+    const ANNEXB_MUXERS: &[&str] = &["mpegts", "h264", "hevc", "rtp_mpegts"];
+    const ASC_MUXERS: &[&str] = &[
+        "mp4", "mov", "ipod", "ismv", "f4v", "psp", "3gp", "3g2", "flv", "matroska", "webm",
+    ];
+    let extradata = par.extradata();
+    // MP4 STYLE (AVCC/HVCC) EXTRADATA RATHER THAN START CODES
+    let is_length_prefixed = !extradata.is_empty() && !starts_with_start_code(extradata);
+    match par.codec_id() {
+        sys::AVCodecID_AV_CODEC_ID_H264 if is_length_prefixed && ANNEXB_MUXERS.contains(&output_format) => {
+            Some("h264_mp4toannexb")
+        }
+        sys::AVCodecID_AV_CODEC_ID_HEVC if is_length_prefixed && ANNEXB_MUXERS.contains(&output_format) => {
+            Some("hevc_mp4toannexb")
+        }
+        // ADTS AAC (E.G. FROM MPEG-TS) CARRIES NO AUDIO SPECIFIC CONFIG
+        sys::AVCodecID_AV_CODEC_ID_AAC if extradata.is_empty() && ASC_MUXERS.contains(&output_format) => {
+            Some("aac_adtstoasc")
+        }
+        _ => None,
+    }
+}
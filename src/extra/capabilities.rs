@@ -0,0 +1,347 @@
+//! What the bundled FFmpeg build supports, queried at runtime.
+//!
+//! Each iterator walks one of FFmpeg's registries (`av_codec_iterate`,
+//! `av_demuxer_iterate`, ...) and copies every entry into an owned
+//! descriptor.
+use std::os::raw::{c_char, c_int, c_void};
+use crate::sys;
+use crate::extra::util::from_c_str;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+    Video,
+    Audio,
+    Data,
+    Subtitle,
+    Attachment,
+    Unknown,
+}
+
+impl MediaType {
+    pub fn from_raw(x: sys::AVMediaType) -> Self {
+        match x {
+            sys::AVMediaType_AVMEDIA_TYPE_VIDEO => MediaType::Video,
+            sys::AVMediaType_AVMEDIA_TYPE_AUDIO => MediaType::Audio,
+            sys::AVMediaType_AVMEDIA_TYPE_DATA => MediaType::Data,
+            sys::AVMediaType_AVMEDIA_TYPE_SUBTITLE => MediaType::Subtitle,
+            sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT => MediaType::Attachment,
+            _ => MediaType::Unknown,
+        }
+    }
+    pub fn to_raw(self) -> sys::AVMediaType {
+        match self {
+            MediaType::Video => sys::AVMediaType_AVMEDIA_TYPE_VIDEO,
+            MediaType::Audio => sys::AVMediaType_AVMEDIA_TYPE_AUDIO,
+            MediaType::Data => sys::AVMediaType_AVMEDIA_TYPE_DATA,
+            MediaType::Subtitle => sys::AVMediaType_AVMEDIA_TYPE_SUBTITLE,
+            MediaType::Attachment => sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT,
+            MediaType::Unknown => sys::AVMediaType_AVMEDIA_TYPE_UNKNOWN,
+        }
+    }
+    /// `video`, `audio`, ... as printed by ffprobe.
+    pub fn name(self) -> &'static str {
+        match self {
+            MediaType::Video => "video",
+            MediaType::Audio => "audio",
+            MediaType::Data => "data",
+            MediaType::Subtitle => "subtitle",
+            MediaType::Attachment => "attachment",
+            MediaType::Unknown => "unknown",
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DESCRIPTORS
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// `FF_PROFILE_*`.
+    pub id: i32,
+    pub name: String,
+}
+
+/// One `AVCodec`; decoders and encoders of the same format are separate
+/// entries.
+#[derive(Debug, Clone)]
+pub struct CodecInfo {
+    pub name: String,
+    pub long_name: Option<String>,
+    pub media_type: MediaType,
+    pub id: sys::AVCodecID,
+    pub is_encoder: bool,
+    /// `AV_CODEC_CAP_*` bits.
+    pub capabilities: c_int,
+    /// Empty when the codec doesn't restrict them (or they don't apply).
+    pub pix_fmts: Vec<sys::AVPixelFormat>,
+    pub sample_fmts: Vec<sys::AVSampleFormat>,
+    pub sample_rates: Vec<i32>,
+    pub channel_layouts: Vec<u64>,
+    pub profiles: Vec<Profile>,
+    /// External library behind the codec, e.g. `libx264`.
+    pub wrapper_name: Option<String>,
+}
+
+impl CodecInfo {
+    pub unsafe fn from_raw(codec: *const sys::AVCodec) -> Self {
+        let codec = &*codec;
+        CodecInfo {
+            name: from_c_str(codec.name).unwrap_or_default(),
+            long_name: from_c_str(codec.long_name),
+            media_type: MediaType::from_raw(codec.type_),
+            id: codec.id,
+            is_encoder: sys::av_codec_is_encoder(codec) != 0,
+            capabilities: codec.capabilities,
+            pix_fmts: terminated(codec.pix_fmts, sys::AVPixelFormat_AV_PIX_FMT_NONE),
+            sample_fmts: terminated(codec.sample_fmts, sys::AVSampleFormat_AV_SAMPLE_FMT_NONE),
+            sample_rates: terminated(codec.supported_samplerates, 0),
+            channel_layouts: terminated(codec.channel_layouts, 0),
+            profiles: profiles(codec.profiles),
+            wrapper_name: from_c_str(codec.wrapper_name),
+        }
+    }
+    pub fn is_decoder(&self) -> bool {
+        !self.is_encoder
+    }
+    /// E.g. `has_capability(sys::AV_CODEC_CAP_VARIABLE_FRAME_SIZE)`.
+    pub fn has_capability(&self, flag: u32) -> bool {
+        self.capabilities & flag as c_int != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DemuxerInfo {
+    pub name: String,
+    pub long_name: Option<String>,
+    /// `AVFMT_*` bits.
+    pub flags: c_int,
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MuxerInfo {
+    pub name: String,
+    pub long_name: Option<String>,
+    /// `AVFMT_*` bits.
+    pub flags: c_int,
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>,
+    /// Defaults picked by the `ffmpeg` CLI; `AV_CODEC_ID_NONE` if none.
+    pub video_codec: sys::AVCodecID,
+    pub audio_codec: sys::AVCodecID,
+    pub subtitle_codec: sys::AVCodecID,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    pub name: String,
+    pub input: bool,
+    pub output: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterPad {
+    pub name: String,
+    pub media_type: MediaType,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterInfo {
+    pub name: String,
+    pub description: Option<String>,
+    /// `AVFILTER_FLAG_*` bits.
+    pub flags: c_int,
+    /// The static pads; filters with `AVFILTER_FLAG_DYNAMIC_INPUTS` or
+    /// `AVFILTER_FLAG_DYNAMIC_OUTPUTS` create more when configured.
+    pub inputs: Vec<FilterPad>,
+    pub outputs: Vec<FilterPad>,
+}
+
+impl FilterInfo {
+    pub fn has_dynamic_inputs(&self) -> bool {
+        self.flags & sys::AVFILTER_FLAG_DYNAMIC_INPUTS as c_int != 0
+    }
+    pub fn has_dynamic_outputs(&self) -> bool {
+        self.flags & sys::AVFILTER_FLAG_DYNAMIC_OUTPUTS as c_int != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BitstreamFilterInfo {
+    pub name: String,
+    /// Empty if the filter works with any codec.
+    pub codec_ids: Vec<sys::AVCodecID>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Copies a list terminated by `end`, e.g. `AVCodec::pix_fmts`.
+unsafe fn terminated<T: Copy + PartialEq>(mut ptr: *const T, end: T) -> Vec<T> {
+    let mut output = Vec::new();
+    if ptr.is_null() {
+        return output;
+    }
+    while *ptr != end {
+        output.push(*ptr);
+        ptr = ptr.add(1);
+    }
+    output
+}
+
+unsafe fn profiles(mut ptr: *const sys::AVProfile) -> Vec<Profile> {
+    let mut output = Vec::new();
+    if ptr.is_null() {
+        return output;
+    }
+    while (*ptr).profile != sys::FF_PROFILE_UNKNOWN {
+        output.push(Profile {
+            id: (*ptr).profile,
+            name: from_c_str((*ptr).name).unwrap_or_default(),
+        });
+        ptr = ptr.add(1);
+    }
+    output
+}
+
+/// Splits `mp4,m4a,3gp`-style lists.
+unsafe fn comma_list(ptr: *const c_char) -> Vec<String> {
+    from_c_str(ptr)
+        .map(|x| {
+            x.split(',')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+unsafe fn filter_pads(pads: *const sys::AVFilterPad) -> Vec<FilterPad> {
+    if pads.is_null() {
+        return Vec::new();
+    }
+    (0..sys::avfilter_pad_count(pads))
+        .map(|index| FilterPad {
+            name: from_c_str(sys::avfilter_pad_get_name(pads, index)).unwrap_or_default(),
+            media_type: MediaType::from_raw(sys::avfilter_pad_get_type(pads, index)),
+        })
+        .collect()
+}
+
+/// Drives one of the `av_*_iterate(void **opaque)` functions.
+struct Registry<T> {
+    opaque: *mut c_void,
+    next: unsafe extern "C" fn(*mut *mut c_void) -> *const T,
+}
+
+impl<T> Registry<T> {
+    fn new(next: unsafe extern "C" fn(*mut *mut c_void) -> *const T) -> Self {
+        Registry {opaque: std::ptr::null_mut(), next}
+    }
+}
+
+impl<T> Iterator for Registry<T> {
+    type Item = *const T;
+    fn next(&mut self) -> Option<*const T> {
+        let ptr = unsafe { (self.next)(&mut self.opaque) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ptr)
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// ITERATORS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Every decoder and encoder, in registration order.
+pub fn codecs() -> impl Iterator<Item = CodecInfo> {
+    Registry::new(sys::av_codec_iterate).map(|x| unsafe { CodecInfo::from_raw(x) })
+}
+
+pub fn decoders() -> impl Iterator<Item = CodecInfo> {
+    codecs().filter(|x| x.is_decoder())
+}
+
+pub fn encoders() -> impl Iterator<Item = CodecInfo> {
+    codecs().filter(|x| x.is_encoder)
+}
+
+pub fn demuxers() -> impl Iterator<Item = DemuxerInfo> {
+    Registry::new(sys::av_demuxer_iterate).map(|x| unsafe {
+        let format = &*x;
+        DemuxerInfo {
+            name: from_c_str(format.name).unwrap_or_default(),
+            long_name: from_c_str(format.long_name),
+            flags: format.flags,
+            extensions: comma_list(format.extensions),
+            mime_types: comma_list(format.mime_type),
+        }
+    })
+}
+
+pub fn muxers() -> impl Iterator<Item = MuxerInfo> {
+    Registry::new(sys::av_muxer_iterate).map(|x| unsafe {
+        let format = &*x;
+        MuxerInfo {
+            name: from_c_str(format.name).unwrap_or_default(),
+            long_name: from_c_str(format.long_name),
+            flags: format.flags,
+            extensions: comma_list(format.extensions),
+            mime_types: comma_list(format.mime_type),
+            video_codec: format.video_codec,
+            audio_codec: format.audio_codec,
+            subtitle_codec: format.subtitle_codec,
+        }
+    })
+}
+
+/// Every protocol, with input and output support merged into one entry.
+pub fn protocols() -> Vec<ProtocolInfo> {
+    let mut output: Vec<ProtocolInfo> = Vec::new();
+    for &is_output in &[false, true] {
+        let mut opaque: *mut c_void = std::ptr::null_mut();
+        loop {
+            let name = unsafe { sys::avio_enum_protocols(&mut opaque, is_output as c_int) };
+            let name = match unsafe { from_c_str(name) } {
+                Some(x) => x,
+                None => break,
+            };
+            match output.iter_mut().find(|x| x.name == name) {
+                Some(x) if is_output => x.output = true,
+                Some(x) => x.input = true,
+                None => output.push(ProtocolInfo {name, input: !is_output, output: is_output}),
+            }
+        }
+    }
+    output
+}
+
+pub fn filters() -> impl Iterator<Item = FilterInfo> {
+    Registry::new(sys::av_filter_iterate).map(|x| unsafe {
+        let filter = &*x;
+        FilterInfo {
+            name: from_c_str(filter.name).unwrap_or_default(),
+            description: from_c_str(filter.description),
+            flags: filter.flags,
+            inputs: filter_pads(filter.inputs),
+            outputs: filter_pads(filter.outputs),
+        }
+    })
+}
+
+pub fn bitstream_filters() -> impl Iterator<Item = BitstreamFilterInfo> {
+    Registry::new(sys::av_bsf_iterate).map(|x| unsafe {
+        let filter = &*x;
+        BitstreamFilterInfo {
+            name: from_c_str(filter.name).unwrap_or_default(),
+            codec_ids: terminated(filter.codec_ids, sys::AVCodecID_AV_CODEC_ID_NONE),
+        }
+    })
+}
@@ -0,0 +1,232 @@
+//! Owned `AVCodecContext` and `AVCodecParameters`.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::Frame;
+use crate::extra::packet::Packet;
+use crate::extra::util::{c_str, to_av_dict};
+
+pub struct CodecContext {
+    ptr: *mut sys::AVCodecContext,
+}
+
+impl CodecContext {
+    unsafe fn alloc(codec: *const sys::AVCodec, what: String) -> Result<Self> {
+        if codec.is_null() {
+            return Err(Error::NotFound(what));
+        }
+        let ptr = sys::avcodec_alloc_context3(codec);
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVCodecContext"));
+        }
+        Ok(CodecContext {ptr})
+    }
+    /// A context for `id` without a codec behind it, e.g. for a parser to
+    /// fill in.
+    pub fn new_without_codec(id: sys::AVCodecID) -> Result<Self> {
+        let ptr = unsafe { sys::avcodec_alloc_context3(std::ptr::null()) };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVCodecContext"));
+        }
+        unsafe {
+            (*ptr).codec_id = id;
+            (*ptr).codec_type = sys::avcodec_get_type(id);
+        }
+        Ok(CodecContext {ptr})
+    }
+    pub fn new_decoder(id: sys::AVCodecID) -> Result<Self> {
+        unsafe {
+            let codec = sys::avcodec_find_decoder(id);
+            CodecContext::alloc(codec, format!("decoder for codec id {}", id))
+        }
+    }
+    pub fn new_decoder_by_name(name: &str) -> Result<Self> {
+        let name_cstr = c_str(name)?;
+        unsafe {
+            let codec = sys::avcodec_find_decoder_by_name(name_cstr.as_ptr());
+            CodecContext::alloc(codec, format!("decoder {:?}", name))
+        }
+    }
+    pub fn new_encoder(id: sys::AVCodecID) -> Result<Self> {
+        unsafe {
+            let codec = sys::avcodec_find_encoder(id);
+            CodecContext::alloc(codec, format!("encoder for codec id {}", id))
+        }
+    }
+    pub fn new_encoder_by_name(name: &str) -> Result<Self> {
+        let name_cstr = c_str(name)?;
+        unsafe {
+            let codec = sys::avcodec_find_encoder_by_name(name_cstr.as_ptr());
+            CodecContext::alloc(codec, format!("encoder {:?}", name))
+        }
+    }
+    /// Calls `avcodec_open2`. Options FFmpeg didn't recognise are ignored.
+    pub fn open(&mut self, options: &[(&str, &str)]) -> Result<()> {
+        unsafe {
+            let mut opts = to_av_dict(options)?;
+            let status = sys::avcodec_open2(self.ptr, (*self.ptr).codec, &mut opts);
+            sys::av_dict_free(&mut opts);
+            check(status)?;
+        }
+        Ok(())
+    }
+    pub fn is_open(&self) -> bool {
+        unsafe { sys::avcodec_is_open(self.ptr) > 0 }
+    }
+    /// Submits a packet to a decoder. Fails with `is_again()` when frames
+    /// have to be received first.
+    pub fn send_packet(&mut self, pkt: &Packet) -> Result<()> {
+        unsafe {
+            check(sys::avcodec_send_packet(self.ptr, pkt.as_ptr()))?;
+        }
+        Ok(())
+    }
+    /// Puts the decoder or encoder into draining mode, so the buffered
+    /// frames or packets come out.
+    pub fn send_eof(&mut self) -> Result<()> {
+        unsafe {
+            if sys::av_codec_is_encoder((*self.ptr).codec) != 0 {
+                check(sys::avcodec_send_frame(self.ptr, std::ptr::null()))?;
+            } else {
+                check(sys::avcodec_send_packet(self.ptr, std::ptr::null()))?;
+            }
+        }
+        Ok(())
+    }
+    /// Drops everything buffered and leaves draining mode, e.g. after a
+    /// seek.
+    pub fn flush(&mut self) {
+        unsafe {
+            sys::avcodec_flush_buffers(self.ptr);
+        }
+    }
+    /// `false` when the decoder needs more input or is drained.
+    pub fn receive_frame(&mut self, frame: &mut Frame) -> Result<bool> {
+        match check(unsafe { sys::avcodec_receive_frame(self.ptr, frame.as_mut_ptr()) }) {
+            Ok(_) => Ok(true),
+            Err(ref err) if err.is_again() || err.is_eof() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+    /// Submits a frame to an encoder. Fails with `is_again()` when packets
+    /// have to be received first.
+    pub fn send_frame(&mut self, frame: &Frame) -> Result<()> {
+        unsafe {
+            check(sys::avcodec_send_frame(self.ptr, frame.as_ptr()))?;
+        }
+        Ok(())
+    }
+    /// `false` when the encoder needs more input or is drained.
+    pub fn receive_packet(&mut self, pkt: &mut Packet) -> Result<bool> {
+        match check(unsafe { sys::avcodec_receive_packet(self.ptr, pkt.as_mut_ptr()) }) {
+            Ok(_) => Ok(true),
+            Err(ref err) if err.is_again() || err.is_eof() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+    pub fn as_ptr(&self) -> *const sys::AVCodecContext {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVCodecContext {
+        self.ptr
+    }
+}
+
+impl Drop for CodecContext {
+    fn drop(&mut self) {
+        unsafe {
+            sys::avcodec_free_context(&mut self.ptr);
+        };
+    }
+}
+
+pub struct CodecParameters {
+    ptr: *mut sys::AVCodecParameters,
+}
+
+impl CodecParameters {
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { sys::avcodec_parameters_alloc() };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVCodecParameters"));
+        }
+        Ok(CodecParameters {ptr})
+    }
+    /// Deep copy of e.g. a stream's `codecpar`.
+    pub unsafe fn copy_from(src: *const sys::AVCodecParameters) -> Result<Self> {
+        let par = CodecParameters::new()?;
+        check(sys::avcodec_parameters_copy(par.ptr, src))?;
+        Ok(par)
+    }
+    pub fn from_context(ctx: &CodecContext) -> Result<Self> {
+        let par = CodecParameters::new()?;
+        unsafe {
+            check(sys::avcodec_parameters_from_context(par.ptr, ctx.as_ptr()))?;
+        }
+        Ok(par)
+    }
+    /// Fills in a (not yet opened) codec context, e.g. a decoder for the stream.
+    pub fn to_context(&self, ctx: &mut CodecContext) -> Result<()> {
+        unsafe {
+            check(sys::avcodec_parameters_to_context(ctx.as_mut_ptr(), self.ptr))?;
+        }
+        Ok(())
+    }
+    pub fn codec_type(&self) -> sys::AVMediaType {
+        unsafe { (*self.ptr).codec_type }
+    }
+    pub fn codec_id(&self) -> sys::AVCodecID {
+        unsafe { (*self.ptr).codec_id }
+    }
+    pub fn codec_tag(&self) -> u32 {
+        unsafe { (*self.ptr).codec_tag }
+    }
+    pub fn width(&self) -> i32 {
+        unsafe { (*self.ptr).width }
+    }
+    pub fn height(&self) -> i32 {
+        unsafe { (*self.ptr).height }
+    }
+    /// `AVPixelFormat` for video, `AVSampleFormat` for audio.
+    pub fn format(&self) -> c_int {
+        unsafe { (*self.ptr).format }
+    }
+    pub fn sample_rate(&self) -> i32 {
+        unsafe { (*self.ptr).sample_rate }
+    }
+    pub fn channels(&self) -> i32 {
+        unsafe { (*self.ptr).channels }
+    }
+    pub fn channel_layout(&self) -> u64 {
+        unsafe { (*self.ptr).channel_layout }
+    }
+    pub fn extradata(&self) -> &[u8] {
+        unsafe {
+            let par = &*self.ptr;
+            if par.extradata.is_null() || par.extradata_size <= 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(par.extradata, par.extradata_size as usize)
+            }
+        }
+    }
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe { CodecParameters::copy_from(self.ptr) }
+    }
+    pub fn as_ptr(&self) -> *const sys::AVCodecParameters {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVCodecParameters {
+        self.ptr
+    }
+}
+
+impl Drop for CodecParameters {
+    fn drop(&mut self) {
+        unsafe {
+            sys::avcodec_parameters_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for CodecParameters {}
@@ -0,0 +1,152 @@
+//! Decoding a single stream of an `InputContext`.
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::codec::CodecContext;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result};
+use crate::extra::format::{InputContext, SeekMode, Timestamp};
+use crate::extra::frame::Frame;
+use crate::extra::packet::Packet;
+use crate::extra::resample::{AudioFormat, normalize_channel_layout};
+
+/// Reads packets of one stream and hands out its decoded frames.
+pub struct StreamDecoder {
+    input: InputContext,
+    index: usize,
+    decoder: CodecContext,
+    pkt: Packet,
+    /// The input is exhausted and the decoder is draining.
+    draining: bool,
+    /// After an exact seek, frames ending before this pts are dropped.
+    skip_until: Option<i64>,
+}
+
+impl StreamDecoder {
+    /// Decodes stream `index`; `options` are decoder options, e.g.
+    /// `threads`.
+    pub fn new(input: InputContext, index: usize, options: &[(&str, &str)]) -> Result<Self> {
+        if index >= input.nb_streams() {
+            return Err(Error::Invalid(format!("no input stream {}", index)));
+        }
+        let par = input.codec_parameters(index)?;
+        let mut decoder = CodecContext::new_decoder(par.codec_id())?;
+        par.to_context(&mut decoder)?;
+        unsafe {
+            (*decoder.as_mut_ptr()).pkt_timebase = input.stream_time_base(index);
+        }
+        decoder.open(options)?;
+        Ok(StreamDecoder {
+            input,
+            index,
+            decoder,
+            pkt: Packet::new()?,
+            draining: false,
+            skip_until: None,
+        })
+    }
+    /// Decodes what `av_find_best_stream` considers the main stream of
+    /// `media_type`.
+    pub fn best(mut input: InputContext, media_type: MediaType, options: &[(&str, &str)]) -> Result<Self> {
+        let index = unsafe {
+            sys::av_find_best_stream(
+                input.as_mut_ptr(),
+                media_type.to_raw(),
+                -1,
+                -1,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if index < 0 {
+            return Err(Error::NotFound(format!("{} stream", media_type.name())));
+        }
+        StreamDecoder::new(input, index as usize, options)
+    }
+    pub fn stream_index(&self) -> usize {
+        self.index
+    }
+    pub fn time_base(&self) -> sys::AVRational {
+        self.input.stream_time_base(self.index)
+    }
+    pub fn input(&self) -> &InputContext {
+        &self.input
+    }
+    pub fn decoder(&self) -> &CodecContext {
+        &self.decoder
+    }
+    pub fn decoder_mut(&mut self) -> &mut CodecContext {
+        &mut self.decoder
+    }
+    /// The format the decoder outputs, as far as known before the first
+    /// frame; only meaningful for audio.
+    pub fn audio_format(&self) -> AudioFormat {
+        let raw = unsafe { &*self.decoder.as_ptr() };
+        AudioFormat {
+            sample_fmt: raw.sample_fmt,
+            sample_rate: raw.sample_rate,
+            channel_layout: normalize_channel_layout(raw.channel_layout, raw.channels),
+        }
+    }
+    pub fn into_input(self) -> InputContext {
+        self.input
+    }
+    /// The next frame, with `pts` set to its best effort timestamp (in the
+    /// stream's time base); `None` at end of stream.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let mut frame = Frame::new()?;
+        loop {
+            if self.decoder.receive_frame(&mut frame)? {
+                let pts = frame.best_effort_timestamp();
+                frame.set_pts(pts);
+                if self.is_before_target(&frame) {
+                    continue;
+                }
+                self.skip_until = None;
+                return Ok(Some(frame));
+            }
+            if self.draining {
+                return Ok(None);
+            }
+            self.feed()?;
+        }
+    }
+    /// Sends the next packet of this stream, or EOF.
+    fn feed(&mut self) -> Result<()> {
+        while self.input.read_packet(&mut self.pkt)? {
+            if self.pkt.stream_index() == self.index {
+                return self.decoder.send_packet(&self.pkt);
+            }
+        }
+        self.draining = true;
+        self.decoder.send_eof()
+    }
+    fn is_before_target(&self, frame: &Frame) -> bool {
+        let target = match self.skip_until {
+            Some(target) => target,
+            None => return false,
+        };
+        if frame.pts() == unsafe { defs::av_nopts_value() } {
+            return false;
+        }
+        // THE FRAME SHOWN AT `target` IS THE ONE WHOSE DURATION COVERS IT
+        let duration = unsafe { (*frame.as_ptr()).pkt_duration };
+        frame.pts() + duration.max(1) <= target
+    }
+    /// Seeks the input and flushes the decoder. With `SeekMode::Exact` the
+    /// next frame returned is the one displayed at `ts`.
+    pub fn seek(&mut self, ts: Timestamp, mode: SeekMode) -> Result<()> {
+        let target = self.input.stream_timestamp(ts, self.index)?;
+        let demuxer_mode = match mode {
+            SeekMode::Exact => SeekMode::KeyframeBefore,
+            mode => mode,
+        };
+        self.input.seek(Timestamp::Stream {index: self.index, ts: target}, demuxer_mode)?;
+        self.decoder.flush();
+        self.draining = false;
+        self.skip_until = match mode {
+            SeekMode::Exact => Some(target),
+            _ => None,
+        };
+        Ok(())
+    }
+}
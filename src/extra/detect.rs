@@ -0,0 +1,441 @@
+//! Scene cuts, black and frozen segments and crop suggestions of a video
+//! stream, read from the frame metadata of `select`, `blackdetect` and
+//! `freezedetect`.
+//!
+//! `cropdetect` is GPL-only and `scdet` newer than the bundled build, so
+//! crops are measured here the way `cropdetect` does it, on sampled luma
+//! planes, and scene cuts come from `select`'s scene score.
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::decode::StreamDecoder;
+use crate::extra::defs;
+use crate::extra::error::Result;
+use crate::extra::filter::{FilterGraph, VideoInput};
+use crate::extra::format::InputContext;
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::image::Image;
+
+/// Options of the `blackdetect` filter.
+#[derive(Debug, Clone, Copy)]
+pub struct BlackOptions {
+    /// Shortest black segment reported, in seconds.
+    pub min_duration: f64,
+    /// Share of black pixels (0 to 1) for a picture to count as black.
+    pub picture_threshold: f64,
+    /// Luma (0 to 1) below which a pixel counts as black.
+    pub pixel_threshold: f64,
+}
+
+impl Default for BlackOptions {
+    fn default() -> Self {
+        BlackOptions {min_duration: 2.0, picture_threshold: 0.98, pixel_threshold: 0.1}
+    }
+}
+
+/// Options of the `freezedetect` filter.
+#[derive(Debug, Clone, Copy)]
+pub struct FreezeOptions {
+    /// Shortest freeze reported, in seconds.
+    pub min_duration: f64,
+    /// Noise tolerance in dB, e.g. -60.
+    pub noise: f64,
+}
+
+impl Default for FreezeOptions {
+    fn default() -> Self {
+        FreezeOptions {min_duration: 2.0, noise: -60.0}
+    }
+}
+
+/// Options of the crop measurement, named like `cropdetect`'s.
+#[derive(Debug, Clone, Copy)]
+pub struct CropOptions {
+    /// Luma (0 to 255) a row or column must average above to be kept.
+    pub limit: u8,
+    /// The crop size is rounded down to a multiple of this.
+    pub round: i32,
+    /// Seconds between measured frames.
+    pub interval: f64,
+}
+
+impl Default for CropOptions {
+    fn default() -> Self {
+        CropOptions {limit: 24, round: 16, interval: 1.0}
+    }
+}
+
+/// Which detectors `analyze_video` runs; `None` disables one.
+#[derive(Debug, Clone)]
+pub struct DetectOptions {
+    /// Scene score (0 to 1) above which a frame counts as a cut, e.g. 0.4.
+    pub scene_threshold: Option<f64>,
+    pub black: Option<BlackOptions>,
+    pub freeze: Option<FreezeOptions>,
+    pub crop: Option<CropOptions>,
+    /// Decoder options, e.g. `threads`.
+    pub decoder_options: Vec<(String, String)>,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        DetectOptions {
+            scene_threshold: Some(0.4),
+            black: Some(BlackOptions::default()),
+            freeze: Some(FreezeOptions::default()),
+            crop: Some(CropOptions::default()),
+            decoder_options: Vec::new(),
+        }
+    }
+}
+
+/// Times are from the start of the input, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneChange {
+    pub time: f64,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Segment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// The picture area without black borders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl CropRect {
+    /// The `crop` filter that applies it.
+    pub fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoAnalysis {
+    pub scene_changes: Vec<SceneChange>,
+    pub black_segments: Vec<Segment>,
+    pub freeze_segments: Vec<Segment>,
+    /// `None` when cropping is disabled or every measured frame was black.
+    pub crop: Option<CropRect>,
+    /// Of the analysed video, in seconds.
+    pub duration: f64,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Set by `select` when its expression uses `scene`.
+fn scene_score(frame: &Frame) -> Option<f64> {
+    frame.metadata()
+        .into_iter()
+        .find(|(key, _)| key == "lavfi.scene_score")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// A `start`/`end` metadata pair being matched up.
+#[derive(Default)]
+struct Segments {
+    start: Option<f64>,
+    segments: Vec<Segment>,
+}
+
+impl Segments {
+    fn set_start(&mut self, value: &str, start_time: f64) {
+        if let Ok(start) = value.parse::<f64>() {
+            self.start = Some(start - start_time);
+        }
+    }
+    fn set_end(&mut self, value: &str, start_time: f64) {
+        if let (Some(start), Ok(end)) = (self.start.take(), value.parse::<f64>()) {
+            self.segments.push(Segment {start, end: end - start_time});
+        }
+    }
+    /// Closes a segment running until the end, which is only logged.
+    fn finish(mut self, duration: f64, min_duration: f64) -> Vec<Segment> {
+        if let Some(start) = self.start.take() {
+            self.segments.push(Segment {start, end: duration});
+        }
+        self.segments.retain(|x| x.duration() >= min_duration);
+        self.segments
+    }
+}
+
+/// Union of the non-black areas seen so far, as inclusive bounds.
+struct CropBounds {
+    limit: u8,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+}
+
+impl CropBounds {
+    fn new(limit: u8) -> Self {
+        CropBounds {limit, x1: i32::MAX, y1: i32::MAX, x2: -1, y2: -1}
+    }
+    fn add(&mut self, luma: &Image) {
+        let (width, height) = (luma.width() as usize, luma.height() as usize);
+        let (plane, linesize) = (luma.plane(0), luma.linesize(0) as usize);
+        let limit = self.limit as u64;
+        let pixel = |x: usize, y: usize| plane[y * linesize + x] as u64;
+        let row = |y: usize| (0..width).map(|x| pixel(x, y)).sum::<u64>() > limit * width as u64;
+        let column = |x: usize| (0..height).map(|y| pixel(x, y)).sum::<u64>() > limit * height as u64;
+        let top = match (0..height).find(|&y| row(y)) {
+            Some(y) => y,
+            // ALL BLACK, E.G. A FADE
+            None => return,
+        };
+        let bottom = (0..height).rev().find(|&y| row(y)).unwrap_or(top);
+        let left = (0..width).find(|&x| column(x)).unwrap_or(0);
+        let right = (0..width).rev().find(|&x| column(x)).unwrap_or(width - 1);
+        self.x1 = self.x1.min(left as i32);
+        self.y1 = self.y1.min(top as i32);
+        self.x2 = self.x2.max(right as i32);
+        self.y2 = self.y2.max(bottom as i32);
+    }
+    /// Rounded and centered like `cropdetect`'s suggestion.
+    fn rect(&self, round: i32) -> Option<CropRect> {
+        if self.x2 < self.x1 || self.y2 < self.y1 {
+            return None;
+        }
+        let round = match round {
+            x if x <= 1 => 16,
+            x if x % 2 != 0 => x * 2,
+            x => x,
+        };
+        let (width, height) = (self.x2 - self.x1 + 1, self.y2 - self.y1 + 1);
+        let (shrink_x, shrink_y) = (width % round, height % round);
+        Some(CropRect {
+            x: (self.x1 + shrink_x / 2 + 1) & !1,
+            y: (self.y1 + shrink_y / 2 + 1) & !1,
+            width: width - shrink_x,
+            height: height - shrink_y,
+        })
+        .filter(|x| x.width > 0 && x.height > 0)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// ANALYSIS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Builds `[in]split=N[s0][s1]...;[s0]chain0[sink0];...` from the given
+/// chains and sink names.
+fn graph_description(branches: &[(String, &str)]) -> String {
+    if branches.len() == 1 {
+        let (chain, sink) = &branches[0];
+        return format!("[in]{}[{}]", chain, sink);
+    }
+    let mut description = format!("[in]split={}", branches.len());
+    for index in 0..branches.len() {
+        description.push_str(&format!("[s{}]", index));
+    }
+    for (index, (chain, sink)) in branches.iter().enumerate() {
+        description.push_str(&format!(";[s{}]{}[{}]", index, chain, sink));
+    }
+    description
+}
+
+fn seconds(ts: i64, time_base: sys::AVRational) -> f64 {
+    ts as f64 * time_base.num as f64 / time_base.den as f64
+}
+
+/// Decodes the main video stream of `input` once and runs the detectors
+/// enabled in `options` over it.
+pub fn analyze_video(input: InputContext, options: &DetectOptions) -> Result<VideoAnalysis> {
+    let decoder_options = options.decoder_options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    let mut decoder = StreamDecoder::best(input, MediaType::Video, &decoder_options)?;
+    let start_time = decoder.input().start_time() as f64 / sys::AV_TIME_BASE as f64;
+    let input = unsafe {
+        let raw = &*decoder.decoder().as_ptr();
+        let stream = &*decoder.input().stream_ptr(decoder.stream_index());
+        VideoInput {
+            format: PictureFormat {width: raw.width, height: raw.height, pix_fmt: raw.pix_fmt},
+            time_base: decoder.time_base(),
+            sample_aspect_ratio: raw.sample_aspect_ratio,
+            frame_rate: Some(stream.avg_frame_rate).filter(|x| x.num > 0 && x.den > 0),
+        }
+    };
+
+    let mut passthrough = Vec::new();
+    if let Some(black) = options.black {
+        // SEGMENTS ARE FILTERED BY DURATION HERE, THE METADATA IGNORES `d`
+        passthrough.push(format!(
+            "blackdetect=d=0:pic_th={}:pix_th={}",
+            black.picture_threshold,
+            black.pixel_threshold,
+        ));
+    }
+    if let Some(freeze) = options.freeze {
+        passthrough.push(format!("freezedetect=n={}dB:d={}", freeze.noise, freeze.min_duration));
+    }
+    let mut branches = Vec::new();
+    if !passthrough.is_empty() {
+        branches.push((passthrough.join(","), "out"));
+    }
+    if let Some(threshold) = options.scene_threshold {
+        branches.push((format!("select='gt(scene\\,{})'", threshold), "scenes"));
+    }
+    if let Some(crop) = options.crop {
+        branches.push((
+            format!("select='isnan(prev_selected_t)+gte(t-prev_selected_t\\,{})'", crop.interval),
+            "crop",
+        ));
+    }
+    if branches.is_empty() {
+        branches.push((String::from("null"), "out"));
+    }
+
+    let mut graph = FilterGraph::new()?;
+    graph.add_video_input("in", input)?;
+    for (_, sink) in &branches {
+        let pix_fmts: &[sys::AVPixelFormat] = match *sink {
+            "crop" => &[sys::AVPixelFormat_AV_PIX_FMT_GRAY8],
+            _ => &[],
+        };
+        graph.add_video_output(sink, pix_fmts)?;
+    }
+    graph.parse(&graph_description(&branches))?;
+    graph.configure()?;
+
+    let mut black = Segments::default();
+    let mut freeze = Segments::default();
+    let mut scene_changes = Vec::new();
+    let mut crop = options.crop.map(|x| CropBounds::new(x.limit));
+    let mut end: Option<f64> = None;
+    let mut eof = false;
+    loop {
+        match decoder.next_frame()? {
+            Some(frame) => {
+                if frame.pts() != unsafe { defs::av_nopts_value() } {
+                    let duration = unsafe { (*frame.as_ptr()).pkt_duration };
+                    let frame_end = seconds(frame.pts() + duration.max(0), decoder.time_base());
+                    end = Some(end.map_or(frame_end, |x: f64| x.max(frame_end)));
+                }
+                graph.push("in", &frame)?;
+            }
+            None => {
+                graph.push_eof("in")?;
+                eof = true;
+            }
+        }
+        for (_, sink) in &branches {
+            while let Some(frame) = graph.pull(sink)? {
+                match *sink {
+                    "scenes" => {
+                        let time = seconds(frame.pts(), graph.output_time_base(sink)?) - start_time;
+                        if let Some(score) = scene_score(&frame) {
+                            scene_changes.push(SceneChange {time, score});
+                        }
+                    }
+                    "crop" => {
+                        if let Some(crop) = crop.as_mut() {
+                            crop.add(&Image::from_frame(&frame)?);
+                        }
+                    }
+                    _ => {
+                        for (key, value) in frame.metadata() {
+                            match key.as_str() {
+                                "lavfi.black_start" => black.set_start(&value, start_time),
+                                "lavfi.black_end" => black.set_end(&value, start_time),
+                                "lavfi.freezedetect.freeze_start" => freeze.set_start(&value, start_time),
+                                "lavfi.freezedetect.freeze_end" => freeze.set_end(&value, start_time),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if eof {
+            break;
+        }
+    }
+
+    let duration = end.map(|x| x - start_time).unwrap_or(0.0).max(0.0);
+    Ok(VideoAnalysis {
+        scene_changes,
+        black_segments: black.finish(duration, options.black.map(|x| x.min_duration).unwrap_or(0.0)),
+        freeze_segments: freeze.finish(duration, 0.0),
+        crop: match (crop, options.crop) {
+            (Some(bounds), Some(crop)) => bounds.rect(crop.round),
+            _ => None,
+        },
+        duration,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A black gray8 picture with a bright `width`x`height` box at `x`, `y`.
+    fn boxed(x: usize, y: usize, width: usize, height: usize) -> Image {
+        let format = PictureFormat {width: 64, height: 48, pix_fmt: sys::AVPixelFormat_AV_PIX_FMT_GRAY8};
+        let mut image = Image::new(format).unwrap();
+        let linesize = image.linesize(0) as usize;
+        let plane = image.plane_mut(0);
+        plane.iter_mut().for_each(|x| *x = 0);
+        for row in plane.chunks_mut(linesize).skip(y).take(height) {
+            row[x..x + width].iter_mut().for_each(|pixel| *pixel = 200);
+        }
+        image
+    }
+
+    fn bounds(x1: i32, y1: i32, x2: i32, y2: i32) -> CropBounds {
+        CropBounds {limit: 24, x1, y1, x2, y2}
+    }
+
+    #[test]
+    fn crop_of_one_frame() {
+        let mut crop = CropBounds::new(24);
+        crop.add(&boxed(8, 6, 48, 36));
+        let rect = crop.rect(16).unwrap();
+        assert_eq!(rect, CropRect {x: 8, y: 8, width: 48, height: 32});
+        assert_eq!(rect.filter(), "crop=48:32:8:8");
+    }
+
+    #[test]
+    fn crop_is_the_union_of_frames() {
+        let mut crop = CropBounds::new(24);
+        crop.add(&boxed(8, 6, 48, 36));
+        crop.add(&boxed(4, 10, 36, 20));
+        assert_eq!(crop.rect(16), Some(CropRect {x: 6, y: 8, width: 48, height: 32}));
+    }
+
+    #[test]
+    fn black_frames_are_ignored() {
+        let mut crop = CropBounds::new(24);
+        crop.add(&boxed(0, 0, 0, 0));
+        assert_eq!(crop.rect(16), None);
+        crop.add(&boxed(0, 0, 64, 48));
+        assert_eq!(crop.rect(16), Some(CropRect {x: 0, y: 0, width: 64, height: 48}));
+    }
+
+    #[test]
+    fn crop_rounding() {
+        assert_eq!(bounds(0, 0, 99, 99).rect(2), Some(CropRect {x: 0, y: 0, width: 100, height: 100}));
+        // ODD VALUES DOUBLE, 0 AND 1 MEAN 16 LIKE cropdetect
+        assert_eq!(bounds(0, 0, 99, 99).rect(3), Some(CropRect {x: 2, y: 2, width: 96, height: 96}));
+        assert_eq!(bounds(0, 0, 99, 99).rect(0), bounds(0, 0, 99, 99).rect(16));
+        assert_eq!(bounds(0, 0, 99, 99).rect(1), Some(CropRect {x: 2, y: 2, width: 96, height: 96}));
+        assert_eq!(bounds(0, 0, 3, 99).rect(16), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Error type shared by the safe wrappers in `ffmpeg_dev::extra`.
+use std::ffi::{CStr, NulError};
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use crate::sys;
+use crate::extra::defs;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A negative `AVERROR` code returned by FFmpeg.
+    Ffmpeg(c_int),
+    /// An FFmpeg allocator returned null.
+    Alloc(&'static str),
+    /// A codec, format, filter, option etc. that isn't part of this build.
+    NotFound(String),
+    /// Rejected before it reached FFmpeg.
+    Invalid(String),
+    Io(std::io::Error),
+    Nul(NulError),
+}
+
+impl Error {
+    pub fn code(&self) -> Option<c_int> {
+        match self {
+            Error::Ffmpeg(code) => Some(*code),
+            _ => None,
+        }
+    }
+    pub fn is_eof(&self) -> bool {
+        self.code() == Some(unsafe { defs::averror_eof() })
+    }
+    /// `AVERROR(EAGAIN)`, i.e. more input is needed or output must be drained first.
+    pub fn is_again(&self) -> bool {
+        self.code() == Some(unsafe { defs::averror(defs::eagain()) })
+    }
+    /// `AVERROR_INVALIDDATA`, e.g. a corrupt packet.
+    pub fn is_invalid_data(&self) -> bool {
+        self.code() == Some(unsafe { defs::averror_invaliddata() })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Ffmpeg(code) => {
+                let size = unsafe { defs::av_error_max_string_size() } as usize;
+                let mut buf = vec![0 as c_char; size];
+                let status = unsafe {
+                    sys::av_strerror(*code, buf.as_mut_ptr(), size as _)
+                };
+                if status < 0 {
+                    return write!(f, "ffmpeg error {}", code);
+                }
+                let msg = unsafe { CStr::from_ptr(buf.as_ptr()) };
+                write!(f, "{} ({})", msg.to_string_lossy(), code)
+            }
+            Error::Alloc(what) => write!(f, "failed to allocate {}", what),
+            Error::NotFound(what) => write!(f, "not found: {}", what),
+            Error::Invalid(msg) => write!(f, "invalid: {}", msg),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Nul(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(err: NulError) -> Self {
+        Error::Nul(err)
+    }
+}
+
+/// Turns a negative FFmpeg return code into an `Err`.
+pub(crate) fn check(ret: c_int) -> Result<c_int> {
+    if ret < 0 {
+        Err(Error::Ffmpeg(ret))
+    } else {
+        Ok(ret)
+    }
+}
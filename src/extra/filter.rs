@@ -0,0 +1,409 @@
+//! libavfilter graphs, e.g. `scale=640:-2,fade=in:0:25` or
+//! `[a]ebur128=metadata=1[out]`.
+//!
+//! Frames go in through named `buffer`/`abuffer` sources and come out of
+//! named `buffersink`/`abuffersink` sinks. Their names are the labels used
+//! in the graph description; with a single input and output, unlabeled
+//! descriptions work too.
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_void};
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::option::Options;
+use crate::extra::resample::AudioFormat;
+use crate::extra::util::{c_str, from_c_str};
+
+#[derive(Debug, Clone, Copy)]
+pub struct VideoInput {
+    pub format: PictureFormat,
+    pub time_base: sys::AVRational,
+    pub sample_aspect_ratio: sys::AVRational,
+    pub frame_rate: Option<sys::AVRational>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioInput {
+    pub format: AudioFormat,
+    pub time_base: sys::AVRational,
+}
+
+/// Formats an audio sink may output; empty means anything.
+#[derive(Debug, Clone, Default)]
+pub struct AudioOutput {
+    pub sample_fmts: Vec<sys::AVSampleFormat>,
+    pub sample_rates: Vec<i32>,
+    pub channel_layouts: Vec<u64>,
+}
+
+struct Sink {
+    name: String,
+    ptr: *mut sys::AVFilterContext,
+    eof: bool,
+}
+
+pub struct FilterGraph {
+    ptr: *mut sys::AVFilterGraph,
+    sources: Vec<(String, *mut sys::AVFilterContext)>,
+    sinks: Vec<Sink>,
+}
+
+/// A filter instance inside a graph, mainly for its `Options`.
+pub struct FilterContext<'a> {
+    ptr: *mut sys::AVFilterContext,
+    marker: PhantomData<&'a mut FilterGraph>,
+}
+
+impl<'a> FilterContext<'a> {
+    pub fn name(&self) -> String {
+        unsafe { from_c_str((*self.ptr).name).unwrap_or_default() }
+    }
+    /// The filter's type, e.g. `scale`.
+    pub fn filter_name(&self) -> String {
+        unsafe { from_c_str((*(*self.ptr).filter).name).unwrap_or_default() }
+    }
+    pub fn as_ptr(&self) -> *const sys::AVFilterContext {
+        self.ptr
+    }
+}
+
+unsafe impl<'a> Options for FilterContext<'a> {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+}
+
+/// Writes a list option (e.g. `pix_fmts`) the way `av_opt_set_int_list` does.
+unsafe fn set_list_option<T>(ctx: *mut sys::AVFilterContext, name: &str, values: &[T]) -> Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let name = c_str(name)?;
+    check(sys::av_opt_set_bin(
+        ctx as *mut c_void,
+        name.as_ptr(),
+        values.as_ptr() as *const u8,
+        (values.len() * std::mem::size_of::<T>()) as c_int,
+        sys::AV_OPT_SEARCH_CHILDREN as c_int,
+    ))?;
+    Ok(())
+}
+
+/// Prepends an entry to `next`; on error `next` is left to the caller.
+unsafe fn new_inout(name: &str, ctx: *mut sys::AVFilterContext, next: *mut sys::AVFilterInOut) -> Result<*mut sys::AVFilterInOut> {
+    let name = c_str(name)?;
+    let mut inout = sys::avfilter_inout_alloc();
+    if inout.is_null() {
+        return Err(Error::Alloc("AVFilterInOut"));
+    }
+    (*inout).name = sys::av_strdup(name.as_ptr());
+    if (*inout).name.is_null() {
+        sys::avfilter_inout_free(&mut inout);
+        return Err(Error::Alloc("AVFilterInOut name"));
+    }
+    (*inout).filter_ctx = ctx;
+    (*inout).pad_idx = 0;
+    (*inout).next = next;
+    Ok(inout)
+}
+
+impl FilterGraph {
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { sys::avfilter_graph_alloc() };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVFilterGraph"));
+        }
+        Ok(FilterGraph {ptr, sources: Vec::new(), sinks: Vec::new()})
+    }
+    /// Parses, links and configures `description` in one go, for graphs
+    /// with one source named `in` and one sink named `out`.
+    pub fn new_video(description: &str, input: VideoInput, pix_fmts: &[sys::AVPixelFormat]) -> Result<Self> {
+        let mut graph = FilterGraph::new()?;
+        graph.add_video_input("in", input)?;
+        graph.add_video_output("out", pix_fmts)?;
+        graph.parse(description)?;
+        graph.configure()?;
+        Ok(graph)
+    }
+    /// Audio counterpart of `new_video`.
+    pub fn new_audio(description: &str, input: AudioInput, output: AudioOutput) -> Result<Self> {
+        let mut graph = FilterGraph::new()?;
+        graph.add_audio_input("in", input)?;
+        graph.add_audio_output("out", output)?;
+        graph.parse(description)?;
+        graph.configure()?;
+        Ok(graph)
+    }
+    unsafe fn create_filter(&mut self, filter: &str, name: &str, args: Option<String>) -> Result<*mut sys::AVFilterContext> {
+        let filter_ptr = sys::avfilter_get_by_name(c_str(filter)?.as_ptr());
+        if filter_ptr.is_null() {
+            return Err(Error::NotFound(format!("filter {:?}", filter)));
+        }
+        let name = c_str(name)?;
+        let args = match args {
+            Some(x) => Some(c_str(&x)?),
+            None => None,
+        };
+        let mut ctx: *mut sys::AVFilterContext = std::ptr::null_mut();
+        check(sys::avfilter_graph_create_filter(
+            &mut ctx,
+            filter_ptr,
+            name.as_ptr(),
+            args.as_ref().map(|x| x.as_ptr()).unwrap_or(std::ptr::null()),
+            std::ptr::null_mut(),
+            self.ptr,
+        ))?;
+        Ok(ctx)
+    }
+    pub fn add_video_input(&mut self, name: &str, input: VideoInput) -> Result<()> {
+        let mut args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+            input.format.width,
+            input.format.height,
+            input.format.pix_fmt,
+            input.time_base.num,
+            input.time_base.den,
+            input.sample_aspect_ratio.num,
+            input.sample_aspect_ratio.den.max(1),
+        );
+        if let Some(rate) = input.frame_rate {
+            args.push_str(&format!(":frame_rate={}/{}", rate.num, rate.den));
+        }
+        let ctx = unsafe { self.create_filter("buffer", name, Some(args))? };
+        self.sources.push((String::from(name), ctx));
+        Ok(())
+    }
+    pub fn add_audio_input(&mut self, name: &str, input: AudioInput) -> Result<()> {
+        let sample_fmt = unsafe { from_c_str(sys::av_get_sample_fmt_name(input.format.sample_fmt)) }
+            .ok_or_else(|| Error::Invalid(format!("sample format {}", input.format.sample_fmt)))?;
+        let args = format!(
+            "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            input.time_base.num,
+            input.time_base.den,
+            input.format.sample_rate,
+            sample_fmt,
+            input.format.channel_layout,
+        );
+        let ctx = unsafe { self.create_filter("abuffer", name, Some(args))? };
+        self.sources.push((String::from(name), ctx));
+        Ok(())
+    }
+    /// `pix_fmts` restricts the output pixel formats; empty means any.
+    pub fn add_video_output(&mut self, name: &str, pix_fmts: &[sys::AVPixelFormat]) -> Result<()> {
+        unsafe {
+            let ctx = self.create_filter("buffersink", name, None)?;
+            set_list_option(ctx, "pix_fmts", pix_fmts)?;
+            self.sinks.push(Sink {name: String::from(name), ptr: ctx, eof: false});
+        }
+        Ok(())
+    }
+    pub fn add_audio_output(&mut self, name: &str, output: AudioOutput) -> Result<()> {
+        unsafe {
+            let ctx = self.create_filter("abuffersink", name, None)?;
+            set_list_option(ctx, "sample_fmts", &output.sample_fmts)?;
+            set_list_option(ctx, "sample_rates", &output.sample_rates)?;
+            set_list_option(ctx, "channel_layouts", &output.channel_layouts)?;
+            self.sinks.push(Sink {name: String::from(name), ptr: ctx, eof: false});
+        }
+        Ok(())
+    }
+    /// Parses an ffmpeg-style filtergraph and links it to the inputs and
+    /// outputs added so far.
+    pub fn parse(&mut self, description: &str) -> Result<()> {
+        let description = c_str(description)?;
+        unsafe {
+            // FROM THE GRAPH'S POINT OF VIEW, OUR SOURCES ARE OPEN OUTPUTS
+            // AND OUR SINKS ARE OPEN INPUTS
+            let mut outputs: *mut sys::AVFilterInOut = std::ptr::null_mut();
+            let mut inputs: *mut sys::AVFilterInOut = std::ptr::null_mut();
+            let mut build = || -> Result<()> {
+                for (name, ctx) in self.sources.iter().rev() {
+                    outputs = new_inout(name, *ctx, outputs)?;
+                }
+                for sink in self.sinks.iter().rev() {
+                    inputs = new_inout(&sink.name, sink.ptr, inputs)?;
+                }
+                check(sys::avfilter_graph_parse_ptr(
+                    self.ptr,
+                    description.as_ptr(),
+                    &mut inputs,
+                    &mut outputs,
+                    std::ptr::null_mut(),
+                ))?;
+                Ok(())
+            };
+            let result = build();
+            sys::avfilter_inout_free(&mut inputs);
+            sys::avfilter_inout_free(&mut outputs);
+            result
+        }
+    }
+    /// Validates the graph and negotiates formats; call after `parse`.
+    pub fn configure(&mut self) -> Result<()> {
+        unsafe {
+            check(sys::avfilter_graph_config(self.ptr, std::ptr::null_mut()))?;
+        }
+        Ok(())
+    }
+    fn source(&self, name: &str) -> Result<*mut sys::AVFilterContext> {
+        self.sources
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, ctx)| *ctx)
+            .ok_or_else(|| Error::NotFound(format!("filter graph input {:?}", name)))
+    }
+    fn sink_index(&self, name: &str) -> Result<usize> {
+        self.sinks
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or_else(|| Error::NotFound(format!("filter graph output {:?}", name)))
+    }
+    /// Feeds a frame into the named input; the graph takes a new reference,
+    /// `frame` is left untouched.
+    pub fn push(&mut self, input: &str, frame: &Frame) -> Result<()> {
+        let ctx = self.source(input)?;
+        unsafe {
+            check(sys::av_buffersrc_add_frame_flags(
+                ctx,
+                frame.as_ptr() as *mut sys::AVFrame,
+                sys::AV_BUFFERSRC_FLAG_KEEP_REF as c_int,
+            ))?;
+        }
+        Ok(())
+    }
+    /// Signals end of stream on the named input.
+    pub fn push_eof(&mut self, input: &str) -> Result<()> {
+        let ctx = self.source(input)?;
+        unsafe {
+            check(sys::av_buffersrc_add_frame_flags(ctx, std::ptr::null_mut(), 0))?;
+        }
+        Ok(())
+    }
+    /// Takes the next filtered frame from the named output. `None` when
+    /// more input is needed or the output reached end of stream (see
+    /// `is_finished`).
+    pub fn pull(&mut self, output: &str) -> Result<Option<Frame>> {
+        let index = self.sink_index(output)?;
+        let mut frame = Frame::new()?;
+        let status = unsafe { sys::av_buffersink_get_frame(self.sinks[index].ptr, frame.as_mut_ptr()) };
+        match check(status) {
+            Ok(_) => Ok(Some(frame)),
+            Err(ref err) if err.is_again() => Ok(None),
+            Err(ref err) if err.is_eof() => {
+                self.sinks[index].eof = true;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+    /// Whether the named output returned end of stream.
+    pub fn is_finished(&self, output: &str) -> Result<bool> {
+        Ok(self.sinks[self.sink_index(output)?].eof)
+    }
+    /// Makes audio outputs return frames of exactly `nb_samples` (except
+    /// the last one), as required by encoders with a fixed frame size.
+    pub fn set_frame_size(&mut self, output: &str, nb_samples: u32) -> Result<()> {
+        let index = self.sink_index(output)?;
+        unsafe {
+            sys::av_buffersink_set_frame_size(self.sinks[index].ptr, nb_samples);
+        }
+        Ok(())
+    }
+    pub fn output_time_base(&self, output: &str) -> Result<sys::AVRational> {
+        let ctx = self.sinks[self.sink_index(output)?].ptr;
+        Ok(unsafe { sys::av_buffersink_get_time_base(ctx) })
+    }
+    pub fn output_frame_rate(&self, output: &str) -> Result<sys::AVRational> {
+        let ctx = self.sinks[self.sink_index(output)?].ptr;
+        Ok(unsafe { sys::av_buffersink_get_frame_rate(ctx) })
+    }
+    pub fn output_sample_aspect_ratio(&self, output: &str) -> Result<sys::AVRational> {
+        let ctx = self.sinks[self.sink_index(output)?].ptr;
+        Ok(unsafe { sys::av_buffersink_get_sample_aspect_ratio(ctx) })
+    }
+    pub fn output_picture_format(&self, output: &str) -> Result<PictureFormat> {
+        let ctx = self.sinks[self.sink_index(output)?].ptr;
+        unsafe {
+            Ok(PictureFormat {
+                width: sys::av_buffersink_get_w(ctx),
+                height: sys::av_buffersink_get_h(ctx),
+                pix_fmt: sys::av_buffersink_get_format(ctx) as sys::AVPixelFormat,
+            })
+        }
+    }
+    pub fn output_audio_format(&self, output: &str) -> Result<AudioFormat> {
+        let ctx = self.sinks[self.sink_index(output)?].ptr;
+        unsafe {
+            Ok(AudioFormat {
+                sample_fmt: sys::av_buffersink_get_format(ctx) as sys::AVSampleFormat,
+                sample_rate: sys::av_buffersink_get_sample_rate(ctx),
+                channel_layout: sys::av_buffersink_get_channel_layout(ctx),
+            })
+        }
+    }
+    /// Sends a runtime command (e.g. `volume` / `volume` / `0.5`) to the
+    /// filter instance(s) matching `target` (an instance name, a filter
+    /// name, or `all`), returning the filter's response.
+    pub fn send_command(&mut self, target: &str, command: &str, arg: &str) -> Result<String> {
+        let target = c_str(target)?;
+        let command = c_str(command)?;
+        let arg = c_str(arg)?;
+        let mut response = vec![0 as c_char; 4096];
+        unsafe {
+            check(sys::avfilter_graph_send_command(
+                self.ptr,
+                target.as_ptr(),
+                command.as_ptr(),
+                arg.as_ptr(),
+                response.as_mut_ptr(),
+                response.len() as c_int,
+                0,
+            ))?;
+            Ok(from_c_str(response.as_ptr()).unwrap_or_default())
+        }
+    }
+    /// Instance names of every filter in the graph, e.g. `Parsed_scale_0`.
+    pub fn filter_names(&self) -> Vec<String> {
+        unsafe {
+            let graph = &*self.ptr;
+            if graph.filters.is_null() {
+                return Vec::new();
+            }
+            std::slice::from_raw_parts(graph.filters, graph.nb_filters as usize)
+                .iter()
+                .filter_map(|x| from_c_str((**x).name))
+                .collect()
+        }
+    }
+    pub fn filter(&mut self, name: &str) -> Option<FilterContext> {
+        let name = c_str(name).ok()?;
+        let ptr = unsafe { sys::avfilter_graph_get_filter(self.ptr, name.as_ptr()) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(FilterContext {ptr, marker: PhantomData})
+        }
+    }
+    pub fn as_ptr(&self) -> *const sys::AVFilterGraph {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVFilterGraph {
+        self.ptr
+    }
+}
+
+unsafe impl Options for FilterGraph {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+}
+
+impl Drop for FilterGraph {
+    fn drop(&mut self) {
+        unsafe {
+            sys::avfilter_graph_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for FilterGraph {}
@@ -0,0 +1,412 @@
+//! Owned demuxer/muxer `AVFormatContext`s.
+use std::os::raw::{c_char, c_int, c_void};
+use crate::sys;
+use crate::extra::codec::CodecParameters;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::io::{IoContext, MemoryFile};
+use crate::extra::packet::Packet;
+use crate::extra::util::{c_str, from_c_str, to_av_dict};
+
+/// A position in an input, for `InputContext::seek`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timestamp {
+    /// Seconds from the start of the input (i.e. relative to its
+    /// `start_time`).
+    Seconds(f64),
+    /// A pts in the time base of stream `index`.
+    Stream {index: usize, ts: i64},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    /// The last keyframe at or before the target.
+    KeyframeBefore,
+    /// Whichever keyframe is closest to the target, going by the stream's
+    /// index. Without an index this is `KeyframeBefore`.
+    KeyframeNearest,
+    /// The target frame itself. Needs decoding, so only `StreamDecoder::seek`
+    /// supports it: it lands on the keyframe before the target and drops the
+    /// frames in between.
+    Exact,
+}
+
+pub(crate) const AV_TIME_BASE_Q: sys::AVRational = sys::AVRational {num: 1, den: sys::AV_TIME_BASE as i32};
+
+pub struct InputContext {
+    ptr: *mut sys::AVFormatContext,
+    // DROPPED AFTER `ptr` IS CLOSED
+    io: Option<IoContext>,
+}
+
+impl InputContext {
+    /// Opens `url` and reads enough of it to fill in the stream info.
+    pub fn open(url: &str) -> Result<Self> {
+        InputContext::open_with(url, None, &[])
+    }
+    /// Like `open`, with a forced input format (e.g. `"rawvideo"`) and
+    /// demuxer options (e.g. `video_size`).
+    pub fn open_with(url: &str, format: Option<&str>, options: &[(&str, &str)]) -> Result<Self> {
+        InputContext::open_impl(Some(url), None, format, options)
+    }
+    /// Reads through custom IO, e.g. from memory. Without a forced `format`
+    /// the container is probed from the data.
+    pub fn open_io(io: IoContext, format: Option<&str>, options: &[(&str, &str)]) -> Result<Self> {
+        InputContext::open_impl(None, Some(io), format, options)
+    }
+    fn open_impl(
+        url: Option<&str>,
+        mut io: Option<IoContext>,
+        format: Option<&str>,
+        options: &[(&str, &str)],
+    ) -> Result<Self> {
+        let url_cstr = match url {
+            Some(url) => Some(c_str(url)?),
+            None => None,
+        };
+        unsafe {
+            let ifmt = match format {
+                Some(name) => {
+                    let ifmt = sys::av_find_input_format(c_str(name)?.as_ptr());
+                    if ifmt.is_null() {
+                        return Err(Error::NotFound(format!("demuxer {:?}", name)));
+                    }
+                    ifmt
+                }
+                None => std::ptr::null_mut(),
+            };
+            let mut opts = to_av_dict(options)?;
+            let mut ptr: *mut sys::AVFormatContext = sys::avformat_alloc_context();
+            if ptr.is_null() {
+                sys::av_dict_free(&mut opts);
+                return Err(Error::Alloc("AVFormatContext"));
+            }
+            if let Some(io) = io.as_mut() {
+                (*ptr).pb = io.as_mut_ptr();
+            }
+            // FREES `ptr` ON FAILURE
+            let status = sys::avformat_open_input(
+                &mut ptr,
+                url_cstr.as_ref().map(|x| x.as_ptr()).unwrap_or(std::ptr::null()),
+                ifmt,
+                &mut opts,
+            );
+            sys::av_dict_free(&mut opts);
+            check(status)?;
+            let input = InputContext {ptr, io};
+            check(sys::avformat_find_stream_info(input.ptr, std::ptr::null_mut()))?;
+            Ok(input)
+        }
+    }
+    pub fn nb_streams(&self) -> usize {
+        unsafe { (*self.ptr).nb_streams as usize }
+    }
+    /// Short name of the demuxer, e.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+    pub fn format_name(&self) -> String {
+        unsafe { from_c_str((*(*self.ptr).iformat).name).unwrap_or_default() }
+    }
+    /// Panics if `index` is out of bounds.
+    pub fn stream_ptr(&self, index: usize) -> *mut sys::AVStream {
+        assert!(index < self.nb_streams(), "stream index out of bounds");
+        unsafe { *(*self.ptr).streams.add(index) }
+    }
+    pub fn stream_time_base(&self, index: usize) -> sys::AVRational {
+        unsafe { (*self.stream_ptr(index)).time_base }
+    }
+    pub fn codec_parameters(&self, index: usize) -> Result<CodecParameters> {
+        unsafe { CodecParameters::copy_from((*self.stream_ptr(index)).codecpar) }
+    }
+    /// In `AV_TIME_BASE` units, 0 if unknown.
+    pub fn start_time(&self) -> i64 {
+        let start_time = unsafe { (*self.ptr).start_time };
+        if start_time == unsafe { defs::av_nopts_value() } { 0 } else { start_time }
+    }
+    /// `ts` in the time base of stream `index`.
+    pub fn stream_timestamp(&self, ts: Timestamp, index: usize) -> Result<i64> {
+        let check_index = |index: usize| {
+            if index < self.nb_streams() {
+                Ok(())
+            } else {
+                Err(Error::Invalid(format!("no stream {}", index)))
+            }
+        };
+        check_index(index)?;
+        let time_base = self.stream_time_base(index);
+        match ts {
+            Timestamp::Seconds(seconds) => {
+                let ts = self.start_time() + (seconds * sys::AV_TIME_BASE as f64) as i64;
+                Ok(unsafe { sys::av_rescale_q(ts, AV_TIME_BASE_Q, time_base) })
+            }
+            Timestamp::Stream {index: src, ts} => {
+                check_index(src)?;
+                Ok(unsafe { sys::av_rescale_q(ts, self.stream_time_base(src), time_base) })
+            }
+        }
+    }
+    /// Repositions the demuxer (files and seekable custom IO only). Decoders
+    /// fed from this input must be flushed afterwards.
+    pub fn seek(&mut self, ts: Timestamp, mode: SeekMode) -> Result<()> {
+        let (stream_index, ts) = match ts {
+            Timestamp::Seconds(seconds) => {
+                (-1, self.start_time() + (seconds * sys::AV_TIME_BASE as f64) as i64)
+            }
+            Timestamp::Stream {index, ts} => {
+                if index >= self.nb_streams() {
+                    return Err(Error::Invalid(format!("no stream {}", index)));
+                }
+                (index as c_int, ts)
+            }
+        };
+        let (stream_index, ts) = match mode {
+            SeekMode::KeyframeBefore => (stream_index, ts),
+            SeekMode::KeyframeNearest => self.nearest_keyframe(stream_index, ts),
+            SeekMode::Exact => {
+                return Err(Error::Invalid(String::from(
+                    "exact seeking needs a decoder, use StreamDecoder::seek",
+                )));
+            }
+        };
+        unsafe {
+            check(sys::avformat_seek_file(self.ptr, stream_index, i64::MIN, ts, ts, 0))?;
+        }
+        Ok(())
+    }
+    /// The indexed keyframe closest to `ts`, in the time base of the
+    /// returned stream; `ts` itself when the stream has no index.
+    fn nearest_keyframe(&self, stream_index: c_int, ts: i64) -> (c_int, i64) {
+        unsafe {
+            // `-1` MEANS AV_TIME_BASE UNITS, BUT ONLY STREAMS HAVE AN INDEX
+            let (stream_index, ts) = if stream_index < 0 {
+                let index = sys::av_find_default_stream_index(self.ptr);
+                if index < 0 {
+                    return (stream_index, ts);
+                }
+                let time_base = self.stream_time_base(index as usize);
+                (index, sys::av_rescale_q(ts, AV_TIME_BASE_Q, time_base))
+            } else {
+                (stream_index, ts)
+            };
+            let stream = self.stream_ptr(stream_index as usize);
+            let keyframe = |flags: u32| {
+                let i = sys::av_index_search_timestamp(stream, ts, flags as c_int);
+                if i < 0 {
+                    None
+                } else {
+                    Some((*(*stream).index_entries.add(i as usize)).timestamp)
+                }
+            };
+            let nearest = match (keyframe(sys::AVSEEK_FLAG_BACKWARD), keyframe(0)) {
+                (Some(before), Some(after)) => {
+                    if ts - before <= after - ts { before } else { after }
+                }
+                (Some(x), None) | (None, Some(x)) => x,
+                (None, None) => ts,
+            };
+            (stream_index, nearest)
+        }
+    }
+    /// Reads the next packet of any stream; `false` at end of file.
+    pub fn read_packet(&mut self, pkt: &mut Packet) -> Result<bool> {
+        pkt.unref();
+        match check(unsafe { sys::av_read_frame(self.ptr, pkt.as_mut_ptr()) }) {
+            Ok(_) => Ok(true),
+            Err(ref err) if err.is_eof() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+    pub fn as_ptr(&self) -> *const sys::AVFormatContext {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVFormatContext {
+        self.ptr
+    }
+}
+
+impl Drop for InputContext {
+    fn drop(&mut self) {
+        unsafe {
+            sys::avformat_close_input(&mut self.ptr);
+        };
+    }
+}
+
+pub struct OutputContext {
+    ptr: *mut sys::AVFormatContext,
+    // DROPPED AFTER `ptr` IS FREED
+    io: Option<IoContext>,
+    memory_file: Option<Box<MemoryFile>>,
+}
+
+/// `io_open` for in-memory outputs. The only thing muxers open on their own
+/// here is the output itself, read back for `movflags=+faststart`.
+unsafe extern "C" fn reopen_memory_file(
+    s: *mut sys::AVFormatContext,
+    pb: *mut *mut sys::AVIOContext,
+    url: *const c_char,
+    flags: c_int,
+    options: *mut *mut sys::AVDictionary,
+) -> c_int {
+    if (*s).opaque.is_null() || (flags & sys::AVIO_FLAG_WRITE as c_int) != 0 {
+        return defs::averror(libc::ENOSYS);
+    }
+    let file = &*((*s).opaque as *const MemoryFile);
+    match IoContext::from_seekable_reader(file.reopen()) {
+        Ok(io) => {
+            *pb = io.into_raw();
+            0
+        }
+        Err(_) => defs::averror(libc::ENOMEM),
+    }
+}
+
+unsafe extern "C" fn close_memory_file(s: *mut sys::AVFormatContext, pb: *mut sys::AVIOContext) {
+    if !pb.is_null() {
+        drop(IoContext::from_raw(pb));
+    }
+}
+
+impl OutputContext {
+    /// Allocates a muxer for `url`. The container is guessed from the file
+    /// extension unless `format` (e.g. `"mp4"`) is given.
+    pub fn new(url: &str, format: Option<&str>) -> Result<Self> {
+        let url_cstr = c_str(url)?;
+        let format_cstr = match format {
+            Some(name) => Some(c_str(name)?),
+            None => None,
+        };
+        unsafe {
+            let mut ptr: *mut sys::AVFormatContext = std::ptr::null_mut();
+            let status = sys::avformat_alloc_output_context2(
+                &mut ptr,
+                std::ptr::null_mut(),
+                format_cstr.as_ref().map(|x| x.as_ptr()).unwrap_or(std::ptr::null()),
+                url_cstr.as_ptr(),
+            );
+            if status < 0 || ptr.is_null() {
+                return Err(Error::NotFound(format!(
+                    "muxer for {:?}",
+                    format.unwrap_or(url),
+                )));
+            }
+            Ok(OutputContext {ptr, io: None, memory_file: None})
+        }
+    }
+    /// Writes through custom IO instead of a file.
+    pub fn with_io(io: IoContext, format: &str) -> Result<Self> {
+        let mut output = OutputContext::new("", Some(format))?;
+        let mut io = io;
+        unsafe {
+            (*output.ptr).pb = io.as_mut_ptr();
+            (*output.ptr).flags |= sys::AVFMT_FLAG_CUSTOM_IO as c_int;
+        }
+        output.io = Some(io);
+        Ok(output)
+    }
+    /// Muxes into memory. The returned file holds the output once
+    /// `write_trailer` returned (see `MemoryFile::take`).
+    pub fn in_memory(format: &str) -> Result<(Self, MemoryFile)> {
+        let file = MemoryFile::new();
+        let io = IoContext::from_seekable_writer(file.clone())?;
+        let mut output = OutputContext::with_io(io, format)?;
+        let mut reopen = Box::new(file.reopen());
+        unsafe {
+            (*output.ptr).opaque = &mut *reopen as *mut MemoryFile as *mut c_void;
+            (*output.ptr).io_open = Some(reopen_memory_file);
+            (*output.ptr).io_close = Some(close_memory_file);
+        }
+        output.memory_file = Some(reopen);
+        Ok((output, file))
+    }
+    pub fn nb_streams(&self) -> usize {
+        unsafe { (*self.ptr).nb_streams as usize }
+    }
+    /// Short name of the muxer, e.g. `mp4`.
+    pub fn format_name(&self) -> String {
+        unsafe { from_c_str((*(*self.ptr).oformat).name).unwrap_or_default() }
+    }
+    /// Panics if `index` is out of bounds.
+    pub fn stream_ptr(&self, index: usize) -> *mut sys::AVStream {
+        assert!(index < self.nb_streams(), "stream index out of bounds");
+        unsafe { *(*self.ptr).streams.add(index) }
+    }
+    /// Only final after `write_header`, since muxers may pick their own.
+    pub fn stream_time_base(&self, index: usize) -> sys::AVRational {
+        unsafe { (*self.stream_ptr(index)).time_base }
+    }
+    /// Adds a stream with a copy of `par`; `time_base` is a hint the muxer
+    /// may override. Returns the new stream index.
+    pub fn add_stream(&mut self, par: &CodecParameters, time_base: sys::AVRational) -> Result<usize> {
+        unsafe {
+            let stream = sys::avformat_new_stream(self.ptr, std::ptr::null());
+            if stream.is_null() {
+                return Err(Error::Alloc("AVStream"));
+            }
+            check(sys::avcodec_parameters_copy((*stream).codecpar, par.as_ptr()))?;
+            // THE SOURCE CONTAINER'S TAG MAY NOT BE VALID FOR THIS ONE
+            (*(*stream).codecpar).codec_tag = 0;
+            (*stream).time_base = time_base;
+            Ok((*stream).index as usize)
+        }
+    }
+    /// Opens the output file (unless the muxer doesn't need one, or IO was
+    /// already set up) and writes the header. Options are muxer options,
+    /// e.g. `movflags`.
+    pub fn write_header(&mut self, options: &[(&str, &str)]) -> Result<()> {
+        unsafe {
+            let ofmt = (*self.ptr).oformat;
+            let needs_file = ((*ofmt).flags & (sys::AVFMT_NOFILE as i32)) == 0;
+            if needs_file && (*self.ptr).pb.is_null() {
+                check(sys::avio_open(
+                    &mut (*self.ptr).pb,
+                    (*self.ptr).url,
+                    sys::AVIO_FLAG_WRITE as i32,
+                ))?;
+            }
+            let mut opts = to_av_dict(options)?;
+            let status = sys::avformat_write_header(self.ptr, &mut opts);
+            sys::av_dict_free(&mut opts);
+            check(status)?;
+        }
+        Ok(())
+    }
+    /// Interleaves and writes `pkt`, whose timestamps must already be in the
+    /// output stream's time base. The packet is left blank.
+    pub fn write_packet(&mut self, pkt: &mut Packet) -> Result<()> {
+        unsafe {
+            check(sys::av_interleaved_write_frame(self.ptr, pkt.as_mut_ptr()))?;
+        }
+        Ok(())
+    }
+    pub fn write_trailer(&mut self) -> Result<()> {
+        unsafe {
+            check(sys::av_write_trailer(self.ptr))?;
+        }
+        if let Some(io) = self.io.as_mut() {
+            io.flush();
+        }
+        Ok(())
+    }
+    pub fn as_ptr(&self) -> *const sys::AVFormatContext {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVFormatContext {
+        self.ptr
+    }
+}
+
+impl Drop for OutputContext {
+    fn drop(&mut self) {
+        unsafe {
+            let ofmt = (*self.ptr).oformat;
+            let owns_pb = {
+                self.io.is_none() &&
+                !ofmt.is_null() &&
+                ((*ofmt).flags & (sys::AVFMT_NOFILE as i32)) == 0
+            };
+            if owns_pb && !(*self.ptr).pb.is_null() {
+                sys::avio_closep(&mut (*self.ptr).pb);
+            }
+            sys::avformat_free_context(self.ptr);
+        };
+    }
+}
@@ -0,0 +1,178 @@
+//! Owned, reference counted `AVFrame`.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::side_data::{FrameSideDataType, SideData};
+use crate::extra::util::{from_av_dict, raw_bytes};
+
+/// Size and pixel format of a video frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PictureFormat {
+    pub width: i32,
+    pub height: i32,
+    pub pix_fmt: sys::AVPixelFormat,
+}
+
+pub struct Frame {
+    ptr: *mut sys::AVFrame,
+}
+
+impl Frame {
+    /// An empty frame, e.g. for `avcodec_receive_frame` to fill.
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { sys::av_frame_alloc() };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVFrame"));
+        }
+        Ok(Frame {ptr})
+    }
+    /// A video frame with freshly allocated (uninitialized) planes.
+    pub fn new_video(format: PictureFormat) -> Result<Self> {
+        let frame = Frame::new()?;
+        unsafe {
+            (*frame.ptr).width = format.width;
+            (*frame.ptr).height = format.height;
+            (*frame.ptr).format = format.pix_fmt as c_int;
+            check(sys::av_frame_get_buffer(frame.ptr, 0))?;
+        }
+        Ok(frame)
+    }
+    /// An audio frame with room for `nb_samples` samples per channel.
+    pub fn new_audio(
+        nb_samples: i32,
+        sample_fmt: sys::AVSampleFormat,
+        channel_layout: u64,
+        sample_rate: i32,
+    ) -> Result<Self> {
+        let frame = Frame::new()?;
+        unsafe {
+            (*frame.ptr).nb_samples = nb_samples;
+            (*frame.ptr).format = sample_fmt as c_int;
+            (*frame.ptr).channel_layout = channel_layout;
+            (*frame.ptr).channels = sys::av_get_channel_layout_nb_channels(channel_layout);
+            (*frame.ptr).sample_rate = sample_rate;
+            check(sys::av_frame_get_buffer(frame.ptr, 0))?;
+        }
+        Ok(frame)
+    }
+    pub fn width(&self) -> i32 {
+        unsafe { (*self.ptr).width }
+    }
+    pub fn height(&self) -> i32 {
+        unsafe { (*self.ptr).height }
+    }
+    /// `AVPixelFormat` for video, `AVSampleFormat` for audio.
+    pub fn format(&self) -> c_int {
+        unsafe { (*self.ptr).format }
+    }
+    pub fn picture_format(&self) -> PictureFormat {
+        PictureFormat {
+            width: self.width(),
+            height: self.height(),
+            pix_fmt: self.format() as sys::AVPixelFormat,
+        }
+    }
+    pub fn nb_samples(&self) -> i32 {
+        unsafe { (*self.ptr).nb_samples }
+    }
+    pub fn sample_rate(&self) -> i32 {
+        unsafe { (*self.ptr).sample_rate }
+    }
+    pub fn channel_layout(&self) -> u64 {
+        unsafe { (*self.ptr).channel_layout }
+    }
+    pub fn channels(&self) -> i32 {
+        unsafe { (*self.ptr).channels }
+    }
+    pub fn pts(&self) -> i64 {
+        unsafe { (*self.ptr).pts }
+    }
+    pub fn set_pts(&mut self, pts: i64) {
+        unsafe {
+            (*self.ptr).pts = pts;
+        };
+    }
+    pub fn best_effort_timestamp(&self) -> i64 {
+        unsafe { (*self.ptr).best_effort_timestamp }
+    }
+    /// Entries attached by filters, e.g. `lavfi.r128.I` from `ebur128`.
+    pub fn metadata(&self) -> Vec<(String, String)> {
+        unsafe { from_av_dict((*self.ptr).metadata) }
+    }
+    /// Typed where `side_data` knows the layout.
+    pub fn side_data(&self) -> Vec<SideData> {
+        unsafe {
+            (0..(*self.ptr).nb_side_data.max(0) as usize)
+                .map(|i| {
+                    let entry = &**(*self.ptr).side_data.add(i);
+                    let data = raw_bytes(entry.data, entry.size.max(0) as usize);
+                    SideData::from_frame(FrameSideDataType::from_raw(entry.type_), data)
+                })
+                .collect()
+        }
+    }
+    /// Adds an entry, replacing any of the same type.
+    pub fn set_side_data(&mut self, side_data: &SideData) -> Result<()> {
+        let kind = side_data.frame_type()
+            .ok_or_else(|| Error::Invalid(format!("{:?} is not frame side data", side_data)))?;
+        let bytes = side_data.to_bytes();
+        unsafe {
+            sys::av_frame_remove_side_data(self.ptr, kind.to_raw());
+            let entry = sys::av_frame_new_side_data(self.ptr, kind.to_raw(), bytes.len() as _);
+            if entry.is_null() {
+                return Err(Error::Alloc("AVFrameSideData"));
+            }
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), (*entry).data, bytes.len());
+        }
+        Ok(())
+    }
+    pub fn remove_side_data(&mut self, kind: FrameSideDataType) {
+        unsafe {
+            sys::av_frame_remove_side_data(self.ptr, kind.to_raw());
+        };
+    }
+    pub fn is_allocated(&self) -> bool {
+        unsafe { !(*self.ptr).data[0].is_null() }
+    }
+    pub fn linesize(&self, plane: usize) -> i32 {
+        unsafe { (*self.ptr).linesize[plane] }
+    }
+    /// Ensures the frame data isn't shared with another frame, copying it
+    /// if needed, so it can be written to.
+    pub fn make_writable(&mut self) -> Result<()> {
+        unsafe {
+            check(sys::av_frame_make_writable(self.ptr))?;
+        }
+        Ok(())
+    }
+    /// Drops the data references, keeping the allocation for reuse.
+    pub fn unref(&mut self) {
+        unsafe {
+            sys::av_frame_unref(self.ptr);
+        };
+    }
+    /// A new reference to the same data (`av_frame_clone`).
+    pub fn try_clone(&self) -> Result<Self> {
+        let ptr = unsafe { sys::av_frame_clone(self.ptr) };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVFrame"));
+        }
+        Ok(Frame {ptr})
+    }
+    pub fn as_ptr(&self) -> *const sys::AVFrame {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVFrame {
+        self.ptr
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_frame_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for Frame {}
@@ -0,0 +1,206 @@
+//! Per-frame and per-stream checksums through the `framecrc`, `framemd5`,
+//! `framehash` and `streamhash` muxers, in the text format FATE compares
+//! against, e.g. for golden-file tests of decoder output.
+//!
+//! ```ignore
+//! let hashes = frame_hashes(InputContext::open("clip.mkv")?, &HashOptions::default())?;
+//! assert_eq!(hashes.text, std::fs::read_to_string("clip.framemd5")?);
+//! ```
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::error::{Error, Result};
+use crate::extra::format::{InputContext, OutputContext};
+use crate::extra::io::MemoryFile;
+use crate::extra::remux::{self, RemuxOptions};
+use crate::extra::transcode::{EncoderSettings, StreamPlan, Transcoder};
+
+/// Any `av_hash` algorithm works with `Named`, e.g. `sha512` or `murmur3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// Adler-32; per frame, this is the `framecrc` format.
+    Adler32,
+    Crc32,
+    Md5,
+    Sha256,
+    Named(String),
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &str {
+        match self {
+            HashAlgorithm::Adler32 => "adler32",
+            HashAlgorithm::Crc32 => "CRC32",
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Named(x) => x,
+        }
+    }
+}
+
+/// What gets hashed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashSource {
+    /// Decoded frames, as `rawvideo` pictures and PCM audio, like
+    /// `ffmpeg -i input -f framemd5 -` does.
+    Decoded {
+        /// PCM encoder for the audio; `pcm_s16le` is what FATE uses unless
+        /// a test says otherwise.
+        audio_codec: String,
+    },
+    /// The demuxed packets, like `ffmpeg -i input -c copy -f framemd5 -`.
+    Packets,
+}
+
+#[derive(Debug, Clone)]
+pub struct HashOptions {
+    pub algorithm: HashAlgorithm,
+    pub source: HashSource,
+}
+
+impl Default for HashOptions {
+    fn default() -> Self {
+        HashOptions {
+            algorithm: HashAlgorithm::Md5,
+            source: HashSource::Decoded {audio_codec: String::from("pcm_s16le")},
+        }
+    }
+}
+
+/// One line of a frame checksum file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameHash {
+    pub stream_index: usize,
+    pub dts: i64,
+    pub pts: i64,
+    pub duration: i64,
+    pub size: usize,
+    /// Lowercase hex, or `0x`-prefixed for `framecrc`.
+    pub hash: String,
+    /// Packet flags and side data checksums following the hash, if any,
+    /// e.g. `F=0x0`.
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHashes {
+    /// The muxer output, comment lines included.
+    pub text: String,
+    pub frames: Vec<FrameHash>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamHash {
+    pub stream_index: usize,
+    /// `v`, `a`, `s`, `d` or `t`.
+    pub media_type: char,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamHashes {
+    pub text: String,
+    pub streams: Vec<StreamHash>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Runs `input` through `muxer` (with its `hash` option, unless `None`)
+/// and returns what it wrote.
+fn run_muxer(input: InputContext, muxer: &str, hash: Option<&str>, source: &HashSource) -> Result<String> {
+    let (output, file) = OutputContext::in_memory(muxer)?;
+    let muxer_options = hash.into_iter().map(|x| (String::from("hash"), String::from(x))).collect::<Vec<_>>();
+    match source {
+        HashSource::Packets => {
+            // `ffmpeg -c copy` SUBTRACTS THE INPUT'S START TIME, NOT THE FIRST PACKET'S
+            let start_time = input.start_time() as f64 / sys::AV_TIME_BASE as f64;
+            let options = RemuxOptions {
+                copy_metadata: false,
+                normalize_timestamps: false,
+                offset: -start_time,
+                muxer_options,
+                ..RemuxOptions::default()
+            };
+            remux::remux_contexts(input, output, &options)?;
+        }
+        HashSource::Decoded {audio_codec} => {
+            let mut transcoder = Transcoder::new(input, output)?;
+            transcoder.set_plan_for(MediaType::Video, StreamPlan::Transcode(EncoderSettings::new("rawvideo")));
+            transcoder.set_plan_for(MediaType::Audio, StreamPlan::Transcode(EncoderSettings::new(audio_codec)));
+            let muxer_options = muxer_options
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+            transcoder.set_muxer_options(&muxer_options);
+            transcoder.run()?;
+        }
+    }
+    text_of(file)
+}
+
+fn text_of(file: MemoryFile) -> Result<String> {
+    String::from_utf8(file.take())
+        .map_err(|_| Error::Invalid(String::from("checksum output is not UTF-8")))
+}
+
+/// Parses `stream, dts, pts, duration, size, hash[, extra...]` lines.
+fn parse_frames(text: &str) -> Result<Vec<FrameHash>> {
+    let invalid = |line: &str| Error::Invalid(format!("checksum line {:?}", line));
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            if fields.len() < 6 {
+                return Err(invalid(line));
+            }
+            Ok(FrameHash {
+                stream_index: fields[0].parse().map_err(|_| invalid(line))?,
+                dts: fields[1].parse().map_err(|_| invalid(line))?,
+                pts: fields[2].parse().map_err(|_| invalid(line))?,
+                duration: fields[3].parse().map_err(|_| invalid(line))?,
+                size: fields[4].parse().map_err(|_| invalid(line))?,
+                hash: String::from(fields[5]),
+                extra: fields[6..].iter().map(|x| String::from(*x)).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Parses `stream,type,ALGORITHM=hash` lines.
+fn parse_streams(text: &str) -> Result<Vec<StreamHash>> {
+    let invalid = |line: &str| Error::Invalid(format!("checksum line {:?}", line));
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.splitn(3, ',');
+            let stream_index = fields.next().and_then(|x| x.parse().ok()).ok_or_else(|| invalid(line))?;
+            let media_type = fields.next().and_then(|x| x.chars().next()).ok_or_else(|| invalid(line))?;
+            let hash = fields.next().and_then(|x| x.splitn(2, '=').nth(1)).ok_or_else(|| invalid(line))?;
+            Ok(StreamHash {stream_index, media_type, hash: String::from(hash)})
+        })
+        .collect()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HASHING
+///////////////////////////////////////////////////////////////////////////////
+
+/// A checksum per frame (or packet) of every audio, video and subtitle
+/// stream.
+pub fn frame_hashes(input: InputContext, options: &HashOptions) -> Result<FrameHashes> {
+    let text = match &options.algorithm {
+        HashAlgorithm::Adler32 => run_muxer(input, "framecrc", None, &options.source)?,
+        HashAlgorithm::Md5 => run_muxer(input, "framemd5", None, &options.source)?,
+        x => run_muxer(input, "framehash", Some(x.name()), &options.source)?,
+    };
+    let frames = parse_frames(&text)?;
+    Ok(FrameHashes {text, frames})
+}
+
+/// One checksum over all frames (or packets) of each stream.
+pub fn stream_hashes(input: InputContext, options: &HashOptions) -> Result<StreamHashes> {
+    let text = run_muxer(input, "streamhash", Some(options.algorithm.name()), &options.source)?;
+    let streams = parse_streams(&text)?;
+    Ok(StreamHashes {text, streams})
+}
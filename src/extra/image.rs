@@ -0,0 +1,210 @@
+//! Still images, decoded into owned and tightly packed planes.
+pub mod encode;
+pub mod transform;
+
+use std::os::raw::{c_int, c_void};
+use crate::sys;
+use crate::extra::codec::CodecContext;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::format::InputContext;
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::io::{IoContext, SliceReader};
+use crate::extra::packet::Packet;
+use crate::extra::util::from_c_str;
+
+/// What `decode_image` should expect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageHint {
+    /// Probed from the data; covers every image format the build can decode.
+    Auto,
+    /// A demuxer by name, e.g. `image_jpeg_pipe`, for data that doesn't
+    /// probe reliably.
+    Format(String),
+    /// Headerless pixels (e.g. YUV420P planes), which carry no size.
+    Raw(PictureFormat),
+}
+
+impl Default for ImageHint {
+    fn default() -> Self {
+        ImageHint::Auto
+    }
+}
+
+pub struct Image {
+    data: [*mut u8; 4],
+    linesize: [c_int; 4],
+    size: usize,
+    format: PictureFormat,
+}
+
+impl Image {
+    /// A zeroed image, with planes packed without padding (like `rawvideo`).
+    pub fn new(format: PictureFormat) -> Result<Self> {
+        let mut data: [*mut u8; 4] = [std::ptr::null_mut(); 4];
+        let mut linesize: [c_int; 4] = [0; 4];
+        unsafe {
+            let size = check(sys::av_image_alloc(
+                data.as_mut_ptr(),
+                linesize.as_mut_ptr(),
+                format.width,
+                format.height,
+                format.pix_fmt,
+                1,
+            ))?;
+            std::ptr::write_bytes(data[0], 0, size as usize);
+            Ok(Image {data, linesize, size: size as usize, format})
+        }
+    }
+    /// Copies the visible area of a video frame.
+    pub fn from_frame(frame: &Frame) -> Result<Self> {
+        let mut image = Image::new(frame.picture_format())?;
+        unsafe {
+            sys::av_image_copy(
+                image.data.as_mut_ptr(),
+                image.linesize.as_mut_ptr(),
+                (*frame.as_ptr()).data.as_ptr() as *mut *const u8,
+                (*frame.as_ptr()).linesize.as_ptr(),
+                image.format.pix_fmt,
+                image.format.width,
+                image.format.height,
+            );
+        }
+        Ok(image)
+    }
+    /// Copies the image into a new frame, e.g. to scale or encode it.
+    pub fn to_frame(&self) -> Result<Frame> {
+        let mut frame = Frame::new_video(self.format)?;
+        unsafe {
+            sys::av_image_copy(
+                (*frame.as_mut_ptr()).data.as_mut_ptr(),
+                (*frame.as_mut_ptr()).linesize.as_mut_ptr(),
+                self.data.as_ptr() as *mut *const u8,
+                self.linesize.as_ptr(),
+                self.format.pix_fmt,
+                self.format.width,
+                self.format.height,
+            );
+        }
+        Ok(frame)
+    }
+    pub fn width(&self) -> i32 {
+        self.format.width
+    }
+    pub fn height(&self) -> i32 {
+        self.format.height
+    }
+    pub fn pix_fmt(&self) -> sys::AVPixelFormat {
+        self.format.pix_fmt
+    }
+    /// E.g. `yuvj420p`.
+    pub fn pix_fmt_name(&self) -> String {
+        unsafe { from_c_str(sys::av_get_pix_fmt_name(self.format.pix_fmt)).unwrap_or_default() }
+    }
+    pub fn format(&self) -> PictureFormat {
+        self.format
+    }
+    /// Number of planes, counting the palette of paletted formats.
+    pub fn nb_planes(&self) -> usize {
+        self.data.iter().take_while(|x| !x.is_null()).count()
+    }
+    pub fn linesize(&self, plane: usize) -> i32 {
+        self.linesize[plane]
+    }
+    /// Panics if `plane` is out of bounds.
+    pub fn plane(&self, plane: usize) -> &[u8] {
+        let (start, len) = self.plane_range(plane);
+        unsafe { std::slice::from_raw_parts(self.data[0].add(start), len) }
+    }
+    pub fn plane_mut(&mut self, plane: usize) -> &mut [u8] {
+        let (start, len) = self.plane_range(plane);
+        unsafe { std::slice::from_raw_parts_mut(self.data[0].add(start), len) }
+    }
+    /// All planes back to back, i.e. the `rawvideo` representation.
+    pub fn data(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data[0], self.size) }
+    }
+    /// A deep copy.
+    pub fn try_clone(&self) -> Result<Self> {
+        let image = Image::new(self.format)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.data[0], image.data[0], self.size);
+        }
+        Ok(image)
+    }
+    fn plane_range(&self, plane: usize) -> (usize, usize) {
+        assert!(plane < self.nb_planes(), "plane index out of bounds");
+        // PLANES ARE ALLOCATED CONTIGUOUSLY AND IN ORDER
+        let offset = |x: *mut u8| x as usize - self.data[0] as usize;
+        let start = offset(self.data[plane]);
+        let end = match self.data.get(plane + 1) {
+            Some(next) if !next.is_null() => offset(*next),
+            _ => self.size,
+        };
+        (start, end - start)
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_freep(&mut self.data[0] as *mut *mut u8 as *mut c_void);
+        };
+    }
+}
+
+unsafe impl Send for Image {}
+
+/// Decodes the first picture in `bytes`.
+pub fn decode_image(bytes: &[u8], hint: &ImageHint) -> Result<Image> {
+    let raw_options;
+    let (format, options) = match hint {
+        ImageHint::Auto => (None, Vec::new()),
+        ImageHint::Format(name) => (Some(name.as_str()), Vec::new()),
+        ImageHint::Raw(format) => {
+            let pix_fmt = unsafe { from_c_str(sys::av_get_pix_fmt_name(format.pix_fmt)) };
+            let pix_fmt = pix_fmt.ok_or_else(|| {
+                Error::Invalid(format!("pixel format {}", format.pix_fmt))
+            })?;
+            raw_options = [format!("{}x{}", format.width, format.height), pix_fmt];
+            let options = vec![
+                ("video_size", raw_options[0].as_str()),
+                ("pixel_format", raw_options[1].as_str()),
+            ];
+            (Some("rawvideo"), options)
+        }
+    };
+    // THE IO CONTEXT NEVER OUTLIVES THIS CALL
+    let reader = unsafe { SliceReader::new(bytes) };
+    let io = IoContext::from_seekable_reader(reader)?;
+    let mut input = InputContext::open_io(io, format, &options)?;
+    let index = unsafe {
+        check(sys::av_find_best_stream(
+            input.as_mut_ptr(),
+            sys::AVMediaType_AVMEDIA_TYPE_VIDEO,
+            -1,
+            -1,
+            std::ptr::null_mut(),
+            0,
+        ))? as usize
+    };
+    let par = input.codec_parameters(index)?;
+    let mut decoder = CodecContext::new_decoder(par.codec_id())?;
+    par.to_context(&mut decoder)?;
+    decoder.open(&[])?;
+    let mut pkt = Packet::new()?;
+    let mut frame = Frame::new()?;
+    while input.read_packet(&mut pkt)? {
+        if pkt.stream_index() != index {
+            continue;
+        }
+        decoder.send_packet(&pkt)?;
+        if decoder.receive_frame(&mut frame)? {
+            return Image::from_frame(&frame);
+        }
+    }
+    decoder.send_eof()?;
+    if decoder.receive_frame(&mut frame)? {
+        return Image::from_frame(&frame);
+    }
+    Err(Error::Invalid(String::from("no picture could be decoded")))
+}
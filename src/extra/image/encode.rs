@@ -0,0 +1,161 @@
+//! Encoding `Image`s with the bundled still image encoders.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::codec::CodecContext;
+use crate::extra::error::{Error, Result};
+use crate::extra::image::Image;
+use crate::extra::image::transform::{self, has_alpha, is_jpeg_yuv};
+use crate::extra::packet::Packet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageCodec {
+    Jpeg,
+    Gif,
+    Tiff,
+    Bmp,
+    Pam,
+}
+
+impl ImageCodec {
+    pub fn encoder_name(self) -> &'static str {
+        match self {
+            ImageCodec::Jpeg => "mjpeg",
+            ImageCodec::Gif => "gif",
+            ImageCodec::Tiff => "tiff",
+            ImageCodec::Bmp => "bmp",
+            ImageCodec::Pam => "pam",
+        }
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageCodec::Jpeg => "jpg",
+            ImageCodec::Gif => "gif",
+            ImageCodec::Tiff => "tiff",
+            ImageCodec::Bmp => "bmp",
+            ImageCodec::Pam => "pam",
+        }
+    }
+    fn accepts(self, pix_fmt: sys::AVPixelFormat) -> bool {
+        match self {
+            // LIMITED RANGE JPEGS ARE NONSTANDARD
+            ImageCodec::Jpeg => is_jpeg_yuv(pix_fmt),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// From 1 (smallest) to 100 (best); only affects JPEG, the others are
+    /// lossless.
+    pub quality: Option<u8>,
+    /// RGB color transparent images are composited onto for codecs without
+    /// alpha support (JPEG).
+    pub background: [u8; 3],
+    /// Encoder private options, e.g. `compression_algo=deflate` for TIFF.
+    pub options: Vec<(String, String)>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            quality: None,
+            background: [255, 255, 255],
+            options: Vec::new(),
+        }
+    }
+}
+
+/// Maps 1..=100 onto the `-q:v` scale, 31 (worst) to 2 (best).
+fn qscale(quality: u8) -> c_int {
+    let quality = quality.max(1).min(100) as c_int;
+    2 + (100 - quality) * 29 / 99
+}
+
+/// The encoder's pixel format closest to `src` that swscale can produce.
+fn encoder_pix_fmt(ctx: &CodecContext, codec: ImageCodec, src: sys::AVPixelFormat) -> Result<sys::AVPixelFormat> {
+    let mut candidates = Vec::new();
+    unsafe {
+        let mut pix_fmts = (*(*ctx.as_ptr()).codec).pix_fmts;
+        if pix_fmts.is_null() {
+            return Ok(src);
+        }
+        while *pix_fmts != sys::AVPixelFormat_AV_PIX_FMT_NONE {
+            let pix_fmt = *pix_fmts;
+            let usable = pix_fmt == src || sys::sws_isSupportedOutput(pix_fmt) > 0;
+            if usable && codec.accepts(pix_fmt) {
+                candidates.push(pix_fmt);
+            }
+            pix_fmts = pix_fmts.add(1);
+        }
+    }
+    if candidates.contains(&src) {
+        return Ok(src);
+    }
+    candidates.push(sys::AVPixelFormat_AV_PIX_FMT_NONE);
+    let best = unsafe {
+        sys::avcodec_find_best_pix_fmt_of_list(
+            candidates.as_ptr(),
+            src,
+            has_alpha(src) as c_int,
+            std::ptr::null_mut(),
+        )
+    };
+    if best == sys::AVPixelFormat_AV_PIX_FMT_NONE {
+        return Err(Error::Invalid(format!("no pixel format usable by {}", codec.encoder_name())));
+    }
+    Ok(best)
+}
+
+/// Encodes `image`, converting it to a pixel format the codec supports if
+/// needed.
+pub fn encode_image(image: &Image, codec: ImageCodec, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let mut ctx = CodecContext::new_encoder_by_name(codec.encoder_name())?;
+    let pix_fmt = encoder_pix_fmt(&ctx, codec, image.pix_fmt())?;
+    let converted;
+    let image = if pix_fmt == image.pix_fmt() {
+        image
+    } else {
+        converted = transform::convert(image, pix_fmt, options.background)?;
+        &converted
+    };
+    let global_quality = options.quality.map(|x| qscale(x) * sys::FF_QP2LAMBDA as c_int);
+    unsafe {
+        let raw = &mut *ctx.as_mut_ptr();
+        raw.width = image.width();
+        raw.height = image.height();
+        raw.pix_fmt = pix_fmt;
+        raw.time_base = sys::AVRational {num: 1, den: 1};
+        if is_jpeg_yuv(pix_fmt) {
+            raw.color_range = sys::AVColorRange_AVCOL_RANGE_JPEG;
+        }
+        if let Some(global_quality) = global_quality {
+            raw.flags |= sys::AV_CODEC_FLAG_QSCALE as c_int;
+            raw.global_quality = global_quality;
+        }
+    }
+    let encoder_options = options.options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    ctx.open(&encoder_options)?;
+    let mut frame = image.to_frame()?;
+    frame.set_pts(0);
+    if let Some(global_quality) = global_quality {
+        // FIXED QUALITY ENCODING READS THE PER FRAME VALUE
+        unsafe {
+            (*frame.as_mut_ptr()).quality = global_quality;
+        }
+    }
+    ctx.send_frame(&frame)?;
+    ctx.send_eof()?;
+    let mut output = Vec::new();
+    let mut pkt = Packet::new()?;
+    while ctx.receive_packet(&mut pkt)? {
+        output.extend_from_slice(pkt.data());
+    }
+    if output.is_empty() {
+        return Err(Error::Invalid(format!("{} produced no output", codec.encoder_name())));
+    }
+    Ok(output)
+}
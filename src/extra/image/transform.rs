@@ -0,0 +1,337 @@
+//! Resizing, pixel format conversion and alpha flattening of `Image`s.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::image::Image;
+use crate::extra::scale::{ColorDetails, ScaleAlgorithm, Scaler, ScalerOptions};
+use crate::extra::util::from_c_str;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Fits within the box, keeping the aspect ratio.
+    Fit,
+    /// Covers the box, keeping the aspect ratio; one side may be larger.
+    Fill,
+    /// Covers the box and crops what overflows (centered), so the result
+    /// is exactly the box.
+    Crop,
+    /// Exactly the box, ignoring the aspect ratio.
+    Stretch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resize {
+    pub width: i32,
+    pub height: i32,
+    pub mode: ResizeMode,
+    /// Whether images smaller than the box may be enlarged.
+    pub upscale: bool,
+}
+
+impl Resize {
+    pub fn fit(width: i32, height: i32) -> Self {
+        Resize {width, height, mode: ResizeMode::Fit, upscale: true}
+    }
+    pub fn fill(width: i32, height: i32) -> Self {
+        Resize {width, height, mode: ResizeMode::Fill, upscale: true}
+    }
+    pub fn crop(width: i32, height: i32) -> Self {
+        Resize {width, height, mode: ResizeMode::Crop, upscale: true}
+    }
+    pub fn stretch(width: i32, height: i32) -> Self {
+        Resize {width, height, mode: ResizeMode::Stretch, upscale: true}
+    }
+    /// Keeps images that are already smaller than the box as they are.
+    pub fn without_upscaling(self) -> Self {
+        Resize {upscale: false, ..self}
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub resize: Option<Resize>,
+    /// Defaults to the source pixel format.
+    pub pix_fmt: Option<sys::AVPixelFormat>,
+    pub algorithm: ScaleAlgorithm,
+    /// RGB color transparent images are composited onto when the target
+    /// format has no alpha channel.
+    pub background: [u8; 3],
+    /// Pads chroma subsampled formats (e.g. YUV420P) to even dimensions by
+    /// repeating the last column/row, as most video encoders require.
+    pub pad_to_even: bool,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            resize: None,
+            pix_fmt: None,
+            algorithm: ScaleAlgorithm::default(),
+            background: [255, 255, 255],
+            pad_to_even: true,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PIXEL FORMAT HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+fn pix_fmt_desc(pix_fmt: sys::AVPixelFormat) -> Result<&'static sys::AVPixFmtDescriptor> {
+    let desc = unsafe { sys::av_pix_fmt_desc_get(pix_fmt) };
+    if desc.is_null() {
+        return Err(Error::Invalid(format!("pixel format {}", pix_fmt)));
+    }
+    Ok(unsafe { &*desc })
+}
+
+pub(crate) fn has_alpha(pix_fmt: sys::AVPixelFormat) -> bool {
+    match pix_fmt_desc(pix_fmt) {
+        Ok(desc) => desc.flags & sys::AV_PIX_FMT_FLAG_ALPHA as u64 != 0,
+        Err(_) => false,
+    }
+}
+
+/// The deprecated `yuvj*` formats, i.e. full range YUV as used by JPEG.
+pub(crate) fn is_jpeg_yuv(pix_fmt: sys::AVPixelFormat) -> bool {
+    match pix_fmt {
+        sys::AVPixelFormat_AV_PIX_FMT_YUVJ420P |
+        sys::AVPixelFormat_AV_PIX_FMT_YUVJ422P |
+        sys::AVPixelFormat_AV_PIX_FMT_YUVJ444P |
+        sys::AVPixelFormat_AV_PIX_FMT_YUVJ440P |
+        sys::AVPixelFormat_AV_PIX_FMT_YUVJ411P => true,
+        _ => false,
+    }
+}
+
+/// `Image`s carry no color metadata, so assume BT.601 with the range the
+/// format implies.
+fn default_color(pix_fmt: sys::AVPixelFormat) -> ColorDetails {
+    let is_limited_yuv = match pix_fmt_desc(pix_fmt) {
+        Ok(desc) => {
+            desc.flags & sys::AV_PIX_FMT_FLAG_RGB as u64 == 0 &&
+            desc.nb_components >= 3 &&
+            !is_jpeg_yuv(pix_fmt)
+        }
+        Err(_) => false,
+    };
+    ColorDetails {
+        space: sys::AVColorSpace_AVCOL_SPC_BT470BG,
+        full_range: !is_limited_yuv,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// STEPS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Source region `(x, y, width, height)` to crop to, if any, and the output size.
+fn geometry(width: i32, height: i32, resize: &Resize) -> Result<(Option<(i32, i32, i32, i32)>, i32, i32)> {
+    if resize.width <= 0 || resize.height <= 0 {
+        return Err(Error::Invalid(format!("resize to {}x{}", resize.width, resize.height)));
+    }
+    let (box_w, box_h) = (resize.width as i64, resize.height as i64);
+    let (src_w, src_h) = (width as i64, height as i64);
+    let scaled = |scale: f64| {
+        let scale = if resize.upscale { scale } else { scale.min(1.0) };
+        let w = ((src_w as f64 * scale).round() as i32).max(1);
+        let h = ((src_h as f64 * scale).round() as i32).max(1);
+        (w, h)
+    };
+    let scale_w = box_w as f64 / src_w as f64;
+    let scale_h = box_h as f64 / src_h as f64;
+    match resize.mode {
+        ResizeMode::Fit => {
+            let (w, h) = scaled(scale_w.min(scale_h));
+            Ok((None, w, h))
+        }
+        ResizeMode::Fill => {
+            let (w, h) = scaled(scale_w.max(scale_h));
+            Ok((None, w, h))
+        }
+        ResizeMode::Crop => {
+            // LARGEST CENTERED REGION WITH THE ASPECT RATIO OF THE BOX
+            let (crop_w, crop_h) = if src_w * box_h > src_h * box_w {
+                (((src_h * box_w) / box_h).max(1), src_h)
+            } else {
+                (src_w, ((src_w * box_h) / box_w).max(1))
+            };
+            // EVEN OFFSETS KEEP SUBSAMPLED CHROMA ALIGNED
+            let x = ((src_w - crop_w) / 2) & !1;
+            let y = ((src_h - crop_h) / 2) & !1;
+            let (w, h) = if !resize.upscale && crop_w < box_w {
+                (crop_w as i32, crop_h as i32)
+            } else {
+                (resize.width, resize.height)
+            };
+            Ok((Some((x as i32, y as i32, crop_w as i32, crop_h as i32)), w, h))
+        }
+        ResizeMode::Stretch if resize.upscale => Ok((None, resize.width, resize.height)),
+        ResizeMode::Stretch => Ok((None, resize.width.min(width), resize.height.min(height))),
+    }
+}
+
+fn crop_frame(frame: &mut Frame, x: i32, y: i32, width: i32, height: i32) -> Result<()> {
+    unsafe {
+        let raw = &mut *frame.as_mut_ptr();
+        raw.crop_left = x as usize;
+        raw.crop_top = y as usize;
+        raw.crop_right = (raw.width - x - width) as usize;
+        raw.crop_bottom = (raw.height - y - height) as usize;
+        check(sys::av_frame_apply_cropping(raw, sys::AV_FRAME_CROP_UNALIGNED as c_int))?;
+    }
+    Ok(())
+}
+
+fn convert_frame(src: &Frame, dst: PictureFormat, algorithm: ScaleAlgorithm) -> Result<Frame> {
+    if src.picture_format() == dst {
+        return src.try_clone();
+    }
+    let options = ScalerOptions {
+        algorithm,
+        accurate_rounding: true,
+        full_chroma: true,
+        src_color: Some(default_color(src.picture_format().pix_fmt)),
+        dst_color: Some(default_color(dst.pix_fmt)),
+    };
+    Scaler::new(src.picture_format(), dst, options)?.scale_new(src)
+}
+
+/// Composites an RGBA frame onto `background`, returning RGB24.
+fn flatten_alpha(src: &Frame, background: [u8; 3]) -> Result<Frame> {
+    let mut dst = Frame::new_video(PictureFormat {
+        width: src.width(),
+        height: src.height(),
+        pix_fmt: sys::AVPixelFormat_AV_PIX_FMT_RGB24,
+    })?;
+    let width = src.width() as usize;
+    unsafe {
+        let src = &*src.as_ptr();
+        let dst = &mut *dst.as_mut_ptr();
+        for y in 0..src.height as isize {
+            let src_row = std::slice::from_raw_parts(
+                src.data[0].offset(y * src.linesize[0] as isize),
+                width * 4,
+            );
+            let dst_row = std::slice::from_raw_parts_mut(
+                dst.data[0].offset(y * dst.linesize[0] as isize),
+                width * 3,
+            );
+            for (s, d) in src_row.chunks_exact(4).zip(dst_row.chunks_exact_mut(3)) {
+                let alpha = s[3] as u32;
+                for c in 0..3 {
+                    let value = s[c] as u32 * alpha + background[c] as u32 * (255 - alpha);
+                    d[c] = ((value + 127) / 255) as u8;
+                }
+            }
+        }
+    }
+    Ok(dst)
+}
+
+/// Bytes from one pixel to the next in `plane`.
+fn plane_step(desc: &sys::AVPixFmtDescriptor, plane: usize) -> Result<usize> {
+    let unsupported = || Error::Invalid(format!("padding plane {} of {}", plane, unsafe {
+        from_c_str(desc.name).unwrap_or_default()
+    }));
+    if desc.flags & sys::AV_PIX_FMT_FLAG_BITSTREAM as u64 != 0 {
+        return Err(unsupported());
+    }
+    desc.comp[..desc.nb_components as usize]
+        .iter()
+        .filter(|x| x.plane as usize == plane)
+        .map(|x| x.step as usize)
+        .max()
+        .filter(|&x| x > 0)
+        .ok_or_else(unsupported)
+}
+
+/// Repeats the last column/row until the size is a multiple of the chroma
+/// subsampling.
+fn pad_to_chroma_alignment(image: Image) -> Result<Image> {
+    let desc = pix_fmt_desc(image.pix_fmt())?;
+    let (log2_w, log2_h) = (desc.log2_chroma_w as i32, desc.log2_chroma_h as i32);
+    let align = |x: i32, log2: i32| (x + (1 << log2) - 1) & !((1 << log2) - 1);
+    let width = align(image.width(), log2_w);
+    let height = align(image.height(), log2_h);
+    if width == image.width() && height == image.height() {
+        return Ok(image);
+    }
+    let mut output = Image::new(PictureFormat {width, height, pix_fmt: image.pix_fmt()})?;
+    // ROUNDS UP LIKE `AV_CEIL_RSHIFT`
+    let plane_size = |x: i32, shift: i32| -((-x) >> shift);
+    let is_pal = desc.flags & sys::AV_PIX_FMT_FLAG_PAL as u64 != 0;
+    for plane in 0..image.nb_planes() {
+        if is_pal && plane == 1 {
+            // THE PALETTE
+            output.plane_mut(plane).copy_from_slice(image.plane(plane));
+            continue;
+        }
+        let (shift_w, shift_h) = if plane == 1 || plane == 2 { (log2_w, log2_h) } else { (0, 0) };
+        let step = plane_step(desc, plane)?;
+        let src_row_size = plane_size(image.width(), shift_w) as usize * step;
+        let dst_row_size = plane_size(width, shift_w) as usize * step;
+        let src_h = plane_size(image.height(), shift_h) as usize;
+        let dst_h = plane_size(height, shift_h) as usize;
+        let src_stride = image.linesize(plane) as usize;
+        let dst_stride = output.linesize(plane) as usize;
+        let src = image.plane(plane);
+        let dst = output.plane_mut(plane);
+        for y in 0..dst_h {
+            let src_row = &src[y.min(src_h - 1) * src_stride..][..src_row_size];
+            let dst_row = &mut dst[y * dst_stride..][..dst_row_size];
+            dst_row[..src_row_size].copy_from_slice(src_row);
+            let last = &src_row[src_row_size - step..];
+            for pixel in dst_row[src_row_size..].chunks_exact_mut(step) {
+                pixel.copy_from_slice(last);
+            }
+        }
+    }
+    Ok(output)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// API
+///////////////////////////////////////////////////////////////////////////////
+
+/// Crops/resizes and converts `image` as described by `transform`.
+pub fn transform(image: &Image, transform: &Transform) -> Result<Image> {
+    let pix_fmt = transform.pix_fmt.unwrap_or(image.pix_fmt());
+    let (crop, width, height) = match &transform.resize {
+        Some(resize) => geometry(image.width(), image.height(), resize)?,
+        None => (None, image.width(), image.height()),
+    };
+    let mut frame = image.to_frame()?;
+    if let Some((x, y, w, h)) = crop {
+        crop_frame(&mut frame, x, y, w, h)?;
+    }
+    // SWSCALE WOULD JUST DROP THE ALPHA CHANNEL
+    let flatten = has_alpha(image.pix_fmt()) && !has_alpha(pix_fmt);
+    let work_fmt = if flatten { sys::AVPixelFormat_AV_PIX_FMT_RGBA } else { pix_fmt };
+    let frame = convert_frame(&frame, PictureFormat {width, height, pix_fmt: work_fmt}, transform.algorithm)?;
+    let frame = if flatten {
+        let flat = flatten_alpha(&frame, transform.background)?;
+        convert_frame(&flat, PictureFormat {width, height, pix_fmt}, transform.algorithm)?
+    } else {
+        frame
+    };
+    let output = Image::from_frame(&frame)?;
+    if transform.pad_to_even {
+        pad_to_chroma_alignment(output)
+    } else {
+        Ok(output)
+    }
+}
+
+/// Converts to `pix_fmt` at the same size, flattening transparency onto
+/// `background` if `pix_fmt` has no alpha.
+pub fn convert(image: &Image, pix_fmt: sys::AVPixelFormat, background: [u8; 3]) -> Result<Image> {
+    let options = Transform {
+        pix_fmt: Some(pix_fmt),
+        background,
+        pad_to_even: false,
+        ..Transform::default()
+    };
+    transform(image, &options)
+}
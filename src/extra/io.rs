@@ -0,0 +1,309 @@
+//! Custom `AVIOContext`s over Rust readers and writers, so FFmpeg can work
+//! on in-memory data without going through the file system.
+use std::cell::RefCell;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_int, c_void};
+use std::rc::Rc;
+use crate::sys;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result};
+use crate::extra::mem::FfBox;
+
+const IO_BUFFER_SIZE: usize = 32 * 1024;
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek> WriteSeek for T {}
+
+enum Handler {
+    Reader(Box<dyn Read>),
+    SeekableReader(Box<dyn ReadSeek>),
+    Writer(Box<dyn Write>),
+    SeekableWriter(Box<dyn WriteSeek>),
+}
+
+fn io_error_code(err: &std::io::Error) -> c_int {
+    unsafe {
+        match err.raw_os_error() {
+            Some(code) => defs::averror(code),
+            None => defs::averror(libc::EIO),
+        }
+    }
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let handler = &mut *(opaque as *mut Handler);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size as usize);
+    let result = match handler {
+        Handler::Reader(x) => x.read(buf),
+        Handler::SeekableReader(x) => x.read(buf),
+        _ => return defs::averror(libc::EINVAL),
+    };
+    match result {
+        Ok(0) => defs::averror_eof(),
+        Ok(n) => n as c_int,
+        Err(err) => io_error_code(&err),
+    }
+}
+
+unsafe extern "C" fn write_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let handler = &mut *(opaque as *mut Handler);
+    let buf = std::slice::from_raw_parts(buf, buf_size as usize);
+    let result = match handler {
+        Handler::Writer(x) => x.write_all(buf),
+        Handler::SeekableWriter(x) => x.write_all(buf),
+        _ => return defs::averror(libc::EINVAL),
+    };
+    match result {
+        Ok(()) => buf_size,
+        Err(err) => io_error_code(&err),
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let handler = &mut *(opaque as *mut Handler);
+    let seeker: &mut dyn Seek = match handler {
+        Handler::SeekableReader(x) => x,
+        Handler::SeekableWriter(x) => x,
+        _ => return defs::averror(libc::ENOSYS) as i64,
+    };
+    // AVSEEK_SIZE ASKS FOR THE TOTAL SIZE WITHOUT MOVING
+    if whence & sys::AVSEEK_SIZE as c_int != 0 {
+        let size = seeker.stream_position().and_then(|current| {
+            let end = seeker.seek(SeekFrom::End(0))?;
+            seeker.seek(SeekFrom::Start(current))?;
+            Ok(end)
+        });
+        return match size {
+            Ok(size) => size as i64,
+            Err(err) => io_error_code(&err) as i64,
+        };
+    }
+    let pos = match whence & !(sys::AVSEEK_FORCE as c_int) {
+        libc::SEEK_SET => SeekFrom::Start(offset as u64),
+        libc::SEEK_CUR => SeekFrom::Current(offset),
+        libc::SEEK_END => SeekFrom::End(offset),
+        _ => return defs::averror(libc::EINVAL) as i64,
+    };
+    match seeker.seek(pos) {
+        Ok(x) => x as i64,
+        Err(err) => io_error_code(&err) as i64,
+    }
+}
+
+/// An `AVIOContext` driven by a Rust reader or writer.
+pub struct IoContext {
+    ptr: *mut sys::AVIOContext,
+    handler: *mut Handler,
+}
+
+impl IoContext {
+    fn new(handler: Handler) -> Result<Self> {
+        let (write_flag, seekable) = match &handler {
+            Handler::Reader(_) => (0, false),
+            Handler::SeekableReader(_) => (0, true),
+            Handler::Writer(_) => (1, false),
+            Handler::SeekableWriter(_) => (1, true),
+        };
+        let buffer = FfBox::new_zeroed(IO_BUFFER_SIZE)?;
+        let handler = Box::into_raw(Box::new(handler));
+        unsafe {
+            // OWNED BY THE CONTEXT FROM HERE ON, WHICH MAY REALLOCATE IT
+            let (buffer, buffer_size) = buffer.into_raw_parts();
+            let ptr = sys::avio_alloc_context(
+                buffer,
+                buffer_size as c_int,
+                write_flag,
+                handler as *mut c_void,
+                if write_flag == 0 { Some(read_packet) } else { None },
+                if write_flag == 1 { Some(write_packet) } else { None },
+                if seekable { Some(seek) } else { None },
+            );
+            if ptr.is_null() {
+                drop(FfBox::from_raw_parts(buffer, buffer_size));
+                drop(Box::from_raw(handler));
+                return Err(Error::Alloc("AVIOContext"));
+            }
+            Ok(IoContext {ptr, handler})
+        }
+    }
+    /// For formats that can be read front to back (e.g. MPEG-TS, raw streams).
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Result<Self> {
+        IoContext::new(Handler::Reader(Box::new(reader)))
+    }
+    /// Required by formats like MP4 with the index at the end of the file.
+    pub fn from_seekable_reader<R: Read + Seek + 'static>(reader: R) -> Result<Self> {
+        IoContext::new(Handler::SeekableReader(Box::new(reader)))
+    }
+    /// For streamable outputs, e.g. MPEG-TS or fragmented MP4.
+    pub fn from_writer<W: Write + 'static>(writer: W) -> Result<Self> {
+        IoContext::new(Handler::Writer(Box::new(writer)))
+    }
+    /// Required by muxers that go back to patch sizes (e.g. regular MP4).
+    pub fn from_seekable_writer<W: Write + Seek + 'static>(writer: W) -> Result<Self> {
+        IoContext::new(Handler::SeekableWriter(Box::new(writer)))
+    }
+    /// Flushes buffered output to the writer.
+    pub fn flush(&mut self) {
+        unsafe {
+            sys::avio_flush(self.ptr);
+        };
+    }
+    pub fn as_ptr(&self) -> *const sys::AVIOContext {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVIOContext {
+        self.ptr
+    }
+    /// Releases ownership, e.g. to hand the context to an `io_open` caller.
+    /// Must be reclaimed with `from_raw`.
+    pub(crate) fn into_raw(self) -> *mut sys::AVIOContext {
+        let ptr = self.ptr;
+        std::mem::forget(self);
+        ptr
+    }
+    /// Reclaims a context created by `into_raw`.
+    pub(crate) unsafe fn from_raw(ptr: *mut sys::AVIOContext) -> Self {
+        IoContext {ptr, handler: (*ptr).opaque as *mut Handler}
+    }
+}
+
+impl Drop for IoContext {
+    fn drop(&mut self) {
+        unsafe {
+            if (*self.ptr).write_flag != 0 {
+                sys::avio_flush(self.ptr);
+            }
+            // THE INTERNAL BUFFER COULD HAVE CHANGED, AND BE != THE ONE WE ALLOCATED
+            sys::av_freep(&mut (*self.ptr).buffer as *mut *mut u8 as *mut c_void);
+            sys::avio_context_free(&mut self.ptr);
+            drop(Box::from_raw(self.handler));
+        };
+    }
+}
+
+/// A growable in-memory file, shared between handles with independent
+/// positions; e.g. a muxer writing to it while reading it back.
+#[derive(Clone, Default)]
+pub struct MemoryFile {
+    data: Rc<RefCell<Vec<u8>>>,
+    pos: u64,
+}
+
+impl MemoryFile {
+    pub fn new() -> Self {
+        MemoryFile::default()
+    }
+    /// Another handle to the same data, positioned at the start.
+    pub fn reopen(&self) -> Self {
+        MemoryFile {data: self.data.clone(), pos: 0}
+    }
+    pub fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Takes the contents, leaving the file empty.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::replace(&mut *self.data.borrow_mut(), Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for MemoryFile {
+    /// Wraps `data` without copying it, e.g. to demux it.
+    fn from(data: Vec<u8>) -> Self {
+        MemoryFile {data: Rc::new(RefCell::new(data)), pos: 0}
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.data.borrow();
+        let start = (self.pos as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut data = self.data.borrow_mut();
+        let start = self.pos as usize;
+        if data.len() < start {
+            data.resize(start, 0);
+        }
+        let overlap = buf.len().min(data.len() - start);
+        data[start..start + overlap].copy_from_slice(&buf[..overlap]);
+        data.extend_from_slice(&buf[overlap..]);
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.len() as i64;
+        let next = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::Current(x) => self.pos as i64 + x,
+            SeekFrom::End(x) => len + x,
+        };
+        if next < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = next as u64;
+        Ok(self.pos)
+    }
+}
+
+/// Reads a borrowed slice without copying it.
+///
+/// The lifetime is erased so it fits `IoContext`; only used internally
+/// where the context is dropped before the borrow ends.
+pub(crate) struct SliceReader {
+    ptr: *const u8,
+    len: usize,
+    pos: u64,
+}
+
+impl SliceReader {
+    pub(crate) unsafe fn new(data: &[u8]) -> Self {
+        SliceReader {ptr: data.as_ptr(), len: data.len(), pos: 0}
+    }
+    fn data(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Read for SliceReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = (self.pos as usize).min(self.len);
+        let len = buf.len().min(self.len - start);
+        buf[..len].copy_from_slice(&self.data()[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for SliceReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let next = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::Current(x) => self.pos as i64 + x,
+            SeekFrom::End(x) => self.len as i64 + x,
+        };
+        if next < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = next as u64;
+        Ok(self.pos)
+    }
+}
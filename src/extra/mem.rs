@@ -0,0 +1,83 @@
+//! Ownership of memory that FFmpeg allocates or frees.
+use std::ops::{Deref, DerefMut};
+use std::os::raw::c_void;
+use std::ptr::NonNull;
+use crate::sys;
+use crate::extra::error::{Error, Result};
+
+/// A `Box` whose memory comes from `av_malloc` and goes back through
+/// `av_free`, e.g. AVIO buffers or `avio_close_dyn_buf` output.
+///
+/// Use `into_raw` to hand the allocation to an FFmpeg API that takes
+/// ownership (and may `av_realloc` it).
+pub struct FfBox<T: ?Sized> {
+    ptr: NonNull<T>,
+}
+
+impl FfBox<[u8]> {
+    /// A zeroed buffer of `len` bytes.
+    pub fn new_zeroed(len: usize) -> Result<Self> {
+        unsafe {
+            let ptr = sys::av_mallocz(len.max(1) as _) as *mut u8;
+            if ptr.is_null() {
+                return Err(Error::Alloc("av_mallocz"));
+            }
+            Ok(FfBox::from_raw_parts(ptr, len))
+        }
+    }
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let mut output = FfBox::new_zeroed(data.len())?;
+        output.copy_from_slice(data);
+        Ok(output)
+    }
+    /// Takes ownership of `len` bytes allocated by `av_malloc` & co.
+    pub unsafe fn from_raw_parts(ptr: *mut u8, len: usize) -> Self {
+        let slice = std::ptr::slice_from_raw_parts_mut(ptr, len);
+        FfBox {ptr: NonNull::new(slice).expect("null FfBox")}
+    }
+    /// Gives up ownership; the caller (or FFmpeg) must `av_free` it.
+    pub fn into_raw_parts(self) -> (*mut u8, usize) {
+        let len = self.len();
+        let ptr = self.ptr.as_ptr() as *mut u8;
+        std::mem::forget(self);
+        (ptr, len)
+    }
+}
+
+impl<T> FfBox<T> {
+    /// Takes ownership of a single value allocated by `av_malloc` & co.
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        FfBox {ptr: NonNull::new(ptr).expect("null FfBox")}
+    }
+    /// Gives up ownership; the caller (or FFmpeg) must `av_free` it.
+    pub fn into_raw(self) -> *mut T {
+        let ptr = self.ptr.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+}
+
+impl<T: ?Sized> Deref for FfBox<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for FfBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized> Drop for FfBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            std::ptr::drop_in_place(self.ptr.as_ptr());
+            sys::av_free(self.ptr.as_ptr() as *mut u8 as *mut c_void);
+        };
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for FfBox<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for FfBox<T> {}
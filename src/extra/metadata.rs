@@ -0,0 +1,442 @@
+//! Container, stream and chapter metadata, dispositions, cover art and
+//! attachments, edited while stream copying (`RemuxOptions::edit`), plus
+//! reading and writing ffmetadata files.
+//!
+//! ```ignore
+//! let edit = MetadataEdit {
+//!     global: vec![(String::from("title"), String::from("Holiday"))],
+//!     cover_art: vec![CoverArt::new(std::fs::read("cover.jpg")?)],
+//!     ..MetadataEdit::default()
+//! };
+//! remux("in.mp3", "out.mp3", &RemuxOptions {edit, ..RemuxOptions::default()})?;
+//! ```
+use std::os::raw::{c_int, c_void};
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::codec::CodecParameters;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::format::{InputContext, OutputContext};
+use crate::extra::image::{ImageHint, decode_image};
+use crate::extra::io::{IoContext, SliceReader};
+use crate::extra::packet::Packet;
+use crate::extra::probe::Disposition;
+use crate::extra::remux::StreamSelector;
+use crate::extra::util::{c_str, from_av_dict};
+
+/// Times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    /// Usually just `title`.
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Chapter {
+    pub fn new(start: f64, end: f64, title: &str) -> Self {
+        Chapter {start, end, metadata: vec![(String::from("title"), String::from(title))]}
+    }
+    pub fn title(&self) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(key, _)| key == "title")
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A picture stored as an `AV_DISPOSITION_ATTACHED_PIC` stream, e.g. an
+/// album cover in MP3, FLAC or MP4. Matroska has no attached pictures and
+/// stores it as a `cover.jpg` or `cover.png` attachment instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverArt {
+    /// JPEG or PNG.
+    pub data: Vec<u8>,
+    /// E.g. `comment` = `Cover (front)`.
+    pub metadata: Vec<(String, String)>,
+}
+
+impl CoverArt {
+    pub fn new(data: Vec<u8>) -> Self {
+        CoverArt {
+            data,
+            metadata: vec![(String::from("comment"), String::from("Cover (front)"))],
+        }
+    }
+    /// From the data's signature; `None` unless JPEG or PNG.
+    pub fn codec_id(&self) -> Option<sys::AVCodecID> {
+        if self.data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(sys::AVCodecID_AV_CODEC_ID_MJPEG)
+        } else if self.data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(sys::AVCodecID_AV_CODEC_ID_PNG)
+        } else {
+            None
+        }
+    }
+    /// The attachment file name and MIME type Matroska players look for.
+    fn matroska_attachment(codec_id: sys::AVCodecID) -> (&'static str, &'static str) {
+        if codec_id == sys::AVCodecID_AV_CODEC_ID_PNG {
+            ("cover.png", "image/png")
+        } else {
+            ("cover.jpg", "image/jpeg")
+        }
+    }
+}
+
+/// A Matroska attachment, e.g. a font for ASS subtitles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub filename: String,
+    /// E.g. `application/x-truetype-font`.
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Changes to one (or more) of the copied input streams.
+#[derive(Debug, Clone)]
+pub struct StreamEdit {
+    pub selector: StreamSelector,
+    /// Set after copying; an empty value removes the key.
+    pub metadata: Vec<(String, String)>,
+    /// Replaces the stream's disposition.
+    pub disposition: Option<Disposition>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetadataEdit {
+    /// Container metadata set after copying; an empty value removes the
+    /// key.
+    pub global: Vec<(String, String)>,
+    pub streams: Vec<StreamEdit>,
+    /// Replaces the chapters; `None` copies the input's, cut to the
+    /// remuxed range.
+    pub chapters: Option<Vec<Chapter>>,
+    /// Leave out the input's attached pictures.
+    pub remove_cover_art: bool,
+    pub cover_art: Vec<CoverArt>,
+    /// Leave out the input's attachment streams.
+    pub remove_attachments: bool,
+    /// Matroska only.
+    pub attachments: Vec<Attachment>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+const CHAPTER_TIME_BASE: sys::AVRational = sys::AVRational {num: 1, den: 1000};
+
+/// Sets (or, for empty values, deletes) entries of `dict`.
+pub(crate) unsafe fn set_metadata(dict: *mut *mut sys::AVDictionary, pairs: &[(String, String)]) -> Result<()> {
+    for (key, value) in pairs {
+        let key = c_str(key)?;
+        let value = c_str(value)?;
+        // A NULL VALUE DELETES THE ENTRY
+        let value_ptr = if value.as_bytes().is_empty() { std::ptr::null() } else { value.as_ptr() };
+        check(sys::av_dict_set(dict, key.as_ptr(), value_ptr, 0))?;
+    }
+    Ok(())
+}
+
+unsafe fn chapters_of(ctx: *const sys::AVFormatContext) -> Vec<Chapter> {
+    let ctx = &*ctx;
+    (0..ctx.nb_chapters as usize)
+        .map(|i| {
+            let chapter = &**ctx.chapters.add(i);
+            let seconds = |ts: i64| ts as f64 * chapter.time_base.num as f64 / chapter.time_base.den as f64;
+            Chapter {
+                start: seconds(chapter.start),
+                end: seconds(chapter.end),
+                metadata: from_av_dict(chapter.metadata),
+            }
+        })
+        .collect()
+}
+
+/// Appends chapters the way `avpriv_new_chapter` does; they're freed
+/// with the context.
+unsafe fn add_chapters(ctx: *mut sys::AVFormatContext, chapters: &[Chapter]) -> Result<()> {
+    for chapter in chapters {
+        let ptr = sys::av_mallocz(std::mem::size_of::<sys::AVChapter>()) as *mut sys::AVChapter;
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVChapter"));
+        }
+        let to_ts = |seconds: f64| (seconds * CHAPTER_TIME_BASE.den as f64).round() as i64;
+        (*ptr).id = (*ctx).nb_chapters as _;
+        (*ptr).time_base = CHAPTER_TIME_BASE;
+        (*ptr).start = to_ts(chapter.start);
+        (*ptr).end = to_ts(chapter.end);
+        if let Err(err) = set_metadata(&mut (*ptr).metadata, &chapter.metadata) {
+            sys::av_dict_free(&mut (*ptr).metadata);
+            sys::av_free(ptr as *mut c_void);
+            return Err(err);
+        }
+        let added = check(sys::av_dynarray_add_nofree(
+            &mut (*ctx).chapters as *mut _ as *mut c_void,
+            &mut (*ctx).nb_chapters as *mut u32 as *mut c_int,
+            ptr as *mut c_void,
+        ));
+        if let Err(err) = added {
+            sys::av_dict_free(&mut (*ptr).metadata);
+            sys::av_free(ptr as *mut c_void);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// A padded, `av_malloc`ed copy, e.g. for `extradata`.
+unsafe fn padded_copy(data: &[u8]) -> Result<*mut u8> {
+    let ptr = sys::av_mallocz(data.len() + sys::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+    if ptr.is_null() {
+        return Err(Error::Alloc("buffer"));
+    }
+    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+    Ok(ptr)
+}
+
+/// Adds an attachment stream holding `data`, tagged with `tags` (which
+/// should include `filename` and `mimetype`).
+fn add_attachment(output: &mut OutputContext, data: &[u8], tags: &[(String, String)]) -> Result<()> {
+    let mut par = CodecParameters::new()?;
+    unsafe {
+        let raw = &mut *par.as_mut_ptr();
+        raw.codec_type = sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT;
+        raw.extradata = padded_copy(data)?;
+        raw.extradata_size = data.len() as c_int;
+    }
+    let index = output.add_stream(&par, sys::AVRational {num: 1, den: 1000})?;
+    unsafe { set_metadata(&mut (*output.stream_ptr(index)).metadata, tags) }
+}
+
+fn disposition_of(input: &InputContext, index: usize) -> c_int {
+    unsafe { (*input.stream_ptr(index)).disposition }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EDITING
+///////////////////////////////////////////////////////////////////////////////
+
+impl MetadataEdit {
+    /// Whether input stream `index` survives the removals.
+    pub(crate) fn keeps(&self, input: &InputContext, index: usize) -> bool {
+        let is_cover = disposition_of(input, index) & sys::AV_DISPOSITION_ATTACHED_PIC as c_int != 0;
+        let codec_type = unsafe { (*(*input.stream_ptr(index)).codecpar).codec_type };
+        let is_attachment = MediaType::from_raw(codec_type) == MediaType::Attachment;
+        !(is_cover && self.remove_cover_art) && !(is_attachment && self.remove_attachments)
+    }
+    /// Applies the stream edits matching input stream `index` to output
+    /// stream `out_index`.
+    pub(crate) fn apply_to_stream(
+        &self,
+        input: &InputContext,
+        index: usize,
+        output: &mut OutputContext,
+        out_index: usize,
+    ) -> Result<()> {
+        for edit in self.streams.iter().filter(|x| x.selector.matches(input, index)) {
+            unsafe {
+                let stream = output.stream_ptr(out_index);
+                set_metadata(&mut (*stream).metadata, &edit.metadata)?;
+                if let Some(disposition) = edit.disposition {
+                    (*stream).disposition = disposition.to_flags();
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Sets the global metadata and chapters; `input_chapters` are what
+    /// `None` chapters keep.
+    pub(crate) fn apply_to_output(&self, output: &mut OutputContext, input_chapters: &[Chapter]) -> Result<()> {
+        unsafe {
+            set_metadata(&mut (*output.as_mut_ptr()).metadata, &self.global)?;
+            add_chapters(output.as_mut_ptr(), self.chapters.as_ref().map(|x| x.as_slice()).unwrap_or(input_chapters))
+        }
+    }
+    /// Adds the new cover art and attachment streams; returns the cover art
+    /// packets to write once the header is out.
+    pub(crate) fn add_streams(&self, output: &mut OutputContext) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        let is_matroska = output.format_name() == "matroska";
+        for picture in &self.cover_art {
+            let codec_id = picture.codec_id()
+                .ok_or_else(|| Error::Invalid(String::from("cover art is neither JPEG nor PNG")))?;
+            if is_matroska {
+                let (filename, mime_type) = CoverArt::matroska_attachment(codec_id);
+                let mut tags = picture.metadata.clone();
+                tags.push((String::from("filename"), String::from(filename)));
+                tags.push((String::from("mimetype"), String::from(mime_type)));
+                add_attachment(output, &picture.data, &tags)?;
+                continue;
+            }
+            // E.G. FLAC WRITES THE SIZE INTO ITS PICTURE BLOCK
+            let image = decode_image(&picture.data, &ImageHint::Auto)?;
+            let mut par = CodecParameters::new()?;
+            unsafe {
+                let raw = &mut *par.as_mut_ptr();
+                raw.codec_type = sys::AVMediaType_AVMEDIA_TYPE_VIDEO;
+                raw.codec_id = codec_id;
+                raw.width = image.width();
+                raw.height = image.height();
+            }
+            let index = output.add_stream(&par, sys::AVRational {num: 1, den: 90000})?;
+            unsafe {
+                let stream = output.stream_ptr(index);
+                (*stream).disposition = sys::AV_DISPOSITION_ATTACHED_PIC as c_int;
+                set_metadata(&mut (*stream).metadata, &picture.metadata)?;
+            }
+            let mut pkt = Packet::from_data(&picture.data)?;
+            pkt.set_stream_index(index);
+            pkt.set_pts(0);
+            pkt.set_dts(0);
+            unsafe {
+                (*pkt.as_mut_ptr()).flags |= sys::AV_PKT_FLAG_KEY as c_int;
+            }
+            packets.push(pkt);
+        }
+        for attachment in &self.attachments {
+            let tags = [
+                (String::from("filename"), attachment.filename.clone()),
+                (String::from("mimetype"), attachment.mime_type.clone()),
+            ];
+            add_attachment(output, &attachment.data, &tags)?;
+        }
+        Ok(packets)
+    }
+}
+
+/// The input's chapters cut to `start..end` (seconds from the start of the
+/// input) and moved by `shift` seconds.
+pub(crate) fn remuxed_chapters(input: &InputContext, start: f64, end: Option<f64>, shift: f64) -> Vec<Chapter> {
+    let input_start = input.start_time() as f64 / sys::AV_TIME_BASE as f64;
+    let clip = |t: f64| {
+        let t = (t - input_start).max(start);
+        end.map_or(t, |end| t.min(end))
+    };
+    let mut chapters = unsafe { chapters_of(input.as_ptr()) };
+    for chapter in &mut chapters {
+        chapter.start = clip(chapter.start);
+        chapter.end = clip(chapter.end);
+    }
+    chapters.retain(|x| x.end > x.start);
+    for chapter in &mut chapters {
+        chapter.start += shift;
+        chapter.end += shift;
+    }
+    chapters
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EXTRACTION
+///////////////////////////////////////////////////////////////////////////////
+
+/// The attached pictures of `input`, Matroska image attachments included.
+pub fn cover_art(input: &InputContext) -> Vec<CoverArt> {
+    (0..input.nb_streams())
+        .filter(|&index| disposition_of(input, index) & sys::AV_DISPOSITION_ATTACHED_PIC as c_int != 0)
+        .map(|index| unsafe {
+            let stream = &*input.stream_ptr(index);
+            let pkt = &stream.attached_pic;
+            let data = if pkt.data.is_null() || pkt.size <= 0 {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(pkt.data, pkt.size as usize).to_vec()
+            };
+            CoverArt {data, metadata: from_av_dict(stream.metadata)}
+        })
+        .filter(|x| !x.data.is_empty())
+        .collect()
+}
+
+/// The attachment streams of `input`, e.g. fonts in Matroska.
+pub fn attachments(input: &InputContext) -> Vec<Attachment> {
+    (0..input.nb_streams())
+        .filter_map(|index| unsafe {
+            let stream = &*input.stream_ptr(index);
+            let par = &*stream.codecpar;
+            if MediaType::from_raw(par.codec_type) != MediaType::Attachment {
+                return None;
+            }
+            let data = if par.extradata.is_null() || par.extradata_size <= 0 {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(par.extradata, par.extradata_size as usize).to_vec()
+            };
+            let tags = from_av_dict(stream.metadata);
+            let tag = |name: &str| {
+                tags.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_default()
+            };
+            Some(Attachment {filename: tag("filename"), mime_type: tag("mimetype"), data})
+        })
+        .collect()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// FFMETADATA
+///////////////////////////////////////////////////////////////////////////////
+
+/// The contents of an ffmetadata file (`;FFMETADATA1`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FfMetadata {
+    pub global: Vec<(String, String)>,
+    /// From `[STREAM]` sections, in order; not written by `to_text`, since
+    /// the muxer doesn't.
+    pub streams: Vec<Vec<(String, String)>>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl FfMetadata {
+    pub fn parse(text: &str) -> Result<Self> {
+        // THE IO CONTEXT NEVER OUTLIVES THIS CALL
+        let reader = unsafe { SliceReader::new(text.as_bytes()) };
+        let input = InputContext::open_io(IoContext::from_seekable_reader(reader)?, Some("ffmetadata"), &[])?;
+        Ok(FfMetadata::of_input(&input))
+    }
+    pub fn of_input(input: &InputContext) -> Self {
+        unsafe {
+            FfMetadata {
+                global: from_av_dict((*input.as_ptr()).metadata),
+                streams: (0..input.nb_streams())
+                    .map(|index| from_av_dict((*input.stream_ptr(index)).metadata))
+                    .collect(),
+                chapters: chapters_of(input.as_ptr()),
+            }
+        }
+    }
+    pub fn to_text(&self) -> Result<String> {
+        let (mut output, file) = OutputContext::in_memory("ffmetadata")?;
+        unsafe {
+            set_metadata(&mut (*output.as_mut_ptr()).metadata, &self.global)?;
+            add_chapters(output.as_mut_ptr(), &self.chapters)?;
+        }
+        output.write_header(&[])?;
+        output.write_trailer()?;
+        drop(output);
+        String::from_utf8(file.take()).map_err(|_| Error::Invalid(String::from("ffmetadata is not UTF-8")))
+    }
+    /// Replaces the global metadata (keys missing here are removed) and
+    /// chapters, and sets the metadata of the streams by index.
+    pub fn to_edit(&self, input: &InputContext) -> MetadataEdit {
+        let mut global = unsafe { from_av_dict((*input.as_ptr()).metadata) }
+            .into_iter()
+            .map(|(key, _)| (key, String::new()))
+            .collect::<Vec<_>>();
+        global.extend(self.global.iter().cloned());
+        MetadataEdit {
+            global,
+            streams: self.streams
+                .iter()
+                .enumerate()
+                .map(|(index, metadata)| StreamEdit {
+                    selector: StreamSelector::Index(index),
+                    metadata: metadata.clone(),
+                    disposition: None,
+                })
+                .collect(),
+            chapters: Some(self.chapters.clone()),
+            ..MetadataEdit::default()
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Typed access to `AVOption`s (see `libavutil/opt.h`).
+//!
+//! Every wrapper around an `AVClass`-bearing struct implements `Options`,
+//! which also reaches the private options of the codec, muxer, filter etc.
+//! behind it (`AV_OPT_SEARCH_CHILDREN`).
+use std::os::raw::{c_char, c_int, c_void};
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::util::{c_str, from_c_str};
+use crate::extra::codec::CodecContext;
+use crate::extra::format::{InputContext, OutputContext};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    Str(String),
+    Int(i64),
+    Double(f64),
+    Rational(i32, i32),
+    PixelFormat(sys::AVPixelFormat),
+    SampleFormat(sys::AVSampleFormat),
+    ImageSize(i32, i32),
+    ChannelLayout(u64),
+}
+
+impl From<&str> for OptionValue {
+    fn from(x: &str) -> Self {
+        OptionValue::Str(String::from(x))
+    }
+}
+impl From<String> for OptionValue {
+    fn from(x: String) -> Self {
+        OptionValue::Str(x)
+    }
+}
+impl From<i64> for OptionValue {
+    fn from(x: i64) -> Self {
+        OptionValue::Int(x)
+    }
+}
+impl From<i32> for OptionValue {
+    fn from(x: i32) -> Self {
+        OptionValue::Int(x as i64)
+    }
+}
+impl From<bool> for OptionValue {
+    fn from(x: bool) -> Self {
+        OptionValue::Int(x as i64)
+    }
+}
+impl From<f64> for OptionValue {
+    fn from(x: f64) -> Self {
+        OptionValue::Double(x)
+    }
+}
+impl From<sys::AVRational> for OptionValue {
+    fn from(x: sys::AVRational) -> Self {
+        OptionValue::Rational(x.num, x.den)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    Flags,
+    Int,
+    Int64,
+    UInt64,
+    Double,
+    Float,
+    String,
+    Rational,
+    Binary,
+    Dict,
+    Const,
+    ImageSize,
+    PixelFormat,
+    SampleFormat,
+    VideoRate,
+    Duration,
+    Color,
+    ChannelLayout,
+    Bool,
+    Unknown(sys::AVOptionType),
+}
+
+impl OptionType {
+    pub fn from_raw(x: sys::AVOptionType) -> Self {
+        match x {
+            sys::AVOptionType_AV_OPT_TYPE_FLAGS => OptionType::Flags,
+            sys::AVOptionType_AV_OPT_TYPE_INT => OptionType::Int,
+            sys::AVOptionType_AV_OPT_TYPE_INT64 => OptionType::Int64,
+            sys::AVOptionType_AV_OPT_TYPE_UINT64 => OptionType::UInt64,
+            sys::AVOptionType_AV_OPT_TYPE_DOUBLE => OptionType::Double,
+            sys::AVOptionType_AV_OPT_TYPE_FLOAT => OptionType::Float,
+            sys::AVOptionType_AV_OPT_TYPE_STRING => OptionType::String,
+            sys::AVOptionType_AV_OPT_TYPE_RATIONAL => OptionType::Rational,
+            sys::AVOptionType_AV_OPT_TYPE_BINARY => OptionType::Binary,
+            sys::AVOptionType_AV_OPT_TYPE_DICT => OptionType::Dict,
+            sys::AVOptionType_AV_OPT_TYPE_CONST => OptionType::Const,
+            sys::AVOptionType_AV_OPT_TYPE_IMAGE_SIZE => OptionType::ImageSize,
+            sys::AVOptionType_AV_OPT_TYPE_PIXEL_FMT => OptionType::PixelFormat,
+            sys::AVOptionType_AV_OPT_TYPE_SAMPLE_FMT => OptionType::SampleFormat,
+            sys::AVOptionType_AV_OPT_TYPE_VIDEO_RATE => OptionType::VideoRate,
+            sys::AVOptionType_AV_OPT_TYPE_DURATION => OptionType::Duration,
+            sys::AVOptionType_AV_OPT_TYPE_COLOR => OptionType::Color,
+            sys::AVOptionType_AV_OPT_TYPE_CHANNEL_LAYOUT => OptionType::ChannelLayout,
+            sys::AVOptionType_AV_OPT_TYPE_BOOL => OptionType::Bool,
+            x => OptionType::Unknown(x),
+        }
+    }
+}
+
+/// A named value of an enum-like option (an `AV_OPT_TYPE_CONST` entry
+/// sharing the option's unit), e.g. `preset=veryfast`.
+#[derive(Debug, Clone)]
+pub struct OptionConstant {
+    pub name: String,
+    pub help: Option<String>,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionInfo {
+    pub name: String,
+    pub help: Option<String>,
+    pub kind: OptionType,
+    pub default: Option<OptionValue>,
+    pub min: f64,
+    pub max: f64,
+    /// `AV_OPT_FLAG_*` bits.
+    pub flags: i32,
+    pub unit: Option<String>,
+    pub constants: Vec<OptionConstant>,
+}
+
+unsafe fn default_value(opt: &sys::AVOption) -> Option<OptionValue> {
+    let default = &opt.default_val;
+    match OptionType::from_raw(opt.type_) {
+        OptionType::Flags |
+        OptionType::Int |
+        OptionType::Int64 |
+        OptionType::UInt64 |
+        OptionType::Duration |
+        OptionType::Bool |
+        OptionType::Const => Some(OptionValue::Int(default.i64)),
+        OptionType::PixelFormat => Some(OptionValue::PixelFormat(default.i64 as _)),
+        OptionType::SampleFormat => Some(OptionValue::SampleFormat(default.i64 as _)),
+        OptionType::ChannelLayout => Some(OptionValue::ChannelLayout(default.i64 as u64)),
+        OptionType::Double | OptionType::Float => Some(OptionValue::Double(default.dbl)),
+        OptionType::Rational => {
+            let q = sys::av_d2q(default.dbl, c_int::max_value());
+            Some(OptionValue::Rational(q.num, q.den))
+        }
+        OptionType::String |
+        OptionType::ImageSize |
+        OptionType::VideoRate |
+        OptionType::Color |
+        OptionType::Binary |
+        OptionType::Dict => from_c_str(default.str).map(OptionValue::Str),
+        OptionType::Unknown(_) => None,
+    }
+}
+
+unsafe fn collect_options(obj: *mut c_void, output: &mut Vec<OptionInfo>) {
+    let mut all: Vec<*const sys::AVOption> = Vec::new();
+    let mut prev: *const sys::AVOption = std::ptr::null();
+    loop {
+        prev = sys::av_opt_next(obj, prev);
+        if prev.is_null() {
+            break;
+        }
+        all.push(prev);
+    }
+    let constants_for = |unit: *const c_char| -> Vec<OptionConstant> {
+        if unit.is_null() {
+            return Vec::new();
+        }
+        let unit = std::ffi::CStr::from_ptr(unit);
+        all
+            .iter()
+            .map(|x| &**x)
+            .filter(|x| OptionType::from_raw(x.type_) == OptionType::Const)
+            .filter(|x| !x.unit.is_null() && std::ffi::CStr::from_ptr(x.unit) == unit)
+            .map(|x| OptionConstant {
+                name: from_c_str(x.name).unwrap_or_default(),
+                help: from_c_str(x.help),
+                value: x.default_val.i64,
+            })
+            .collect()
+    };
+    for opt in all.iter().map(|x| &**x) {
+        let kind = OptionType::from_raw(opt.type_);
+        if kind == OptionType::Const {
+            continue;
+        }
+        output.push(OptionInfo {
+            name: from_c_str(opt.name).unwrap_or_default(),
+            help: from_c_str(opt.help),
+            kind,
+            default: default_value(opt),
+            min: opt.min,
+            max: opt.max,
+            flags: opt.flags,
+            unit: from_c_str(opt.unit),
+            constants: constants_for(opt.unit),
+        });
+    }
+}
+
+/// The object behind `x`, unless it hasn't been set up yet.
+fn object_of<T: Options + ?Sized>(x: &T) -> Result<*mut c_void> {
+    let obj = x.as_av_class_ptr();
+    if obj.is_null() {
+        return Err(Error::Invalid(String::from("options of an object that isn't set up yet")));
+    }
+    Ok(obj)
+}
+
+/// # Safety
+///
+/// `as_av_class_ptr` must return null or a valid pointer to a struct whose
+/// first member is its `AVClass` pointer, alive as long as `self`.
+pub unsafe trait Options {
+    /// Pointer to the struct whose first member is its `AVClass` pointer;
+    /// null if there's none yet.
+    fn as_av_class_ptr(&self) -> *mut c_void;
+
+    fn set_option<V: Into<OptionValue>>(&mut self, name: &str, value: V) -> Result<()> {
+        let obj = object_of(self)?;
+        let name_cstr = c_str(name)?;
+        let name = name_cstr.as_ptr();
+        let flags = sys::AV_OPT_SEARCH_CHILDREN as c_int;
+        let status = unsafe {
+            match value.into() {
+                OptionValue::Str(x) => {
+                    let x = c_str(&x)?;
+                    sys::av_opt_set(obj, name, x.as_ptr(), flags)
+                }
+                OptionValue::Int(x) => sys::av_opt_set_int(obj, name, x, flags),
+                OptionValue::Double(x) => sys::av_opt_set_double(obj, name, x, flags),
+                OptionValue::Rational(num, den) => {
+                    sys::av_opt_set_q(obj, name, sys::AVRational {num, den}, flags)
+                }
+                OptionValue::PixelFormat(x) => sys::av_opt_set_pixel_fmt(obj, name, x, flags),
+                OptionValue::SampleFormat(x) => sys::av_opt_set_sample_fmt(obj, name, x, flags),
+                OptionValue::ImageSize(w, h) => sys::av_opt_set_image_size(obj, name, w, h, flags),
+                OptionValue::ChannelLayout(x) => {
+                    sys::av_opt_set_channel_layout(obj, name, x as i64, flags)
+                }
+            }
+        };
+        if status == unsafe { crate::extra::defs::averror_option_not_found() } {
+            return Err(Error::NotFound(format!("option {:?}", name_cstr)));
+        }
+        check(status)?;
+        Ok(())
+    }
+
+    /// Reads an option back, typed after its `AVOptionType`. Types without
+    /// a dedicated variant come back as their string form.
+    fn get_option(&self, name: &str) -> Result<OptionValue> {
+        let obj = object_of(self)?;
+        let name_cstr = c_str(name)?;
+        let name = name_cstr.as_ptr();
+        let flags = sys::AV_OPT_SEARCH_CHILDREN as c_int;
+        unsafe {
+            let opt = sys::av_opt_find(obj, name, std::ptr::null(), 0, flags);
+            if opt.is_null() {
+                return Err(Error::NotFound(format!("option {:?}", name_cstr)));
+            }
+            match OptionType::from_raw((*opt).type_) {
+                OptionType::Flags |
+                OptionType::Int |
+                OptionType::Int64 |
+                OptionType::UInt64 |
+                OptionType::Duration |
+                OptionType::Bool => {
+                    let mut x: i64 = 0;
+                    check(sys::av_opt_get_int(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::Int(x))
+                }
+                OptionType::Double | OptionType::Float => {
+                    let mut x: f64 = 0.0;
+                    check(sys::av_opt_get_double(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::Double(x))
+                }
+                OptionType::Rational | OptionType::VideoRate => {
+                    let mut x = sys::AVRational {num: 0, den: 1};
+                    check(sys::av_opt_get_q(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::Rational(x.num, x.den))
+                }
+                OptionType::PixelFormat => {
+                    let mut x: sys::AVPixelFormat = std::mem::zeroed();
+                    check(sys::av_opt_get_pixel_fmt(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::PixelFormat(x))
+                }
+                OptionType::SampleFormat => {
+                    let mut x: sys::AVSampleFormat = std::mem::zeroed();
+                    check(sys::av_opt_get_sample_fmt(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::SampleFormat(x))
+                }
+                OptionType::ImageSize => {
+                    let (mut w, mut h) = (0, 0);
+                    check(sys::av_opt_get_image_size(obj, name, flags, &mut w, &mut h))?;
+                    Ok(OptionValue::ImageSize(w, h))
+                }
+                OptionType::ChannelLayout => {
+                    let mut x: i64 = 0;
+                    check(sys::av_opt_get_channel_layout(obj, name, flags, &mut x))?;
+                    Ok(OptionValue::ChannelLayout(x as u64))
+                }
+                _ => {
+                    let mut out: *mut u8 = std::ptr::null_mut();
+                    check(sys::av_opt_get(obj, name, flags, &mut out))?;
+                    let value = from_c_str(out as *const c_char).unwrap_or_default();
+                    sys::av_free(out as *mut c_void);
+                    Ok(OptionValue::Str(value))
+                }
+            }
+        }
+    }
+
+    /// Every option of the object and of its direct children (e.g. the
+    /// codec's private options), without the `AV_OPT_TYPE_CONST` entries,
+    /// which are folded into `OptionInfo::constants` instead.
+    fn list_options(&self) -> Result<Vec<OptionInfo>> {
+        let obj = object_of(self)?;
+        let mut output = Vec::new();
+        unsafe {
+            collect_options(obj, &mut output);
+            let mut child: *mut c_void = std::ptr::null_mut();
+            loop {
+                child = sys::av_opt_child_next(obj, child);
+                if child.is_null() {
+                    break;
+                }
+                collect_options(child, &mut output);
+            }
+        }
+        Ok(output)
+    }
+}
+
+unsafe impl Options for CodecContext {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.as_ptr() as *mut c_void
+    }
+}
+
+unsafe impl Options for InputContext {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.as_ptr() as *mut c_void
+    }
+}
+
+unsafe impl Options for OutputContext {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.as_ptr() as *mut c_void
+    }
+}
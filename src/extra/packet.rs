@@ -0,0 +1,182 @@
+//! Owned, reference counted `AVPacket`.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::side_data::{PacketSideDataType, SideData};
+use crate::extra::util::raw_bytes;
+
+pub struct Packet {
+    ptr: *mut sys::AVPacket,
+}
+
+impl Packet {
+    /// An empty packet, e.g. for `av_read_frame` to fill.
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { sys::av_packet_alloc() };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVPacket"));
+        }
+        Ok(Packet {ptr})
+    }
+    /// A packet holding a (padded) copy of `data`.
+    pub fn from_data(data: &[u8]) -> Result<Self> {
+        let packet = Packet::new()?;
+        unsafe {
+            check(sys::av_new_packet(packet.ptr, data.len() as c_int))?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), (*packet.ptr).data, data.len());
+        }
+        Ok(packet)
+    }
+    /// Takes over the references held by `pkt`, leaving it blank.
+    pub unsafe fn take_from(pkt: *mut sys::AVPacket) -> Result<Self> {
+        let packet = Packet::new()?;
+        sys::av_packet_move_ref(packet.ptr, pkt);
+        Ok(packet)
+    }
+    pub fn data(&self) -> &[u8] {
+        unsafe {
+            let pkt = &*self.ptr;
+            if pkt.data.is_null() || pkt.size <= 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(pkt.data, pkt.size as usize)
+            }
+        }
+    }
+    pub fn size(&self) -> usize {
+        unsafe { (*self.ptr).size.max(0) as usize }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+    pub fn pts(&self) -> i64 {
+        unsafe { (*self.ptr).pts }
+    }
+    pub fn set_pts(&mut self, pts: i64) {
+        unsafe {
+            (*self.ptr).pts = pts;
+        };
+    }
+    pub fn dts(&self) -> i64 {
+        unsafe { (*self.ptr).dts }
+    }
+    pub fn set_dts(&mut self, dts: i64) {
+        unsafe {
+            (*self.ptr).dts = dts;
+        };
+    }
+    pub fn duration(&self) -> i64 {
+        unsafe { (*self.ptr).duration }
+    }
+    pub fn set_duration(&mut self, duration: i64) {
+        unsafe {
+            (*self.ptr).duration = duration;
+        };
+    }
+    /// Byte position in the input, -1 if unknown.
+    pub fn pos(&self) -> i64 {
+        unsafe { (*self.ptr).pos }
+    }
+    pub fn set_pos(&mut self, pos: i64) {
+        unsafe {
+            (*self.ptr).pos = pos;
+        };
+    }
+    pub fn stream_index(&self) -> usize {
+        unsafe { (*self.ptr).stream_index as usize }
+    }
+    pub fn set_stream_index(&mut self, index: usize) {
+        unsafe {
+            (*self.ptr).stream_index = index as c_int;
+        };
+    }
+    pub fn is_key(&self) -> bool {
+        unsafe { ((*self.ptr).flags & sys::AV_PKT_FLAG_KEY as c_int) != 0 }
+    }
+    /// Typed where `side_data` knows the layout.
+    pub fn side_data(&self) -> Vec<SideData> {
+        unsafe {
+            (0..(*self.ptr).side_data_elems.max(0) as usize)
+                .map(|i| {
+                    let entry = &*(*self.ptr).side_data.add(i);
+                    let data = raw_bytes(entry.data, entry.size.max(0) as usize);
+                    SideData::from_packet(PacketSideDataType::from_raw(entry.type_), data)
+                })
+                .collect()
+        }
+    }
+    /// Adds an entry, replacing any of the same type.
+    pub fn set_side_data(&mut self, side_data: &SideData) -> Result<()> {
+        let kind = side_data.packet_type()
+            .ok_or_else(|| Error::Invalid(format!("{:?} is not packet side data", side_data)))?;
+        let bytes = side_data.to_bytes();
+        unsafe {
+            // `av_packet_new_side_data` WOULD ADD A SECOND ENTRY OF THE SAME TYPE
+            let mut size: c_int = 0;
+            let existing = sys::av_packet_get_side_data(self.ptr, kind.to_raw(), &mut size);
+            if !existing.is_null() && size as usize == bytes.len() {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), existing, bytes.len());
+                return Ok(());
+            }
+            if !existing.is_null() {
+                let data = sys::av_mallocz(bytes.len() + sys::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+                if data.is_null() {
+                    return Err(Error::Alloc("packet side data"));
+                }
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+                let pkt = &mut *self.ptr;
+                for i in 0..pkt.side_data_elems.max(0) as usize {
+                    let entry = &mut *pkt.side_data.add(i);
+                    if entry.data == existing {
+                        sys::av_free(entry.data as *mut _);
+                        entry.data = data;
+                        entry.size = bytes.len() as _;
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+            let data = sys::av_packet_new_side_data(self.ptr, kind.to_raw(), bytes.len() as _);
+            if data.is_null() {
+                return Err(Error::Alloc("packet side data"));
+            }
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+        }
+        Ok(())
+    }
+    /// Converts pts, dts and duration between time bases.
+    pub fn rescale_ts(&mut self, from: sys::AVRational, to: sys::AVRational) {
+        unsafe {
+            sys::av_packet_rescale_ts(self.ptr, from, to);
+        };
+    }
+    pub fn unref(&mut self) {
+        unsafe {
+            sys::av_packet_unref(self.ptr);
+        };
+    }
+    /// A new reference to the same data (`av_packet_clone`).
+    pub fn try_clone(&self) -> Result<Self> {
+        let ptr = unsafe { sys::av_packet_clone(self.ptr) };
+        if ptr.is_null() {
+            return Err(Error::Alloc("AVPacket"));
+        }
+        Ok(Packet {ptr})
+    }
+    pub fn as_ptr(&self) -> *const sys::AVPacket {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut sys::AVPacket {
+        self.ptr
+    }
+}
+
+impl Drop for Packet {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_packet_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for Packet {}
@@ -0,0 +1,138 @@
+//! Splits raw elementary streams (e.g. a `.h264` or `.aac` file) into
+//! packets via the libavcodec parsers.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::codec::CodecContext;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::packet::Packet;
+use crate::extra::util::c_str;
+
+pub struct Parser {
+    ptr: *mut sys::AVCodecParserContext,
+    ctx: CodecContext,
+    buffer: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new(codec_id: sys::AVCodecID) -> Result<Self> {
+        let ptr = unsafe { sys::av_parser_init(codec_id as c_int) };
+        if ptr.is_null() {
+            return Err(Error::NotFound(format!("parser for codec id {}", codec_id)));
+        }
+        // PARSERS FILL IN E.G. THE PROFILE AND DIMENSIONS OF THIS CONTEXT;
+        // THEY DON'T NEED A DECODER
+        match CodecContext::new_without_codec(codec_id) {
+            Ok(ctx) => Ok(Parser {ptr, ctx, buffer: Vec::new()}),
+            Err(err) => {
+                unsafe { sys::av_parser_close(ptr) };
+                Err(err)
+            }
+        }
+    }
+    /// By codec name, e.g. `h264`, `hevc`, `aac`, `mp3`.
+    pub fn by_codec_name(name: &str) -> Result<Self> {
+        let name_cstr = c_str(name)?;
+        let desc = unsafe { sys::avcodec_descriptor_get_by_name(name_cstr.as_ptr()) };
+        if desc.is_null() {
+            return Err(Error::NotFound(format!("codec {:?}", name)));
+        }
+        Parser::new(unsafe { (*desc).id })
+    }
+    /// The context the parser writes stream properties into; usable to
+    /// configure a decoder once the first packets came out.
+    pub fn codec_context(&self) -> &CodecContext {
+        &self.ctx
+    }
+    /// Feeds an arbitrary chunk, returning the packets completed so far.
+    pub fn parse(&mut self, data: &[u8]) -> Result<Vec<Packet>> {
+        self.parse_with_timestamps(data, None, None, None)
+    }
+    /// Like `parse`; the timestamps and byte position are attached to the
+    /// packet that starts within this chunk.
+    pub fn parse_with_timestamps(
+        &mut self,
+        data: &[u8],
+        pts: Option<i64>,
+        dts: Option<i64>,
+        pos: Option<i64>,
+    ) -> Result<Vec<Packet>> {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let mut pts = pts.unwrap_or(nopts);
+        let mut dts = dts.unwrap_or(nopts);
+        let mut pos = pos.unwrap_or(-1);
+        // PARSERS MAY READ PAST THE END, SO WORK ON A ZERO PADDED COPY
+        self.buffer.clear();
+        self.buffer.extend_from_slice(data);
+        self.buffer.resize(data.len() + sys::AV_INPUT_BUFFER_PADDING_SIZE as usize, 0);
+        let mut output = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let (consumed, pkt) = unsafe {
+                let input = self.buffer.as_ptr().add(offset);
+                self.parse_raw(input, (data.len() - offset) as c_int, pts, dts, pos)?
+            };
+            offset += consumed;
+            pts = nopts;
+            dts = nopts;
+            pos = -1;
+            let produced = pkt.is_some();
+            output.extend(pkt);
+            if consumed == 0 && !produced {
+                break;
+            }
+        }
+        Ok(output)
+    }
+    /// Returns the final, buffered packet at end of input.
+    pub fn flush(&mut self) -> Result<Option<Packet>> {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let (_, pkt) = unsafe { self.parse_raw(std::ptr::null(), 0, nopts, nopts, -1)? };
+        Ok(pkt)
+    }
+    unsafe fn parse_raw(
+        &mut self,
+        input: *const u8,
+        size: c_int,
+        pts: i64,
+        dts: i64,
+        pos: i64,
+    ) -> Result<(usize, Option<Packet>)> {
+        let mut out_data: *mut u8 = std::ptr::null_mut();
+        let mut out_size: c_int = 0;
+        let consumed = check(sys::av_parser_parse2(
+            self.ptr,
+            self.ctx.as_mut_ptr(),
+            &mut out_data,
+            &mut out_size,
+            input,
+            size,
+            pts,
+            dts,
+            pos,
+        ))?;
+        if out_size <= 0 {
+            return Ok((consumed as usize, None));
+        }
+        let mut pkt = Packet::from_data(std::slice::from_raw_parts(out_data, out_size as usize))?;
+        let parser = &*self.ptr;
+        pkt.set_pts(parser.pts);
+        pkt.set_dts(parser.dts);
+        pkt.set_pos(parser.pos);
+        pkt.set_duration(parser.duration as i64);
+        if parser.key_frame == 1 {
+            (*pkt.as_mut_ptr()).flags |= sys::AV_PKT_FLAG_KEY as c_int;
+        }
+        Ok((consumed as usize, Some(pkt)))
+    }
+}
+
+impl Drop for Parser {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_parser_close(self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for Parser {}
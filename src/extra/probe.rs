@@ -0,0 +1,472 @@
+//! What is in a file, without decoding it; like `ffprobe -show_format
+//! -show_streams -show_chapters -show_programs`.
+//!
+//! With the `serde` feature the report serializes to JSON using ffprobe's
+//! field names.
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use std::os::raw::{c_char, c_int};
+use crate::sys;
+use crate::extra::defs;
+use crate::extra::error::Result;
+use crate::extra::format::InputContext;
+use crate::extra::io::{IoContext, SliceReader};
+use crate::extra::util::{from_av_dict, from_c_str};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    pub num: i32,
+    pub den: i32,
+}
+
+impl Rational {
+    fn from_av(x: sys::AVRational) -> Option<Self> {
+        if x.num == 0 || x.den == 0 {
+            None
+        } else {
+            Some(Rational {num: x.num, den: x.den})
+        }
+    }
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+/// As a `num/den` string, like ffprobe.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rational {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FormatInfo {
+    pub filename: Option<String>,
+    pub nb_streams: usize,
+    pub nb_programs: usize,
+    /// E.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+    pub format_name: String,
+    pub format_long_name: Option<String>,
+    /// Seconds.
+    pub start_time: Option<f64>,
+    /// Seconds.
+    pub duration: Option<f64>,
+    /// Bytes, when the input size is known.
+    pub size: Option<i64>,
+    pub bit_rate: Option<i64>,
+    pub probe_score: i32,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// The `AV_DISPOSITION_*` flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Disposition {
+    pub default: bool,
+    pub dub: bool,
+    pub original: bool,
+    pub comment: bool,
+    pub lyrics: bool,
+    pub karaoke: bool,
+    pub forced: bool,
+    pub hearing_impaired: bool,
+    pub visual_impaired: bool,
+    pub clean_effects: bool,
+    pub attached_pic: bool,
+    pub timed_thumbnails: bool,
+}
+
+impl Disposition {
+    pub fn from_flags(flags: c_int) -> Self {
+        let has = |flag: u32| flags & flag as c_int != 0;
+        Disposition {
+            default: has(sys::AV_DISPOSITION_DEFAULT),
+            dub: has(sys::AV_DISPOSITION_DUB),
+            original: has(sys::AV_DISPOSITION_ORIGINAL),
+            comment: has(sys::AV_DISPOSITION_COMMENT),
+            lyrics: has(sys::AV_DISPOSITION_LYRICS),
+            karaoke: has(sys::AV_DISPOSITION_KARAOKE),
+            forced: has(sys::AV_DISPOSITION_FORCED),
+            hearing_impaired: has(sys::AV_DISPOSITION_HEARING_IMPAIRED),
+            visual_impaired: has(sys::AV_DISPOSITION_VISUAL_IMPAIRED),
+            clean_effects: has(sys::AV_DISPOSITION_CLEAN_EFFECTS),
+            attached_pic: has(sys::AV_DISPOSITION_ATTACHED_PIC),
+            timed_thumbnails: has(sys::AV_DISPOSITION_TIMED_THUMBNAILS),
+        }
+    }
+    /// Back to `AV_DISPOSITION_*` bits.
+    pub fn to_flags(&self) -> c_int {
+        let flags = [
+            (self.default, sys::AV_DISPOSITION_DEFAULT),
+            (self.dub, sys::AV_DISPOSITION_DUB),
+            (self.original, sys::AV_DISPOSITION_ORIGINAL),
+            (self.comment, sys::AV_DISPOSITION_COMMENT),
+            (self.lyrics, sys::AV_DISPOSITION_LYRICS),
+            (self.karaoke, sys::AV_DISPOSITION_KARAOKE),
+            (self.forced, sys::AV_DISPOSITION_FORCED),
+            (self.hearing_impaired, sys::AV_DISPOSITION_HEARING_IMPAIRED),
+            (self.visual_impaired, sys::AV_DISPOSITION_VISUAL_IMPAIRED),
+            (self.clean_effects, sys::AV_DISPOSITION_CLEAN_EFFECTS),
+            (self.attached_pic, sys::AV_DISPOSITION_ATTACHED_PIC),
+            (self.timed_thumbnails, sys::AV_DISPOSITION_TIMED_THUMBNAILS),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |acc, (_, flag)| acc | *flag as c_int)
+    }
+}
+
+/// As 0/1 ints, like ffprobe.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Disposition {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let fields = [
+            ("default", self.default),
+            ("dub", self.dub),
+            ("original", self.original),
+            ("comment", self.comment),
+            ("lyrics", self.lyrics),
+            ("karaoke", self.karaoke),
+            ("forced", self.forced),
+            ("hearing_impaired", self.hearing_impaired),
+            ("visual_impaired", self.visual_impaired),
+            ("clean_effects", self.clean_effects),
+            ("attached_pic", self.attached_pic),
+            ("timed_thumbnails", self.timed_thumbnails),
+        ];
+        let mut state = serializer.serialize_struct("Disposition", fields.len())?;
+        for (name, set) in fields.iter() {
+            state.serialize_field(name, &(*set as i32))?;
+        }
+        state.end()
+    }
+}
+
+/// Stream level side data, e.g. a display matrix.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SideData {
+    /// E.g. `Display Matrix`.
+    pub side_data_type: String,
+    /// Degrees counterclockwise, for display matrices.
+    pub rotation: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamInfo {
+    pub index: usize,
+    pub codec_name: String,
+    pub codec_long_name: Option<String>,
+    pub profile: Option<String>,
+    /// `video`, `audio`, `subtitle`, `data` or `attachment`.
+    pub codec_type: Option<String>,
+    pub codec_tag_string: String,
+    pub codec_tag: u32,
+    // VIDEO
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub sample_aspect_ratio: Option<Rational>,
+    pub display_aspect_ratio: Option<Rational>,
+    pub pix_fmt: Option<String>,
+    pub level: Option<i32>,
+    pub color_range: Option<String>,
+    pub color_space: Option<String>,
+    pub color_transfer: Option<String>,
+    pub color_primaries: Option<String>,
+    pub chroma_location: Option<String>,
+    pub r_frame_rate: Option<Rational>,
+    pub avg_frame_rate: Option<Rational>,
+    // AUDIO
+    pub sample_fmt: Option<String>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub channel_layout: Option<String>,
+    pub bits_per_sample: Option<i32>,
+    // TIMING
+    pub time_base: Option<Rational>,
+    pub start_pts: Option<i64>,
+    /// Seconds.
+    pub start_time: Option<f64>,
+    pub duration_ts: Option<i64>,
+    /// Seconds.
+    pub duration: Option<f64>,
+    pub bit_rate: Option<i64>,
+    pub nb_frames: Option<i64>,
+    pub disposition: Disposition,
+    /// ISO 639-2 code from the `language` tag. Not an ffprobe field, so
+    /// it only shows up under `tags` when serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub language: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub side_data_list: Vec<SideData>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChapterInfo {
+    pub id: i64,
+    pub time_base: Option<Rational>,
+    pub start: i64,
+    /// Seconds.
+    pub start_time: f64,
+    pub end: i64,
+    /// Seconds.
+    pub end_time: f64,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProgramInfo {
+    pub program_id: i32,
+    pub program_num: i32,
+    pub nb_streams: usize,
+    pub pmt_pid: i32,
+    pub pcr_pid: i32,
+    pub tags: BTreeMap<String, String>,
+    /// Copies of the program's entries in `MediaInfo::streams`, nested
+    /// like ffprobe does.
+    pub streams: Vec<StreamInfo>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MediaInfo {
+    pub format: FormatInfo,
+    pub streams: Vec<StreamInfo>,
+    pub chapters: Vec<ChapterInfo>,
+    pub programs: Vec<ProgramInfo>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+fn tags(dict: *const sys::AVDictionary) -> BTreeMap<String, String> {
+    unsafe { from_av_dict(dict).into_iter().collect() }
+}
+
+fn timestamp(ts: i64) -> Option<i64> {
+    if ts == unsafe { defs::av_nopts_value() } {
+        None
+    } else {
+        Some(ts)
+    }
+}
+
+fn seconds(ts: i64, time_base: sys::AVRational) -> Option<f64> {
+    // `av_q2d` IS AN INLINE FUNCTION, NOT EXPORTED
+    timestamp(ts).map(|ts| ts as f64 * time_base.num as f64 / time_base.den as f64)
+}
+
+fn positive<T: PartialOrd + Default>(x: T) -> Option<T> {
+    if x > T::default() {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+/// FFmpeg's name for an enum value, leaving out unspecified ones.
+fn enum_name(name: *const c_char) -> Option<String> {
+    unsafe { from_c_str(name) }.filter(|x| x != "unknown" && x != "unspecified")
+}
+
+/// Like `av_fourcc_make_string`: `avc1`, or `[27][0][0][0]`.
+fn codec_tag_string(tag: u32) -> String {
+    let mut output = String::new();
+    for byte in tag.to_le_bytes().iter() {
+        if byte.is_ascii_alphanumeric() || b" .-_".contains(byte) {
+            output.push(*byte as char);
+        } else {
+            output.push_str(&format!("[{}]", byte));
+        }
+    }
+    output
+}
+
+fn channel_layout_name(channels: c_int, layout: u64) -> Option<String> {
+    if channels <= 0 {
+        return None;
+    }
+    let mut buffer = [0 as c_char; 128];
+    unsafe {
+        sys::av_get_channel_layout_string(buffer.as_mut_ptr(), buffer.len() as c_int, channels, layout);
+        from_c_str(buffer.as_ptr())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// REPORT
+///////////////////////////////////////////////////////////////////////////////
+
+unsafe fn side_data(stream: &sys::AVStream) -> Vec<SideData> {
+    let mut output = Vec::new();
+    for i in 0..stream.nb_side_data.max(0) as usize {
+        let entry = &*stream.side_data.add(i);
+        let name = from_c_str(sys::av_packet_side_data_name(entry.type_)).unwrap_or_default();
+        let rotation = {
+            let is_matrix = entry.type_ == sys::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX;
+            if is_matrix && entry.size as usize >= 9 * 4 {
+                Some(sys::av_display_rotation_get(entry.data as *const i32))
+            } else {
+                None
+            }
+        };
+        output.push(SideData {side_data_type: name, rotation});
+    }
+    output
+}
+
+unsafe fn stream_info(index: usize, stream: &sys::AVStream) -> StreamInfo {
+    let par = &*stream.codecpar;
+    let descriptor = sys::avcodec_descriptor_get(par.codec_id);
+    let mut info = StreamInfo {
+        index,
+        codec_name: from_c_str(sys::avcodec_get_name(par.codec_id)).unwrap_or_default(),
+        codec_long_name: if descriptor.is_null() { None } else { from_c_str((*descriptor).long_name) },
+        profile: from_c_str(sys::avcodec_profile_name(par.codec_id, par.profile)),
+        codec_type: from_c_str(sys::av_get_media_type_string(par.codec_type)),
+        codec_tag_string: codec_tag_string(par.codec_tag),
+        codec_tag: par.codec_tag,
+        time_base: Rational::from_av(stream.time_base),
+        start_pts: timestamp(stream.start_time),
+        start_time: seconds(stream.start_time, stream.time_base),
+        duration_ts: timestamp(stream.duration),
+        duration: seconds(stream.duration, stream.time_base),
+        bit_rate: positive(par.bit_rate),
+        nb_frames: positive(stream.nb_frames),
+        disposition: Disposition::from_flags(stream.disposition),
+        tags: tags(stream.metadata),
+        side_data_list: side_data(stream),
+        ..StreamInfo::default()
+    };
+    info.language = info.tags.get("language").cloned();
+    match par.codec_type {
+        sys::AVMediaType_AVMEDIA_TYPE_VIDEO => {
+            info.width = Some(par.width);
+            info.height = Some(par.height);
+            // THE CONTAINER'S VALUE OVERRIDES THE BITSTREAM'S
+            let sar = if stream.sample_aspect_ratio.num != 0 {
+                stream.sample_aspect_ratio
+            } else {
+                par.sample_aspect_ratio
+            };
+            info.sample_aspect_ratio = Rational::from_av(sar);
+            if let Some(sar) = info.sample_aspect_ratio {
+                let mut dar = sys::AVRational {num: 0, den: 0};
+                sys::av_reduce(
+                    &mut dar.num,
+                    &mut dar.den,
+                    par.width as i64 * sar.num as i64,
+                    par.height as i64 * sar.den as i64,
+                    1024 * 1024,
+                );
+                info.display_aspect_ratio = Rational::from_av(dar);
+            }
+            info.pix_fmt = from_c_str(sys::av_get_pix_fmt_name(par.format));
+            info.level = if par.level == sys::FF_LEVEL_UNKNOWN { None } else { Some(par.level) };
+            info.color_range = enum_name(sys::av_color_range_name(par.color_range));
+            info.color_space = enum_name(sys::av_color_space_name(par.color_space));
+            info.color_transfer = enum_name(sys::av_color_transfer_name(par.color_trc));
+            info.color_primaries = enum_name(sys::av_color_primaries_name(par.color_primaries));
+            info.chroma_location = enum_name(sys::av_chroma_location_name(par.chroma_location));
+            info.r_frame_rate = Rational::from_av(stream.r_frame_rate);
+            info.avg_frame_rate = Rational::from_av(stream.avg_frame_rate);
+        }
+        sys::AVMediaType_AVMEDIA_TYPE_AUDIO => {
+            info.sample_fmt = from_c_str(sys::av_get_sample_fmt_name(par.format));
+            info.sample_rate = Some(par.sample_rate);
+            info.channels = Some(par.channels);
+            info.channel_layout = channel_layout_name(par.channels, par.channel_layout);
+            info.bits_per_sample = Some(sys::av_get_bits_per_sample(par.codec_id));
+        }
+        _ => {}
+    }
+    info
+}
+
+unsafe fn media_info(input: &InputContext, filename: Option<&str>) -> MediaInfo {
+    let ctx = &*input.as_ptr();
+    let av_time_base = sys::AVRational {num: 1, den: sys::AV_TIME_BASE as c_int};
+    let format = FormatInfo {
+        filename: filename.map(String::from),
+        nb_streams: ctx.nb_streams as usize,
+        nb_programs: ctx.nb_programs as usize,
+        format_name: input.format_name(),
+        format_long_name: from_c_str((*ctx.iformat).long_name),
+        start_time: seconds(ctx.start_time, av_time_base),
+        duration: seconds(ctx.duration, av_time_base),
+        size: if ctx.pb.is_null() { None } else { positive(sys::avio_size(ctx.pb)) },
+        bit_rate: positive(ctx.bit_rate),
+        probe_score: ctx.probe_score,
+        tags: tags(ctx.metadata),
+    };
+    let streams = (0..input.nb_streams())
+        .map(|i| stream_info(i, &*input.stream_ptr(i)))
+        .collect::<Vec<_>>();
+    let chapters = (0..ctx.nb_chapters as usize)
+        .map(|i| {
+            let chapter = &**ctx.chapters.add(i);
+            ChapterInfo {
+                id: chapter.id as i64,
+                time_base: Rational::from_av(chapter.time_base),
+                start: chapter.start,
+                start_time: seconds(chapter.start, chapter.time_base).unwrap_or(0.0),
+                end: chapter.end,
+                end_time: seconds(chapter.end, chapter.time_base).unwrap_or(0.0),
+                tags: tags(chapter.metadata),
+            }
+        })
+        .collect();
+    let programs = (0..ctx.nb_programs as usize)
+        .map(|i| {
+            let program = &**ctx.programs.add(i);
+            let program_streams = (0..program.nb_stream_indexes as usize)
+                .filter_map(|j| streams.get(*program.stream_index.add(j) as usize).cloned())
+                .collect::<Vec<_>>();
+            ProgramInfo {
+                program_id: program.id,
+                program_num: program.program_num,
+                nb_streams: program_streams.len(),
+                pmt_pid: program.pmt_pid,
+                pcr_pid: program.pcr_pid,
+                tags: tags(program.metadata),
+                streams: program_streams,
+            }
+        })
+        .collect();
+    MediaInfo {format, streams, chapters, programs}
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// API
+///////////////////////////////////////////////////////////////////////////////
+
+/// Probes a file path or URL.
+pub fn probe(url: &str) -> Result<MediaInfo> {
+    let input = InputContext::open(url)?;
+    Ok(unsafe { media_info(&input, Some(url)) })
+}
+
+pub fn probe_reader<R: Read + Seek + 'static>(reader: R) -> Result<MediaInfo> {
+    let input = InputContext::open_io(IoContext::from_seekable_reader(reader)?, None, &[])?;
+    Ok(unsafe { media_info(&input, None) })
+}
+
+pub fn probe_bytes(data: &[u8]) -> Result<MediaInfo> {
+    // THE IO CONTEXT NEVER OUTLIVES THIS CALL
+    let reader = unsafe { SliceReader::new(data) };
+    let input = InputContext::open_io(IoContext::from_seekable_reader(reader)?, None, &[])?;
+    Ok(unsafe { media_info(&input, None) })
+}
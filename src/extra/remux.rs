@@ -0,0 +1,583 @@
+//! Stream copy between containers, without decoding.
+use std::io::{Read, Seek};
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::bsf::{self, BitstreamFilter};
+use crate::extra::capabilities::MediaType;
+use crate::extra::codec::CodecParameters;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::format::{InputContext, OutputContext};
+use crate::extra::io::{IoContext, SliceReader};
+use crate::extra::metadata::{self, MetadataEdit};
+use crate::extra::packet::Packet;
+use crate::extra::util::from_av_dict;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+    Ts,
+}
+
+impl Container {
+    pub fn muxer_name(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::Mkv => "matroska",
+            Container::Webm => "webm",
+            Container::Ts => "mpegts",
+        }
+    }
+    fn is_isobmff(self) -> bool {
+        self == Container::Mp4 || self == Container::Mov
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageOptions {
+    pub container: Container,
+    /// `movflags=+faststart`, i.e. the index up front (MP4/MOV only).
+    pub faststart: bool,
+    /// Fragmented MP4/MOV (`movflags=+frag_keyframe+empty_moov+default_base_moof`).
+    pub fragmented: bool,
+    /// Additional muxer options.
+    pub muxer_options: Vec<(String, String)>,
+}
+
+impl Default for PackageOptions {
+    fn default() -> Self {
+        PackageOptions {
+            container: Container::Mp4,
+            faststart: false,
+            fragmented: false,
+            muxer_options: Vec::new(),
+        }
+    }
+}
+
+impl PackageOptions {
+    fn muxer_options(&self) -> Vec<(String, String)> {
+        let mut movflags = String::new();
+        if self.container.is_isobmff() {
+            if self.faststart {
+                movflags.push_str("+faststart");
+            }
+            if self.fragmented {
+                movflags.push_str("+frag_keyframe+empty_moov+default_base_moof");
+            }
+        }
+        let mut output = self.muxer_options.clone();
+        if !movflags.is_empty() {
+            match output.iter_mut().find(|(key, _)| key == "movflags") {
+                Some((_, value)) => value.push_str(&movflags),
+                None => output.push((String::from("movflags"), movflags)),
+            }
+        }
+        output
+    }
+}
+
+/// An input stream being copied into the output.
+pub(crate) struct CopiedStream {
+    out_index: usize,
+    in_time_base: sys::AVRational,
+    filter: Option<BitstreamFilter>,
+}
+
+fn mktag(tag: &[u8; 4]) -> u32 {
+    (tag[0] as u32) | ((tag[1] as u32) << 8) | ((tag[2] as u32) << 16) | ((tag[3] as u32) << 24)
+}
+
+/// Adds an output stream for copying `par`, with whatever bitstream filter
+/// the muxer needs.
+pub(crate) fn add_copied_stream(
+    output: &mut OutputContext,
+    par: &CodecParameters,
+    in_time_base: sys::AVRational,
+) -> Result<CopiedStream> {
+    let muxer = output.format_name();
+    let filter = match bsf::required_filter(par, &muxer) {
+        Some(name) => Some(BitstreamFilter::new(name, par, in_time_base)?),
+        None => None,
+    };
+    let out_par = match &filter {
+        Some(filter) => filter.output_parameters()?,
+        None => par.try_clone()?,
+    };
+    let out_index = output.add_stream(&out_par, in_time_base)?;
+    let is_isobmff = muxer == "mp4" || muxer == "mov";
+    if out_par.codec_id() == sys::AVCodecID_AV_CODEC_ID_HEVC && is_isobmff {
+        // `hvc1` RATHER THAN `hev1` FOR APPLE PLAYERS
+        unsafe {
+            (*output.as_mut_ptr()).strict_std_compliance = sys::FF_COMPLIANCE_UNOFFICIAL as c_int;
+            (*(*output.stream_ptr(out_index)).codecpar).codec_tag = mktag(b"hvc1");
+        }
+    }
+    Ok(CopiedStream {out_index, in_time_base, filter})
+}
+
+fn write_copied(output: &mut OutputContext, stream: &CopiedStream, pkt: &mut Packet) -> Result<()> {
+    pkt.set_stream_index(stream.out_index);
+    pkt.rescale_ts(stream.in_time_base, output.stream_time_base(stream.out_index));
+    pkt.set_pos(-1);
+    output.write_packet(pkt)
+}
+
+/// Writes one input packet (through the stream's bitstream filter, if any).
+pub(crate) fn copy_packet(output: &mut OutputContext, stream: &mut CopiedStream, pkt: &mut Packet) -> Result<()> {
+    match stream.filter.as_mut() {
+        Some(filter) => {
+            let packet = unsafe { Packet::take_from(pkt.as_mut_ptr())? };
+            for mut packet in filter.filter(packet)? {
+                write_copied(output, stream, &mut packet)?;
+            }
+            Ok(())
+        }
+        None => write_copied(output, stream, pkt),
+    }
+}
+
+/// Drains the stream's bitstream filter.
+pub(crate) fn finish_copied(output: &mut OutputContext, stream: &mut CopiedStream) -> Result<()> {
+    if let Some(filter) = stream.filter.as_mut() {
+        for mut packet in filter.finish()? {
+            write_copied(output, stream, &mut packet)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_packets(
+    input: &mut InputContext,
+    output: &mut OutputContext,
+    streams: &mut [Option<CopiedStream>],
+) -> Result<()> {
+    let mut pkt = Packet::new()?;
+    while input.read_packet(&mut pkt)? {
+        if let Some(Some(stream)) = streams.get_mut(pkt.stream_index()) {
+            copy_packet(output, stream, &mut pkt)?;
+        }
+    }
+    for stream in streams.iter_mut().filter_map(|x| x.as_mut()) {
+        finish_copied(output, stream)?;
+    }
+    Ok(())
+}
+
+fn package_io(io: IoContext, options: &PackageOptions) -> Result<Vec<u8>> {
+    let mut input = InputContext::open_io(io, None, &[])?;
+    let muxer = options.container.muxer_name();
+    let (mut output, file) = OutputContext::in_memory(muxer)?;
+    let mut streams: Vec<Option<CopiedStream>> = Vec::new();
+    for index in 0..input.nb_streams() {
+        let par = input.codec_parameters(index)?;
+        let in_time_base = input.stream_time_base(index);
+        let supported = {
+            let is_av = {
+                par.codec_type() == sys::AVMediaType_AVMEDIA_TYPE_VIDEO ||
+                par.codec_type() == sys::AVMediaType_AVMEDIA_TYPE_AUDIO ||
+                par.codec_type() == sys::AVMediaType_AVMEDIA_TYPE_SUBTITLE
+            };
+            // 0 MEANS KNOWN TO BE UNSUPPORTED, < 0 MEANS UNKNOWN
+            is_av && unsafe {
+                sys::avformat_query_codec((*output.as_ptr()).oformat, par.codec_id(), 0) != 0
+            }
+        };
+        if !supported {
+            streams.push(None);
+            continue;
+        }
+        streams.push(Some(add_copied_stream(&mut output, &par, in_time_base)?));
+    }
+    if output.nb_streams() == 0 {
+        return Err(Error::Invalid(format!("no streams that fit into {}", muxer)));
+    }
+    let muxer_options = options.muxer_options();
+    let muxer_options = muxer_options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    output.write_header(&muxer_options)?;
+    copy_packets(&mut input, &mut output, &mut streams)?;
+    output.write_trailer()?;
+    drop(output);
+    Ok(file.take())
+}
+
+/// Repackages every audio, video and subtitle stream the target container
+/// can hold.
+pub fn package<R: Read + Seek + 'static>(input: R, options: &PackageOptions) -> Result<Vec<u8>> {
+    package_io(IoContext::from_seekable_reader(input)?, options)
+}
+
+/// Like `package`, reading from memory without copying the input.
+pub fn package_bytes(input: &[u8], options: &PackageOptions) -> Result<Vec<u8>> {
+    // THE IO CONTEXT NEVER OUTLIVES THIS CALL
+    let reader = unsafe { SliceReader::new(input) };
+    package_io(IoContext::from_seekable_reader(reader)?, options)
+}
+
+/// E.g. a raw H264/HEVC stream or MPEG-TS into a regular MP4.
+pub fn remux_to_mp4(input: &[u8]) -> Result<Vec<u8>> {
+    package_bytes(input, &PackageOptions::default())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// FILE REMUXING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Picks input streams for `remux`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamSelector {
+    Type(MediaType),
+    Index(usize),
+    /// The stream's `language` tag, e.g. `eng`.
+    Language(String),
+}
+
+impl StreamSelector {
+    pub(crate) fn matches(&self, input: &InputContext, index: usize) -> bool {
+        match self {
+            StreamSelector::Type(x) => unsafe {
+                *x == MediaType::from_raw((*(*input.stream_ptr(index)).codecpar).codec_type)
+            },
+            StreamSelector::Index(x) => *x == index,
+            StreamSelector::Language(x) => unsafe {
+                from_av_dict((*input.stream_ptr(index)).metadata)
+                    .into_iter()
+                    .any(|(key, value)| key == "language" && value == *x)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemuxOptions {
+    /// Streams matching any selector are kept; empty keeps every audio,
+    /// video and subtitle stream. Streams the output format can't hold are
+    /// always left out.
+    pub streams: Vec<StreamSelector>,
+    /// Keep (selected) data and attachment streams too.
+    pub keep_data: bool,
+    /// Start in seconds, snapped back to the previous keyframe; the other
+    /// streams start where that video keyframe is shown.
+    pub start: Option<f64>,
+    /// End in seconds (exclusive).
+    pub end: Option<f64>,
+    /// Copy the container, stream and chapter metadata, e.g. `title` or
+    /// `language`.
+    pub copy_metadata: bool,
+    /// Tags, chapters, dispositions, cover art and attachments to change.
+    pub edit: MetadataEdit,
+    /// Shift timestamps so the output starts at zero.
+    pub normalize_timestamps: bool,
+    /// Seconds added to every timestamp (after normalization).
+    pub offset: f64,
+    /// Muxer name; guessed from the output path when `None`.
+    pub format: Option<String>,
+    pub muxer_options: Vec<(String, String)>,
+}
+
+impl Default for RemuxOptions {
+    fn default() -> Self {
+        RemuxOptions {
+            streams: Vec::new(),
+            keep_data: false,
+            start: None,
+            end: None,
+            copy_metadata: true,
+            edit: MetadataEdit::default(),
+            normalize_timestamps: true,
+            offset: 0.0,
+            format: None,
+            muxer_options: Vec::new(),
+        }
+    }
+}
+
+impl RemuxOptions {
+    fn selects(&self, input: &InputContext, index: usize, par: &CodecParameters) -> bool {
+        let media_type = MediaType::from_raw(par.codec_type());
+        let is_data = media_type == MediaType::Data || media_type == MediaType::Attachment;
+        if is_data && !self.keep_data {
+            return false;
+        }
+        if !self.edit.keeps(input, index) {
+            return false;
+        }
+        if self.streams.is_empty() {
+            return is_data || media_type != MediaType::Unknown;
+        }
+        self.streams.iter().any(|selector| selector.matches(input, index))
+    }
+}
+
+/// A stream kept by `remux`.
+struct RemuxedStream {
+    copied: CopiedStream,
+    time_base: sys::AVRational,
+    is_video: bool,
+    /// Waiting for the first keyframe after seeking.
+    started: bool,
+    finished: bool,
+}
+
+fn to_seconds(ts: i64, time_base: sys::AVRational) -> f64 {
+    ts as f64 * time_base.num as f64 / time_base.den as f64
+}
+
+fn from_seconds(seconds: f64, time_base: sys::AVRational) -> i64 {
+    (seconds * time_base.den as f64 / time_base.num as f64).round() as i64
+}
+
+/// `ts` in seconds, or `fallback` if `ts` is unset; `None` if both are.
+fn ts_seconds(ts: i64, fallback: i64, time_base: sys::AVRational) -> Option<f64> {
+    let nopts = unsafe { defs::av_nopts_value() };
+    let ts = if ts != nopts { ts } else { fallback };
+    if ts != nopts { Some(to_seconds(ts, time_base)) } else { None }
+}
+
+/// Reads up to the next packet that goes into the output; `false` at the
+/// end of the input or once every stream is past `end`. Packets of other
+/// than video streams shown before `cut` (in input seconds) are skipped.
+fn read_kept(
+    input: &mut InputContext,
+    streams: &mut [Option<RemuxedStream>],
+    pkt: &mut Packet,
+    end: Option<f64>,
+    input_start: f64,
+    cut: Option<f64>,
+) -> Result<bool> {
+    while input.read_packet(pkt)? {
+        let stream = match streams.get_mut(pkt.stream_index()) {
+            Some(Some(stream)) if !stream.finished => stream,
+            _ => continue,
+        };
+        let time = ts_seconds(pkt.pts(), pkt.dts(), stream.time_base);
+        if let (Some(end), Some(time)) = (end, time) {
+            if time - input_start >= end {
+                stream.finished = true;
+                if streams.iter().flatten().all(|x| x.finished) {
+                    return Ok(false);
+                }
+                continue;
+            }
+        }
+        if let (Some(cut), Some(time), false) = (cut, time, stream.is_video) {
+            if time < cut {
+                continue;
+            }
+        }
+        if !stream.started {
+            // CUT ON KEYFRAMES, NO DECODING INVOLVED
+            if stream.is_video && !pkt.is_key() {
+                continue;
+            }
+            stream.started = true;
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// How far `read_lead_in` reads ahead, in seconds; the muxers' default
+/// `max_interleave_delta`.
+const MAX_LEAD_IN: f64 = 10.0;
+
+/// The first packets to write, read ahead to find where the output starts.
+struct LeadIn {
+    packets: Vec<Packet>,
+    /// After seeking: where the first video keyframe is shown, in input
+    /// seconds. Other streams' packets from before it are dropped, so the
+    /// output doesn't start with audio over no picture.
+    cut: Option<f64>,
+    /// `false` once `read_kept` has run out.
+    more: bool,
+}
+
+impl LeadIn {
+    /// The lowest dts (pts if unset) read, in input seconds.
+    fn origin(&self, streams: &[Option<RemuxedStream>]) -> Option<f64> {
+        self.packets
+            .iter()
+            .filter_map(|pkt| match streams.get(pkt.stream_index()) {
+                Some(Some(stream)) => ts_seconds(pkt.dts(), pkt.pts(), stream.time_base),
+                _ => None,
+            })
+            .fold(None, |min: Option<f64>, x| Some(min.map_or(x, |min| min.min(x))))
+    }
+}
+
+/// Reads until every kept stream has a packet (up to `MAX_LEAD_IN`), and
+/// after seeking at least up to the first video keyframe.
+fn read_lead_in(
+    input: &mut InputContext,
+    streams: &mut [Option<RemuxedStream>],
+    end: Option<f64>,
+    input_start: f64,
+    seeked: bool,
+) -> Result<LeadIn> {
+    let mut waiting = seeked && streams.iter().flatten().any(|x| x.is_video);
+    let mut lead_in = LeadIn {packets: Vec::new(), cut: None, more: true};
+    let mut seen = vec![false; streams.len()];
+    let mut first: Option<f64> = None;
+    loop {
+        let mut pkt = Packet::new()?;
+        if !read_kept(input, streams, &mut pkt, end, input_start, lead_in.cut)? {
+            lead_in.more = false;
+            return Ok(lead_in);
+        }
+        let index = pkt.stream_index();
+        let (is_video, time_base) = match streams.get(index) {
+            Some(Some(stream)) => (stream.is_video, stream.time_base),
+            _ => continue,
+        };
+        let time = ts_seconds(pkt.dts(), pkt.pts(), time_base);
+        if waiting && is_video {
+            // `read_kept` LETS NOTHING BEFORE THE KEYFRAME THROUGH
+            waiting = false;
+            lead_in.cut = ts_seconds(pkt.pts(), pkt.dts(), time_base);
+            if let Some(cut) = lead_in.cut {
+                lead_in.packets.retain(|x| match streams.get(x.stream_index()) {
+                    Some(Some(stream)) if !stream.is_video => {
+                        ts_seconds(x.pts(), x.dts(), stream.time_base).map_or(true, |t| t >= cut)
+                    }
+                    _ => true,
+                });
+                seen.iter_mut().for_each(|x| *x = false);
+                for x in lead_in.packets.iter() {
+                    seen[x.stream_index()] = true;
+                }
+            }
+        }
+        seen[index] = true;
+        lead_in.packets.push(pkt);
+        first = first.or(time);
+        let all_seen = streams.iter().zip(seen.iter()).all(|(stream, seen)| stream.is_none() || *seen);
+        let too_long = match (first, time) {
+            (Some(first), Some(time)) => time - first > MAX_LEAD_IN,
+            _ => false,
+        };
+        if (all_seen && !waiting) || too_long {
+            return Ok(lead_in);
+        }
+    }
+}
+
+/// Moves `pkt` by `shift` seconds and writes it.
+fn write_shifted(output: &mut OutputContext, stream: &mut RemuxedStream, pkt: &mut Packet, shift: f64) -> Result<()> {
+    if shift != 0.0 {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let shift = from_seconds(shift, stream.time_base);
+        if pkt.pts() != nopts {
+            pkt.set_pts(pkt.pts() + shift);
+        }
+        if pkt.dts() != nopts {
+            pkt.set_dts(pkt.dts() + shift);
+        }
+    }
+    copy_packet(output, &mut stream.copied, pkt)
+}
+
+/// Stream copy between already opened contexts, e.g. for custom IO.
+pub fn remux_contexts(mut input: InputContext, mut output: OutputContext, options: &RemuxOptions) -> Result<()> {
+    let mut streams: Vec<Option<RemuxedStream>> = Vec::new();
+    for index in 0..input.nb_streams() {
+        let par = input.codec_parameters(index)?;
+        let supported = unsafe {
+            sys::avformat_query_codec((*output.as_ptr()).oformat, par.codec_id(), 0) != 0
+        };
+        if !supported || !options.selects(&input, index, &par) {
+            streams.push(None);
+            continue;
+        }
+        let time_base = input.stream_time_base(index);
+        let copied = add_copied_stream(&mut output, &par, time_base)?;
+        if options.copy_metadata {
+            unsafe {
+                let src = input.stream_ptr(index);
+                let dst = output.stream_ptr(copied.out_index);
+                check(sys::av_dict_copy(&mut (*dst).metadata, (*src).metadata, 0))?;
+                (*dst).disposition = (*src).disposition;
+            }
+        }
+        options.edit.apply_to_stream(&input, index, &mut output, copied.out_index)?;
+        streams.push(Some(RemuxedStream {
+            copied,
+            time_base,
+            is_video: par.codec_type() == sys::AVMediaType_AVMEDIA_TYPE_VIDEO,
+            started: options.start.is_none(),
+            finished: false,
+        }));
+    }
+    let cover_art = options.edit.add_streams(&mut output)?;
+    if output.nb_streams() == 0 {
+        return Err(Error::Invalid(format!("no selected streams fit into {}", output.format_name())));
+    }
+    let input_start = input.start_time() as f64 / sys::AV_TIME_BASE as f64;
+    unsafe {
+        if options.copy_metadata {
+            check(sys::av_dict_copy(&mut (*output.as_mut_ptr()).metadata, (*input.as_ptr()).metadata, 0))?;
+        }
+        if let Some(start) = options.start {
+            let ts = ((input_start + start) * sys::AV_TIME_BASE as f64) as i64;
+            check(sys::av_seek_frame(input.as_mut_ptr(), -1, ts, sys::AVSEEK_FLAG_BACKWARD as c_int))?;
+        }
+    }
+    let start = options.start.unwrap_or(0.0);
+    let lead_in = read_lead_in(&mut input, &mut streams, options.end, input_start, options.start.is_some())?;
+    // WHERE THE OUTPUT STARTS IN INPUT SECONDS: THE LOWEST DTS OF ANY STREAM,
+    // SO NONE GOES NEGATIVE; AFTER SEEKING AROUND THE KEYFRAME BEFORE `start`
+    let origin = lead_in.origin(&streams).unwrap_or(input_start + start);
+    let shift = if options.normalize_timestamps { -origin } else { 0.0 } + options.offset;
+    let chapters = if options.copy_metadata {
+        let output_start = if options.normalize_timestamps {
+            start.min(origin - input_start)
+        } else {
+            start
+        };
+        metadata::remuxed_chapters(&input, output_start, options.end, input_start + shift)
+    } else {
+        Vec::new()
+    };
+    options.edit.apply_to_output(&mut output, &chapters)?;
+    let muxer_options = options.muxer_options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    output.write_header(&muxer_options)?;
+    // ONE PACKET AT ZERO EACH, AHEAD OF EVERYTHING ELSE
+    for mut pkt in cover_art {
+        output.write_packet(&mut pkt)?;
+    }
+    let cut = lead_in.cut;
+    let more = lead_in.more;
+    for mut pkt in lead_in.packets {
+        if let Some(Some(stream)) = streams.get_mut(pkt.stream_index()) {
+            write_shifted(&mut output, stream, &mut pkt, shift)?;
+        }
+    }
+    let mut pkt = Packet::new()?;
+    while more && read_kept(&mut input, &mut streams, &mut pkt, options.end, input_start, cut)? {
+        if let Some(Some(stream)) = streams.get_mut(pkt.stream_index()) {
+            write_shifted(&mut output, stream, &mut pkt, shift)?;
+        }
+    }
+    for stream in streams.iter_mut().flatten() {
+        finish_copied(&mut output, &mut stream.copied)?;
+    }
+    output.write_trailer()?;
+    Ok(())
+}
+
+/// Copies the selected streams of `input` into `output` (paths or URLs),
+/// inserting the bitstream filters the output container needs.
+pub fn remux(input: &str, output: &str, options: &RemuxOptions) -> Result<()> {
+    let input = InputContext::open(input)?;
+    let output = OutputContext::new(output, options.format.as_ref().map(|x| x.as_str()))?;
+    remux_contexts(input, output, options)
+}
@@ -0,0 +1,225 @@
+//! Audio sample format, rate and channel layout conversion via libswresample.
+use std::os::raw::{c_int, c_void};
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::Frame;
+use crate::extra::option::Options;
+
+/// Sample format, rate and channel layout of an audio stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    /// Packed (e.g. `AV_SAMPLE_FMT_S16`) or planar (e.g. `AV_SAMPLE_FMT_FLTP`).
+    pub sample_fmt: sys::AVSampleFormat,
+    pub sample_rate: i32,
+    /// `AV_CH_LAYOUT_*` bits.
+    pub channel_layout: u64,
+}
+
+impl AudioFormat {
+    pub fn of_frame(frame: &Frame) -> Self {
+        AudioFormat {
+            sample_fmt: frame.format() as sys::AVSampleFormat,
+            sample_rate: frame.sample_rate(),
+            channel_layout: normalize_channel_layout(frame.channel_layout(), frame.channels()),
+        }
+    }
+    pub fn channels(&self) -> i32 {
+        unsafe { sys::av_get_channel_layout_nb_channels(self.channel_layout) }
+    }
+    pub fn is_planar(&self) -> bool {
+        unsafe { sys::av_sample_fmt_is_planar(self.sample_fmt) != 0 }
+    }
+}
+
+/// Falls back to the default layout for `channels` when the layout is unset.
+pub fn normalize_channel_layout(channel_layout: u64, channels: i32) -> u64 {
+    if channel_layout != 0 {
+        channel_layout
+    } else {
+        unsafe { sys::av_get_default_channel_layout(channels) as u64 }
+    }
+}
+
+/// See `SwrDitherType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    Rectangular,
+    Triangular,
+    TriangularHighpass,
+    Lipshitz,
+    FWeighted,
+    ModifiedEWeighted,
+    ImprovedEWeighted,
+    Shibata,
+    LowShibata,
+    HighShibata,
+}
+
+impl Dither {
+    fn to_raw(self) -> sys::SwrDitherType {
+        match self {
+            Dither::None => sys::SwrDitherType_SWR_DITHER_NONE,
+            Dither::Rectangular => sys::SwrDitherType_SWR_DITHER_RECTANGULAR,
+            Dither::Triangular => sys::SwrDitherType_SWR_DITHER_TRIANGULAR,
+            Dither::TriangularHighpass => sys::SwrDitherType_SWR_DITHER_TRIANGULAR_HIGHPASS,
+            Dither::Lipshitz => sys::SwrDitherType_SWR_DITHER_NS_LIPSHITZ,
+            Dither::FWeighted => sys::SwrDitherType_SWR_DITHER_NS_F_WEIGHTED,
+            Dither::ModifiedEWeighted => sys::SwrDitherType_SWR_DITHER_NS_MODIFIED_E_WEIGHTED,
+            Dither::ImprovedEWeighted => sys::SwrDitherType_SWR_DITHER_NS_IMPROVED_E_WEIGHTED,
+            Dither::Shibata => sys::SwrDitherType_SWR_DITHER_NS_SHIBATA,
+            Dither::LowShibata => sys::SwrDitherType_SWR_DITHER_NS_LOW_SHIBATA,
+            Dither::HighShibata => sys::SwrDitherType_SWR_DITHER_NS_HIGH_SHIBATA,
+        }
+    }
+}
+
+/// Unset fields keep the libswresample defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ResamplerOptions {
+    pub dither: Option<Dither>,
+    /// Length of each FIR filter in the resampling filterbank (default 32).
+    pub filter_size: Option<i32>,
+    /// log2 of the number of filterbank entries (default 10).
+    pub phase_shift: Option<i32>,
+    /// Interpolate between filterbank entries.
+    pub linear_interp: bool,
+    /// Cutoff frequency ratio, 0.0 to 1.0 (default 0.97).
+    pub cutoff: Option<f64>,
+}
+
+pub struct Resampler {
+    ptr: *mut sys::SwrContext,
+    src: AudioFormat,
+    dst: AudioFormat,
+    samples_out: i64,
+}
+
+impl Resampler {
+    pub fn new(src: AudioFormat, dst: AudioFormat, options: ResamplerOptions) -> Result<Self> {
+        let ptr = unsafe {
+            sys::swr_alloc_set_opts(
+                std::ptr::null_mut(),
+                dst.channel_layout as i64,
+                dst.sample_fmt,
+                dst.sample_rate,
+                src.channel_layout as i64,
+                src.sample_fmt,
+                src.sample_rate,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if ptr.is_null() {
+            return Err(Error::Alloc("SwrContext"));
+        }
+        let mut resampler = Resampler {ptr, src, dst, samples_out: 0};
+        if let Some(dither) = options.dither {
+            resampler.set_option("dither_method", dither.to_raw() as i64)?;
+        }
+        if let Some(filter_size) = options.filter_size {
+            resampler.set_option("filter_size", filter_size)?;
+        }
+        if let Some(phase_shift) = options.phase_shift {
+            resampler.set_option("phase_shift", phase_shift)?;
+        }
+        if options.linear_interp {
+            resampler.set_option("linear_interp", true)?;
+        }
+        if let Some(cutoff) = options.cutoff {
+            resampler.set_option("cutoff", cutoff)?;
+        }
+        resampler.reinit()?;
+        Ok(resampler)
+    }
+    /// Re-initializes the context, e.g. after changing options through
+    /// `Options::set_option`. Buffered samples are dropped.
+    pub fn reinit(&mut self) -> Result<()> {
+        unsafe {
+            check(sys::swr_init(self.ptr))?;
+        }
+        Ok(())
+    }
+    pub fn src_format(&self) -> AudioFormat {
+        self.src
+    }
+    pub fn dst_format(&self) -> AudioFormat {
+        self.dst
+    }
+    /// Buffered samples not yet returned, expressed in `1/base` units
+    /// (e.g. pass the output sample rate to get a sample count).
+    pub fn delay(&self, base: i64) -> i64 {
+        unsafe { sys::swr_get_delay(self.ptr, base) }
+    }
+    fn new_output_frame(&self) -> Result<Frame> {
+        let mut frame = Frame::new()?;
+        unsafe {
+            let ptr = &mut *frame.as_mut_ptr();
+            ptr.format = self.dst.sample_fmt as c_int;
+            ptr.sample_rate = self.dst.sample_rate;
+            ptr.channel_layout = self.dst.channel_layout;
+            ptr.channels = self.dst.channels();
+        }
+        Ok(frame)
+    }
+    fn stamp(&mut self, frame: &mut Frame) {
+        frame.set_pts(self.samples_out);
+        self.samples_out += frame.nb_samples() as i64;
+    }
+    /// Converts `src`, returning a newly allocated frame of `dst_format()`.
+    ///
+    /// The output may hold more or fewer samples than `src`, since the
+    /// resampler buffers internally. Output `pts` counts samples (time base
+    /// `1/dst_format().sample_rate`).
+    pub fn convert(&mut self, src: &Frame) -> Result<Frame> {
+        let mut dst = self.new_output_frame()?;
+        unsafe {
+            check(sys::swr_convert_frame(self.ptr, dst.as_mut_ptr(), src.as_ptr()))?;
+        }
+        self.stamp(&mut dst);
+        Ok(dst)
+    }
+    /// Converts into a preallocated frame; its `nb_samples` is the capacity
+    /// on input and the number of samples written on output.
+    pub fn convert_into(&mut self, src: &Frame, dst: &mut Frame) -> Result<()> {
+        if !dst.is_allocated() {
+            return Err(Error::Invalid(String::from("output frame has no buffers")));
+        }
+        unsafe {
+            check(sys::swr_convert_frame(self.ptr, dst.as_mut_ptr(), src.as_ptr()))?;
+        }
+        self.stamp(dst);
+        Ok(())
+    }
+    /// Drains buffered samples at end of stream; `None` once empty.
+    pub fn flush(&mut self) -> Result<Option<Frame>> {
+        if self.delay(self.dst.sample_rate as i64) <= 0 {
+            return Ok(None);
+        }
+        let mut dst = self.new_output_frame()?;
+        unsafe {
+            check(sys::swr_convert_frame(self.ptr, dst.as_mut_ptr(), std::ptr::null()))?;
+        }
+        if dst.nb_samples() == 0 {
+            return Ok(None);
+        }
+        self.stamp(&mut dst);
+        Ok(Some(dst))
+    }
+}
+
+unsafe impl Options for Resampler {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+}
+
+impl Drop for Resampler {
+    fn drop(&mut self) {
+        unsafe {
+            sys::swr_free(&mut self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for Resampler {}
@@ -0,0 +1,260 @@
+//! Image scaling and pixel format conversion via libswscale.
+use std::os::raw::{c_int, c_void};
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::option::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleAlgorithm {
+    FastBilinear,
+    Bilinear,
+    Bicubic,
+    Experimental,
+    Point,
+    Area,
+    Bicublin,
+    Gauss,
+    Sinc,
+    Lanczos,
+    Spline,
+}
+
+impl ScaleAlgorithm {
+    pub fn to_sws_flags(self) -> c_int {
+        let flags = match self {
+            ScaleAlgorithm::FastBilinear => sys::SWS_FAST_BILINEAR,
+            ScaleAlgorithm::Bilinear => sys::SWS_BILINEAR,
+            ScaleAlgorithm::Bicubic => sys::SWS_BICUBIC,
+            ScaleAlgorithm::Experimental => sys::SWS_X,
+            ScaleAlgorithm::Point => sys::SWS_POINT,
+            ScaleAlgorithm::Area => sys::SWS_AREA,
+            ScaleAlgorithm::Bicublin => sys::SWS_BICUBLIN,
+            ScaleAlgorithm::Gauss => sys::SWS_GAUSS,
+            ScaleAlgorithm::Sinc => sys::SWS_SINC,
+            ScaleAlgorithm::Lanczos => sys::SWS_LANCZOS,
+            ScaleAlgorithm::Spline => sys::SWS_SPLINE,
+        };
+        flags as c_int
+    }
+}
+
+impl Default for ScaleAlgorithm {
+    fn default() -> Self {
+        ScaleAlgorithm::Bicubic
+    }
+}
+
+/// YUV <-> RGB matrix and range of one side of the conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorDetails {
+    /// `AVColorSpace` (`AVCOL_SPC_*`); unknown values fall back to BT.601.
+    pub space: sys::AVColorSpace,
+    /// Full (JPEG) range rather than limited (MPEG) range.
+    pub full_range: bool,
+}
+
+impl ColorDetails {
+    /// Taken from the frame's `colorspace`/`color_range` fields.
+    pub fn of_frame(frame: &Frame) -> Self {
+        unsafe {
+            let frame = &*frame.as_ptr();
+            ColorDetails {
+                space: frame.colorspace,
+                full_range: frame.color_range == sys::AVColorRange_AVCOL_RANGE_JPEG,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScalerOptions {
+    pub algorithm: ScaleAlgorithm,
+    /// `SWS_ACCURATE_RND`
+    pub accurate_rounding: bool,
+    /// `SWS_FULL_CHR_H_INT | SWS_FULL_CHR_H_INP`
+    pub full_chroma: bool,
+    /// Defaults to whatever the source frame says.
+    pub src_color: Option<ColorDetails>,
+    /// Defaults to `src_color`.
+    pub dst_color: Option<ColorDetails>,
+}
+
+impl ScalerOptions {
+    fn sws_flags(&self) -> c_int {
+        let mut flags = self.algorithm.to_sws_flags();
+        if self.accurate_rounding {
+            flags |= sys::SWS_ACCURATE_RND as c_int;
+        }
+        if self.full_chroma {
+            flags |= (sys::SWS_FULL_CHR_H_INT | sys::SWS_FULL_CHR_H_INP) as c_int;
+        }
+        flags
+    }
+}
+
+/// A reusable `SwsContext`.
+///
+/// The context is only rebuilt (through `sws_getCachedContext`) when the
+/// source or destination size/format changes, so scaling into preallocated
+/// frames doesn't allocate.
+pub struct Scaler {
+    ptr: *mut sys::SwsContext,
+    src: PictureFormat,
+    dst: PictureFormat,
+    src_color: Option<ColorDetails>,
+    options: ScalerOptions,
+}
+
+impl Scaler {
+    pub fn new(src: PictureFormat, dst: PictureFormat, options: ScalerOptions) -> Result<Self> {
+        let mut scaler = Scaler {
+            ptr: std::ptr::null_mut(),
+            src,
+            dst,
+            src_color: options.src_color,
+            options,
+        };
+        scaler.configure(src, dst)?;
+        Ok(scaler)
+    }
+    pub fn src_format(&self) -> PictureFormat {
+        self.src
+    }
+    pub fn dst_format(&self) -> PictureFormat {
+        self.dst
+    }
+    fn configure(&mut self, src: PictureFormat, dst: PictureFormat) -> Result<()> {
+        let ptr = unsafe {
+            sys::sws_getCachedContext(
+                self.ptr,
+                src.width,
+                src.height,
+                src.pix_fmt,
+                dst.width,
+                dst.height,
+                dst.pix_fmt,
+                self.options.sws_flags(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        if ptr.is_null() {
+            // THE OLD CONTEXT WAS FREED BY `sws_getCachedContext`
+            self.ptr = std::ptr::null_mut();
+            return Err(Error::Invalid(format!(
+                "unsupported conversion {:?} -> {:?}",
+                src,
+                dst,
+            )));
+        }
+        self.ptr = ptr;
+        self.src = src;
+        self.dst = dst;
+        if let Some(src_color) = self.src_color {
+            self.set_colorspace(src_color);
+        }
+        Ok(())
+    }
+    fn set_colorspace(&mut self, src_color: ColorDetails) {
+        let dst_color = self.options.dst_color.unwrap_or(src_color);
+        unsafe {
+            // -1 ALSO COMES BACK FOR YUV -> YUV WITH THE SAME TABLES ON BOTH
+            // SIDES, AFTER APPLYING THEM; vf_scale IGNORES IT TOO
+            sys::sws_setColorspaceDetails(
+                self.ptr,
+                sys::sws_getCoefficients(src_color.space as c_int),
+                src_color.full_range as c_int,
+                sys::sws_getCoefficients(dst_color.space as c_int),
+                dst_color.full_range as c_int,
+                0,
+                1 << 16,
+                1 << 16,
+            );
+        }
+        self.src_color = Some(src_color);
+    }
+    /// Scales `src` into the already allocated `dst`.
+    ///
+    /// The frames' own size and format are used, reconfiguring the context
+    /// if they differ from the previous call.
+    pub fn scale(&mut self, src: &Frame, dst: &mut Frame) -> Result<()> {
+        if !src.is_allocated() || !dst.is_allocated() {
+            return Err(Error::Invalid(String::from("scaling requires allocated frames")));
+        }
+        let src_fmt = src.picture_format();
+        let dst_fmt = dst.picture_format();
+        if self.ptr.is_null() || src_fmt != self.src || dst_fmt != self.dst {
+            self.configure(src_fmt, dst_fmt)?;
+        }
+        let src_color = self.options.src_color.unwrap_or_else(|| ColorDetails::of_frame(src));
+        if self.src_color != Some(src_color) {
+            self.set_colorspace(src_color);
+        }
+        let dst_color = self.options.dst_color.unwrap_or(src_color);
+        unsafe {
+            let src_ptr = &*src.as_ptr();
+            check(sys::sws_scale(
+                self.ptr,
+                src_ptr.data.as_ptr() as *const *const u8,
+                src_ptr.linesize.as_ptr(),
+                0,
+                src_ptr.height,
+                (*dst.as_mut_ptr()).data.as_ptr(),
+                (*dst.as_mut_ptr()).linesize.as_ptr(),
+            ))?;
+            let dst_ptr = &mut *dst.as_mut_ptr();
+            dst_ptr.pts = src_ptr.pts;
+            dst_ptr.best_effort_timestamp = src_ptr.best_effort_timestamp;
+            dst_ptr.colorspace = dst_color.space;
+            dst_ptr.color_range = if dst_color.full_range {
+                sys::AVColorRange_AVCOL_RANGE_JPEG
+            } else {
+                sys::AVColorRange_AVCOL_RANGE_MPEG
+            };
+        }
+        Ok(())
+    }
+    /// Scales into a newly allocated frame of `dst_format()`.
+    pub fn scale_new(&mut self, src: &Frame) -> Result<Frame> {
+        let mut dst = Frame::new_video(self.dst)?;
+        self.scale(src, &mut dst)?;
+        Ok(dst)
+    }
+}
+
+unsafe impl Options for Scaler {
+    fn as_av_class_ptr(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+}
+
+impl Drop for Scaler {
+    fn drop(&mut self) {
+        unsafe {
+            sys::sws_freeContext(self.ptr);
+        };
+    }
+}
+
+unsafe impl Send for Scaler {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_bt709_yuv_to_yuv() {
+        let src_format = PictureFormat {width: 64, height: 48, pix_fmt: sys::AVPixelFormat_AV_PIX_FMT_YUV420P};
+        let dst_format = PictureFormat {width: 32, height: 24, pix_fmt: sys::AVPixelFormat_AV_PIX_FMT_YUV420P};
+        let mut src = Frame::new_video(src_format).unwrap();
+        unsafe {
+            (*src.as_mut_ptr()).colorspace = sys::AVColorSpace_AVCOL_SPC_BT709;
+        }
+        let mut scaler = Scaler::new(src_format, dst_format, ScalerOptions::default()).unwrap();
+        let dst = scaler.scale_new(&src).unwrap();
+        assert_eq!(dst.picture_format(), dst_format);
+        assert_eq!(unsafe { (*dst.as_ptr()).colorspace }, sys::AVColorSpace_AVCOL_SPC_BT709);
+    }
+}
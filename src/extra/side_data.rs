@@ -0,0 +1,950 @@
+//! Typed frame, packet and stream side data: display matrices (rotation),
+//! HDR mastering display and content light levels, stereo 3D, spherical
+//! video, motion vectors, A53 closed captions, encoder stats and so on.
+//!
+//! Types without a layout here round trip as raw bytes. Decoders only
+//! export motion vectors when opened with `flags2=+export_mvs`.
+//!
+//! ```ignore
+//! for side_data in frame.side_data() {
+//!     if let SideData::MasteringDisplay(x) = side_data {
+//!         println!("{:?}", x.luminance);
+//!     }
+//! }
+//! ```
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::error::{Error, Result};
+use crate::extra::format::{InputContext, OutputContext};
+use crate::extra::util::{from_c_str, raw_bytes};
+
+/// `AVFrameSideDataType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSideDataType {
+    Panscan,
+    A53Cc,
+    Stereo3d,
+    MatrixEncoding,
+    DownmixInfo,
+    ReplayGain,
+    DisplayMatrix,
+    Afd,
+    MotionVectors,
+    SkipSamples,
+    AudioServiceType,
+    MasteringDisplayMetadata,
+    GopTimecode,
+    Spherical,
+    ContentLightLevel,
+    IccProfile,
+    QpTableProperties,
+    QpTableData,
+    S12mTimecode,
+    DynamicHdrPlus,
+    RegionsOfInterest,
+    /// Newer than this crate.
+    Other(sys::AVFrameSideDataType),
+}
+
+impl FrameSideDataType {
+    pub fn from_raw(x: sys::AVFrameSideDataType) -> Self {
+        match x {
+            sys::AVFrameSideDataType_AV_FRAME_DATA_PANSCAN => FrameSideDataType::Panscan,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_A53_CC => FrameSideDataType::A53Cc,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_STEREO3D => FrameSideDataType::Stereo3d,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_MATRIXENCODING => FrameSideDataType::MatrixEncoding,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_DOWNMIX_INFO => FrameSideDataType::DownmixInfo,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_REPLAYGAIN => FrameSideDataType::ReplayGain,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_DISPLAYMATRIX => FrameSideDataType::DisplayMatrix,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_AFD => FrameSideDataType::Afd,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_MOTION_VECTORS => FrameSideDataType::MotionVectors,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_SKIP_SAMPLES => FrameSideDataType::SkipSamples,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_AUDIO_SERVICE_TYPE => FrameSideDataType::AudioServiceType,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_MASTERING_DISPLAY_METADATA => {
+                FrameSideDataType::MasteringDisplayMetadata
+            }
+            sys::AVFrameSideDataType_AV_FRAME_DATA_GOP_TIMECODE => FrameSideDataType::GopTimecode,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_SPHERICAL => FrameSideDataType::Spherical,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_CONTENT_LIGHT_LEVEL => FrameSideDataType::ContentLightLevel,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_ICC_PROFILE => FrameSideDataType::IccProfile,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_QP_TABLE_PROPERTIES => FrameSideDataType::QpTableProperties,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_QP_TABLE_DATA => FrameSideDataType::QpTableData,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_S12M_TIMECODE => FrameSideDataType::S12mTimecode,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_DYNAMIC_HDR_PLUS => FrameSideDataType::DynamicHdrPlus,
+            sys::AVFrameSideDataType_AV_FRAME_DATA_REGIONS_OF_INTEREST => FrameSideDataType::RegionsOfInterest,
+            x => FrameSideDataType::Other(x),
+        }
+    }
+    pub fn to_raw(self) -> sys::AVFrameSideDataType {
+        match self {
+            FrameSideDataType::Panscan => sys::AVFrameSideDataType_AV_FRAME_DATA_PANSCAN,
+            FrameSideDataType::A53Cc => sys::AVFrameSideDataType_AV_FRAME_DATA_A53_CC,
+            FrameSideDataType::Stereo3d => sys::AVFrameSideDataType_AV_FRAME_DATA_STEREO3D,
+            FrameSideDataType::MatrixEncoding => sys::AVFrameSideDataType_AV_FRAME_DATA_MATRIXENCODING,
+            FrameSideDataType::DownmixInfo => sys::AVFrameSideDataType_AV_FRAME_DATA_DOWNMIX_INFO,
+            FrameSideDataType::ReplayGain => sys::AVFrameSideDataType_AV_FRAME_DATA_REPLAYGAIN,
+            FrameSideDataType::DisplayMatrix => sys::AVFrameSideDataType_AV_FRAME_DATA_DISPLAYMATRIX,
+            FrameSideDataType::Afd => sys::AVFrameSideDataType_AV_FRAME_DATA_AFD,
+            FrameSideDataType::MotionVectors => sys::AVFrameSideDataType_AV_FRAME_DATA_MOTION_VECTORS,
+            FrameSideDataType::SkipSamples => sys::AVFrameSideDataType_AV_FRAME_DATA_SKIP_SAMPLES,
+            FrameSideDataType::AudioServiceType => sys::AVFrameSideDataType_AV_FRAME_DATA_AUDIO_SERVICE_TYPE,
+            FrameSideDataType::MasteringDisplayMetadata => {
+                sys::AVFrameSideDataType_AV_FRAME_DATA_MASTERING_DISPLAY_METADATA
+            }
+            FrameSideDataType::GopTimecode => sys::AVFrameSideDataType_AV_FRAME_DATA_GOP_TIMECODE,
+            FrameSideDataType::Spherical => sys::AVFrameSideDataType_AV_FRAME_DATA_SPHERICAL,
+            FrameSideDataType::ContentLightLevel => sys::AVFrameSideDataType_AV_FRAME_DATA_CONTENT_LIGHT_LEVEL,
+            FrameSideDataType::IccProfile => sys::AVFrameSideDataType_AV_FRAME_DATA_ICC_PROFILE,
+            FrameSideDataType::QpTableProperties => sys::AVFrameSideDataType_AV_FRAME_DATA_QP_TABLE_PROPERTIES,
+            FrameSideDataType::QpTableData => sys::AVFrameSideDataType_AV_FRAME_DATA_QP_TABLE_DATA,
+            FrameSideDataType::S12mTimecode => sys::AVFrameSideDataType_AV_FRAME_DATA_S12M_TIMECODE,
+            FrameSideDataType::DynamicHdrPlus => sys::AVFrameSideDataType_AV_FRAME_DATA_DYNAMIC_HDR_PLUS,
+            FrameSideDataType::RegionsOfInterest => sys::AVFrameSideDataType_AV_FRAME_DATA_REGIONS_OF_INTEREST,
+            FrameSideDataType::Other(x) => x,
+        }
+    }
+    /// E.g. `Mastering display metadata`.
+    pub fn name(self) -> String {
+        unsafe { from_c_str(sys::av_frame_side_data_name(self.to_raw())) }
+            .unwrap_or_else(|| format!("unknown frame side data {}", self.to_raw()))
+    }
+}
+
+/// `AVPacketSideDataType`, also used for stream side data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketSideDataType {
+    Palette,
+    NewExtradata,
+    ParamChange,
+    H263MbInfo,
+    ReplayGain,
+    DisplayMatrix,
+    Stereo3d,
+    AudioServiceType,
+    QualityStats,
+    FallbackTrack,
+    CpbProperties,
+    SkipSamples,
+    JpDualMono,
+    StringsMetadata,
+    SubtitlePosition,
+    MatroskaBlockAdditional,
+    WebVttIdentifier,
+    WebVttSettings,
+    MetadataUpdate,
+    MpegTsStreamId,
+    MasteringDisplayMetadata,
+    Spherical,
+    ContentLightLevel,
+    A53Cc,
+    EncryptionInitInfo,
+    EncryptionInfo,
+    Afd,
+    /// Newer than this crate.
+    Other(sys::AVPacketSideDataType),
+}
+
+impl PacketSideDataType {
+    pub fn from_raw(x: sys::AVPacketSideDataType) -> Self {
+        match x {
+            sys::AVPacketSideDataType_AV_PKT_DATA_PALETTE => PacketSideDataType::Palette,
+            sys::AVPacketSideDataType_AV_PKT_DATA_NEW_EXTRADATA => PacketSideDataType::NewExtradata,
+            sys::AVPacketSideDataType_AV_PKT_DATA_PARAM_CHANGE => PacketSideDataType::ParamChange,
+            sys::AVPacketSideDataType_AV_PKT_DATA_H263_MB_INFO => PacketSideDataType::H263MbInfo,
+            sys::AVPacketSideDataType_AV_PKT_DATA_REPLAYGAIN => PacketSideDataType::ReplayGain,
+            sys::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX => PacketSideDataType::DisplayMatrix,
+            sys::AVPacketSideDataType_AV_PKT_DATA_STEREO3D => PacketSideDataType::Stereo3d,
+            sys::AVPacketSideDataType_AV_PKT_DATA_AUDIO_SERVICE_TYPE => PacketSideDataType::AudioServiceType,
+            sys::AVPacketSideDataType_AV_PKT_DATA_QUALITY_STATS => PacketSideDataType::QualityStats,
+            sys::AVPacketSideDataType_AV_PKT_DATA_FALLBACK_TRACK => PacketSideDataType::FallbackTrack,
+            sys::AVPacketSideDataType_AV_PKT_DATA_CPB_PROPERTIES => PacketSideDataType::CpbProperties,
+            sys::AVPacketSideDataType_AV_PKT_DATA_SKIP_SAMPLES => PacketSideDataType::SkipSamples,
+            sys::AVPacketSideDataType_AV_PKT_DATA_JP_DUALMONO => PacketSideDataType::JpDualMono,
+            sys::AVPacketSideDataType_AV_PKT_DATA_STRINGS_METADATA => PacketSideDataType::StringsMetadata,
+            sys::AVPacketSideDataType_AV_PKT_DATA_SUBTITLE_POSITION => PacketSideDataType::SubtitlePosition,
+            sys::AVPacketSideDataType_AV_PKT_DATA_MATROSKA_BLOCKADDITIONAL => {
+                PacketSideDataType::MatroskaBlockAdditional
+            }
+            sys::AVPacketSideDataType_AV_PKT_DATA_WEBVTT_IDENTIFIER => PacketSideDataType::WebVttIdentifier,
+            sys::AVPacketSideDataType_AV_PKT_DATA_WEBVTT_SETTINGS => PacketSideDataType::WebVttSettings,
+            sys::AVPacketSideDataType_AV_PKT_DATA_METADATA_UPDATE => PacketSideDataType::MetadataUpdate,
+            sys::AVPacketSideDataType_AV_PKT_DATA_MPEGTS_STREAM_ID => PacketSideDataType::MpegTsStreamId,
+            sys::AVPacketSideDataType_AV_PKT_DATA_MASTERING_DISPLAY_METADATA => {
+                PacketSideDataType::MasteringDisplayMetadata
+            }
+            sys::AVPacketSideDataType_AV_PKT_DATA_SPHERICAL => PacketSideDataType::Spherical,
+            sys::AVPacketSideDataType_AV_PKT_DATA_CONTENT_LIGHT_LEVEL => PacketSideDataType::ContentLightLevel,
+            sys::AVPacketSideDataType_AV_PKT_DATA_A53_CC => PacketSideDataType::A53Cc,
+            sys::AVPacketSideDataType_AV_PKT_DATA_ENCRYPTION_INIT_INFO => PacketSideDataType::EncryptionInitInfo,
+            sys::AVPacketSideDataType_AV_PKT_DATA_ENCRYPTION_INFO => PacketSideDataType::EncryptionInfo,
+            sys::AVPacketSideDataType_AV_PKT_DATA_AFD => PacketSideDataType::Afd,
+            x => PacketSideDataType::Other(x),
+        }
+    }
+    pub fn to_raw(self) -> sys::AVPacketSideDataType {
+        match self {
+            PacketSideDataType::Palette => sys::AVPacketSideDataType_AV_PKT_DATA_PALETTE,
+            PacketSideDataType::NewExtradata => sys::AVPacketSideDataType_AV_PKT_DATA_NEW_EXTRADATA,
+            PacketSideDataType::ParamChange => sys::AVPacketSideDataType_AV_PKT_DATA_PARAM_CHANGE,
+            PacketSideDataType::H263MbInfo => sys::AVPacketSideDataType_AV_PKT_DATA_H263_MB_INFO,
+            PacketSideDataType::ReplayGain => sys::AVPacketSideDataType_AV_PKT_DATA_REPLAYGAIN,
+            PacketSideDataType::DisplayMatrix => sys::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX,
+            PacketSideDataType::Stereo3d => sys::AVPacketSideDataType_AV_PKT_DATA_STEREO3D,
+            PacketSideDataType::AudioServiceType => sys::AVPacketSideDataType_AV_PKT_DATA_AUDIO_SERVICE_TYPE,
+            PacketSideDataType::QualityStats => sys::AVPacketSideDataType_AV_PKT_DATA_QUALITY_STATS,
+            PacketSideDataType::FallbackTrack => sys::AVPacketSideDataType_AV_PKT_DATA_FALLBACK_TRACK,
+            PacketSideDataType::CpbProperties => sys::AVPacketSideDataType_AV_PKT_DATA_CPB_PROPERTIES,
+            PacketSideDataType::SkipSamples => sys::AVPacketSideDataType_AV_PKT_DATA_SKIP_SAMPLES,
+            PacketSideDataType::JpDualMono => sys::AVPacketSideDataType_AV_PKT_DATA_JP_DUALMONO,
+            PacketSideDataType::StringsMetadata => sys::AVPacketSideDataType_AV_PKT_DATA_STRINGS_METADATA,
+            PacketSideDataType::SubtitlePosition => sys::AVPacketSideDataType_AV_PKT_DATA_SUBTITLE_POSITION,
+            PacketSideDataType::MatroskaBlockAdditional => {
+                sys::AVPacketSideDataType_AV_PKT_DATA_MATROSKA_BLOCKADDITIONAL
+            }
+            PacketSideDataType::WebVttIdentifier => sys::AVPacketSideDataType_AV_PKT_DATA_WEBVTT_IDENTIFIER,
+            PacketSideDataType::WebVttSettings => sys::AVPacketSideDataType_AV_PKT_DATA_WEBVTT_SETTINGS,
+            PacketSideDataType::MetadataUpdate => sys::AVPacketSideDataType_AV_PKT_DATA_METADATA_UPDATE,
+            PacketSideDataType::MpegTsStreamId => sys::AVPacketSideDataType_AV_PKT_DATA_MPEGTS_STREAM_ID,
+            PacketSideDataType::MasteringDisplayMetadata => {
+                sys::AVPacketSideDataType_AV_PKT_DATA_MASTERING_DISPLAY_METADATA
+            }
+            PacketSideDataType::Spherical => sys::AVPacketSideDataType_AV_PKT_DATA_SPHERICAL,
+            PacketSideDataType::ContentLightLevel => sys::AVPacketSideDataType_AV_PKT_DATA_CONTENT_LIGHT_LEVEL,
+            PacketSideDataType::A53Cc => sys::AVPacketSideDataType_AV_PKT_DATA_A53_CC,
+            PacketSideDataType::EncryptionInitInfo => sys::AVPacketSideDataType_AV_PKT_DATA_ENCRYPTION_INIT_INFO,
+            PacketSideDataType::EncryptionInfo => sys::AVPacketSideDataType_AV_PKT_DATA_ENCRYPTION_INFO,
+            PacketSideDataType::Afd => sys::AVPacketSideDataType_AV_PKT_DATA_AFD,
+            PacketSideDataType::Other(x) => x,
+        }
+    }
+    /// E.g. `Display Matrix`.
+    pub fn name(self) -> String {
+        unsafe { from_c_str(sys::av_packet_side_data_name(self.to_raw())) }
+            .unwrap_or_else(|| format!("unknown packet side data {}", self.to_raw()))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PAYLOADS
+///////////////////////////////////////////////////////////////////////////////
+
+/// The 3x3 matrix of `libavutil/display.h`, row major, in 16.16 fixed
+/// point except for the last column (2.30).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMatrix(pub [i32; 9]);
+
+impl DisplayMatrix {
+    /// Rotates `angle` degrees counterclockwise, then flips.
+    pub fn new(angle: f64, hflip: bool, vflip: bool) -> Self {
+        let mut matrix = [0; 9];
+        unsafe {
+            sys::av_display_rotation_set(matrix.as_mut_ptr(), angle);
+            sys::av_display_matrix_flip(matrix.as_mut_ptr(), hflip as c_int, vflip as c_int);
+        }
+        DisplayMatrix(matrix)
+    }
+    /// Degrees counterclockwise in `[-180, 180]`; `None` for a singular
+    /// matrix.
+    pub fn rotation(&self) -> Option<f64> {
+        let angle = unsafe { sys::av_display_rotation_get(self.0.as_ptr()) };
+        if angle.is_nan() { None } else { Some(angle) }
+    }
+}
+
+/// CIE 1931 xy chromaticity coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Primaries {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white_point: (f64, f64),
+}
+
+/// In cd/m².
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Luminance {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// SMPTE ST 2086 mastering display color volume, i.e. HDR10 static
+/// metadata along with `ContentLightLevel`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    pub primaries: Option<Primaries>,
+    pub luminance: Option<Luminance>,
+}
+
+impl MasteringDisplay {
+    /// x265's `master-display` parameter, e.g.
+    /// `G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)`.
+    pub fn to_x265_param(&self) -> Option<String> {
+        let primaries = self.primaries?;
+        let luminance = self.luminance?;
+        let xy = |(x, y): (f64, f64)| format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round());
+        Some(format!(
+            "G{}B{}R{}WP{}L({},{})",
+            xy(primaries.green),
+            xy(primaries.blue),
+            xy(primaries.red),
+            xy(primaries.white_point),
+            (luminance.max * 10000.0).round(),
+            (luminance.min * 10000.0).round(),
+        ))
+    }
+}
+
+/// CTA-861.3 content light level, in cd/m².
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level.
+    pub max_cll: u32,
+    /// Maximum frame-average light level.
+    pub max_fall: u32,
+}
+
+/// How the two views are packed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stereo3dType {
+    /// Not stereoscopic.
+    TwoD,
+    SideBySide,
+    TopBottom,
+    FrameSequence,
+    Checkerboard,
+    SideBySideQuincunx,
+    Lines,
+    Columns,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stereo3d {
+    pub kind: Stereo3dType,
+    /// Right view first.
+    pub inverted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    Equirectangular,
+    Cubemap,
+    /// Equirectangular, cropped to `Spherical::bounds`.
+    EquirectangularTile,
+}
+
+/// 360° video.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spherical {
+    pub projection: Projection,
+    /// Degrees.
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+    /// Left, top, right and bottom, in 0.32 fixed point fractions of the
+    /// full frame; tiled projections only.
+    pub bounds: [u32; 4],
+    /// Pixels between cube faces; cubemaps only.
+    pub padding: u32,
+}
+
+/// One block of `AVMotionVector`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionVector {
+    /// Negative if the reference is in the past, positive if in the future.
+    pub source: i32,
+    pub width: u8,
+    pub height: u8,
+    /// Where the block comes from in the reference.
+    pub src_x: i16,
+    pub src_y: i16,
+    /// Where the block is in this frame.
+    pub dst_x: i16,
+    pub dst_y: i16,
+    pub flags: u64,
+    /// `src` is `dst + motion / motion_scale`.
+    pub motion_x: i32,
+    pub motion_y: i32,
+    pub motion_scale: u16,
+}
+
+/// Encoder stats of a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityStats {
+    /// Lagrange multiplier, i.e. `FF_QP2LAMBDA` times the quantizer.
+    pub quality: u32,
+    pub picture_type: sys::AVPictureType,
+    /// Sum of squared errors per plane, if the encoder computed them.
+    pub errors: Vec<u64>,
+}
+
+/// Samples of the frame to drop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkipSamples {
+    pub start: u32,
+    pub end: u32,
+    pub start_reason: u8,
+    pub end_reason: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SideData {
+    DisplayMatrix(DisplayMatrix),
+    MasteringDisplay(MasteringDisplay),
+    ContentLightLevel(ContentLightLevel),
+    Stereo3d(Stereo3d),
+    Spherical(Spherical),
+    /// Frames only.
+    MotionVectors(Vec<MotionVector>),
+    /// CEA-708 `cc_data` triplets.
+    A53Cc(Vec<u8>),
+    /// Packets only.
+    QualityStats(QualityStats),
+    SkipSamples(SkipSamples),
+    /// Active format description.
+    Afd(u8),
+    /// Frame side data without a layout here.
+    RawFrame(FrameSideDataType, Vec<u8>),
+    /// Packet side data without a layout here.
+    RawPacket(PacketSideDataType, Vec<u8>),
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+fn too_short(what: &str, size: usize) -> Error {
+    Error::Invalid(format!("{} side data of {} bytes", what, size))
+}
+
+/// A `T` from the start of `data`, which needn't be aligned.
+unsafe fn read_struct<T: Copy>(data: &[u8], what: &str) -> Result<T> {
+    if data.len() < std::mem::size_of::<T>() {
+        return Err(too_short(what, data.len()));
+    }
+    Ok(std::ptr::read_unaligned(data.as_ptr() as *const T))
+}
+
+/// For structs built from `std::mem::zeroed`, so padding is initialized.
+unsafe fn struct_bytes<T: Copy>(value: &T) -> Vec<u8> {
+    std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>()).to_vec()
+}
+
+fn q2d(x: sys::AVRational) -> f64 {
+    if x.den == 0 { 0.0 } else { x.num as f64 / x.den as f64 }
+}
+
+/// With the denominators FFmpeg's HEVC decoder uses.
+fn d2q(x: f64, den: i32) -> sys::AVRational {
+    sys::AVRational {num: (x * den as f64).round() as i32, den}
+}
+
+const CHROMA_DEN: i32 = 50000;
+const LUMA_DEN: i32 = 10000;
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PARSING
+///////////////////////////////////////////////////////////////////////////////
+
+fn parse_display_matrix(data: &[u8]) -> Result<DisplayMatrix> {
+    Ok(DisplayMatrix(unsafe { read_struct::<[i32; 9]>(data, "display matrix")? }))
+}
+
+fn parse_mastering_display(data: &[u8]) -> Result<MasteringDisplay> {
+    let raw = unsafe { read_struct::<sys::AVMasteringDisplayMetadata>(data, "mastering display")? };
+    let xy = |x: [sys::AVRational; 2]| (q2d(x[0]), q2d(x[1]));
+    Ok(MasteringDisplay {
+        primaries: if raw.has_primaries != 0 {
+            Some(Primaries {
+                red: xy(raw.display_primaries[0]),
+                green: xy(raw.display_primaries[1]),
+                blue: xy(raw.display_primaries[2]),
+                white_point: xy(raw.white_point),
+            })
+        } else {
+            None
+        },
+        luminance: if raw.has_luminance != 0 {
+            Some(Luminance {min: q2d(raw.min_luminance), max: q2d(raw.max_luminance)})
+        } else {
+            None
+        },
+    })
+}
+
+fn parse_content_light_level(data: &[u8]) -> Result<ContentLightLevel> {
+    let raw = unsafe { read_struct::<sys::AVContentLightMetadata>(data, "content light level")? };
+    Ok(ContentLightLevel {max_cll: raw.MaxCLL, max_fall: raw.MaxFALL})
+}
+
+fn parse_stereo3d(data: &[u8]) -> Result<Stereo3d> {
+    let raw = unsafe { read_struct::<sys::AVStereo3D>(data, "stereo 3D")? };
+    let kind = match raw.type_ {
+        sys::AVStereo3DType_AV_STEREO3D_2D => Stereo3dType::TwoD,
+        sys::AVStereo3DType_AV_STEREO3D_SIDEBYSIDE => Stereo3dType::SideBySide,
+        sys::AVStereo3DType_AV_STEREO3D_TOPBOTTOM => Stereo3dType::TopBottom,
+        sys::AVStereo3DType_AV_STEREO3D_FRAMESEQUENCE => Stereo3dType::FrameSequence,
+        sys::AVStereo3DType_AV_STEREO3D_CHECKERBOARD => Stereo3dType::Checkerboard,
+        sys::AVStereo3DType_AV_STEREO3D_SIDEBYSIDE_QUINCUNX => Stereo3dType::SideBySideQuincunx,
+        sys::AVStereo3DType_AV_STEREO3D_LINES => Stereo3dType::Lines,
+        sys::AVStereo3DType_AV_STEREO3D_COLUMNS => Stereo3dType::Columns,
+        x => return Err(Error::Invalid(format!("stereo 3D type {}", x))),
+    };
+    let inverted = raw.flags & sys::AV_STEREO3D_FLAG_INVERT as c_int != 0;
+    Ok(Stereo3d {kind, inverted})
+}
+
+fn parse_spherical(data: &[u8]) -> Result<Spherical> {
+    let raw = unsafe { read_struct::<sys::AVSphericalMapping>(data, "spherical")? };
+    let projection = match raw.projection {
+        sys::AVSphericalProjection_AV_SPHERICAL_EQUIRECTANGULAR => Projection::Equirectangular,
+        sys::AVSphericalProjection_AV_SPHERICAL_CUBEMAP => Projection::Cubemap,
+        sys::AVSphericalProjection_AV_SPHERICAL_EQUIRECTANGULAR_TILE => Projection::EquirectangularTile,
+        x => return Err(Error::Invalid(format!("spherical projection {}", x))),
+    };
+    // ANGLES ARE 16.16 FIXED POINT
+    let degrees = |x: i32| x as f64 / 65536.0;
+    Ok(Spherical {
+        projection,
+        yaw: degrees(raw.yaw),
+        pitch: degrees(raw.pitch),
+        roll: degrees(raw.roll),
+        bounds: [raw.bound_left, raw.bound_top, raw.bound_right, raw.bound_bottom],
+        padding: raw.padding,
+    })
+}
+
+fn parse_motion_vectors(data: &[u8]) -> Result<Vec<MotionVector>> {
+    let size = std::mem::size_of::<sys::AVMotionVector>();
+    data.chunks_exact(size)
+        .map(|chunk| {
+            let raw = unsafe { read_struct::<sys::AVMotionVector>(chunk, "motion vector")? };
+            Ok(MotionVector {
+                source: raw.source,
+                width: raw.w,
+                height: raw.h,
+                src_x: raw.src_x,
+                src_y: raw.src_y,
+                dst_x: raw.dst_x,
+                dst_y: raw.dst_y,
+                flags: raw.flags,
+                motion_x: raw.motion_x,
+                motion_y: raw.motion_y,
+                motion_scale: raw.motion_scale,
+            })
+        })
+        .collect()
+}
+
+/// `u32le quality, u8 picture type, u8 error count, u8[2], u64le errors[]`.
+fn parse_quality_stats(data: &[u8]) -> Result<QualityStats> {
+    if data.len() < 8 {
+        return Err(too_short("quality stats", data.len()));
+    }
+    let count = (data[5] as usize).min((data.len() - 8) / 8);
+    Ok(QualityStats {
+        quality: u32_at(data, 0),
+        picture_type: data[4] as sys::AVPictureType,
+        errors: (0..count).map(|i| u64_at(data, 8 + i * 8)).collect(),
+    })
+}
+
+/// `u32le start, u32le end, u8 start reason, u8 end reason`.
+fn parse_skip_samples(data: &[u8]) -> Result<SkipSamples> {
+    if data.len() < 10 {
+        return Err(too_short("skip samples", data.len()));
+    }
+    Ok(SkipSamples {
+        start: u32_at(data, 0),
+        end: u32_at(data, 4),
+        start_reason: data[8],
+        end_reason: data[9],
+    })
+}
+
+fn parse_afd(data: &[u8]) -> Result<u8> {
+    data.first().cloned().ok_or_else(|| too_short("AFD", 0))
+}
+
+impl SideData {
+    /// Entries that don't parse, e.g. too short for their type, come back
+    /// as `RawFrame`.
+    pub fn from_frame(kind: FrameSideDataType, data: &[u8]) -> Self {
+        SideData::parse_frame(kind, data).unwrap_or_else(|_| SideData::RawFrame(kind, data.to_vec()))
+    }
+    /// Entries that don't parse come back as `RawPacket`.
+    pub fn from_packet(kind: PacketSideDataType, data: &[u8]) -> Self {
+        SideData::parse_packet(kind, data).unwrap_or_else(|_| SideData::RawPacket(kind, data.to_vec()))
+    }
+    fn parse_frame(kind: FrameSideDataType, data: &[u8]) -> Result<Self> {
+        Ok(match kind {
+            FrameSideDataType::DisplayMatrix => SideData::DisplayMatrix(parse_display_matrix(data)?),
+            FrameSideDataType::MasteringDisplayMetadata => SideData::MasteringDisplay(parse_mastering_display(data)?),
+            FrameSideDataType::ContentLightLevel => SideData::ContentLightLevel(parse_content_light_level(data)?),
+            FrameSideDataType::Stereo3d => SideData::Stereo3d(parse_stereo3d(data)?),
+            FrameSideDataType::Spherical => SideData::Spherical(parse_spherical(data)?),
+            FrameSideDataType::MotionVectors => SideData::MotionVectors(parse_motion_vectors(data)?),
+            FrameSideDataType::A53Cc => SideData::A53Cc(data.to_vec()),
+            FrameSideDataType::SkipSamples => SideData::SkipSamples(parse_skip_samples(data)?),
+            FrameSideDataType::Afd => SideData::Afd(parse_afd(data)?),
+            kind => SideData::RawFrame(kind, data.to_vec()),
+        })
+    }
+    fn parse_packet(kind: PacketSideDataType, data: &[u8]) -> Result<Self> {
+        Ok(match kind {
+            PacketSideDataType::DisplayMatrix => SideData::DisplayMatrix(parse_display_matrix(data)?),
+            PacketSideDataType::MasteringDisplayMetadata => SideData::MasteringDisplay(parse_mastering_display(data)?),
+            PacketSideDataType::ContentLightLevel => SideData::ContentLightLevel(parse_content_light_level(data)?),
+            PacketSideDataType::Stereo3d => SideData::Stereo3d(parse_stereo3d(data)?),
+            PacketSideDataType::Spherical => SideData::Spherical(parse_spherical(data)?),
+            PacketSideDataType::A53Cc => SideData::A53Cc(data.to_vec()),
+            PacketSideDataType::QualityStats => SideData::QualityStats(parse_quality_stats(data)?),
+            PacketSideDataType::SkipSamples => SideData::SkipSamples(parse_skip_samples(data)?),
+            PacketSideDataType::Afd => SideData::Afd(parse_afd(data)?),
+            kind => SideData::RawPacket(kind, data.to_vec()),
+        })
+    }
+    /// `None` if this can't be frame side data.
+    pub fn frame_type(&self) -> Option<FrameSideDataType> {
+        match self {
+            SideData::DisplayMatrix(_) => Some(FrameSideDataType::DisplayMatrix),
+            SideData::MasteringDisplay(_) => Some(FrameSideDataType::MasteringDisplayMetadata),
+            SideData::ContentLightLevel(_) => Some(FrameSideDataType::ContentLightLevel),
+            SideData::Stereo3d(_) => Some(FrameSideDataType::Stereo3d),
+            SideData::Spherical(_) => Some(FrameSideDataType::Spherical),
+            SideData::MotionVectors(_) => Some(FrameSideDataType::MotionVectors),
+            SideData::A53Cc(_) => Some(FrameSideDataType::A53Cc),
+            SideData::QualityStats(_) => None,
+            SideData::SkipSamples(_) => Some(FrameSideDataType::SkipSamples),
+            SideData::Afd(_) => Some(FrameSideDataType::Afd),
+            SideData::RawFrame(kind, _) => Some(*kind),
+            SideData::RawPacket(..) => None,
+        }
+    }
+    /// `None` if this can't be packet (or stream) side data.
+    pub fn packet_type(&self) -> Option<PacketSideDataType> {
+        match self {
+            SideData::DisplayMatrix(_) => Some(PacketSideDataType::DisplayMatrix),
+            SideData::MasteringDisplay(_) => Some(PacketSideDataType::MasteringDisplayMetadata),
+            SideData::ContentLightLevel(_) => Some(PacketSideDataType::ContentLightLevel),
+            SideData::Stereo3d(_) => Some(PacketSideDataType::Stereo3d),
+            SideData::Spherical(_) => Some(PacketSideDataType::Spherical),
+            SideData::MotionVectors(_) => None,
+            SideData::A53Cc(_) => Some(PacketSideDataType::A53Cc),
+            SideData::QualityStats(_) => Some(PacketSideDataType::QualityStats),
+            SideData::SkipSamples(_) => Some(PacketSideDataType::SkipSamples),
+            SideData::Afd(_) => Some(PacketSideDataType::Afd),
+            SideData::RawFrame(..) => None,
+            SideData::RawPacket(kind, _) => Some(*kind),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// SERIALIZATION
+///////////////////////////////////////////////////////////////////////////////
+
+impl SideData {
+    /// The layout FFmpeg expects, for either kind of side data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SideData::DisplayMatrix(matrix) => matrix.0.iter().flat_map(|x| x.to_ne_bytes().to_vec()).collect(),
+            SideData::MasteringDisplay(x) => unsafe {
+                let mut raw: sys::AVMasteringDisplayMetadata = std::mem::zeroed();
+                if let Some(primaries) = x.primaries {
+                    let xy = |(x, y): (f64, f64)| [d2q(x, CHROMA_DEN), d2q(y, CHROMA_DEN)];
+                    raw.display_primaries = [xy(primaries.red), xy(primaries.green), xy(primaries.blue)];
+                    raw.white_point = xy(primaries.white_point);
+                    raw.has_primaries = 1;
+                }
+                if let Some(luminance) = x.luminance {
+                    raw.min_luminance = d2q(luminance.min, LUMA_DEN);
+                    raw.max_luminance = d2q(luminance.max, LUMA_DEN);
+                    raw.has_luminance = 1;
+                }
+                struct_bytes(&raw)
+            },
+            SideData::ContentLightLevel(x) => unsafe {
+                let mut raw: sys::AVContentLightMetadata = std::mem::zeroed();
+                raw.MaxCLL = x.max_cll;
+                raw.MaxFALL = x.max_fall;
+                struct_bytes(&raw)
+            },
+            SideData::Stereo3d(x) => unsafe {
+                let mut raw: sys::AVStereo3D = std::mem::zeroed();
+                raw.type_ = match x.kind {
+                    Stereo3dType::TwoD => sys::AVStereo3DType_AV_STEREO3D_2D,
+                    Stereo3dType::SideBySide => sys::AVStereo3DType_AV_STEREO3D_SIDEBYSIDE,
+                    Stereo3dType::TopBottom => sys::AVStereo3DType_AV_STEREO3D_TOPBOTTOM,
+                    Stereo3dType::FrameSequence => sys::AVStereo3DType_AV_STEREO3D_FRAMESEQUENCE,
+                    Stereo3dType::Checkerboard => sys::AVStereo3DType_AV_STEREO3D_CHECKERBOARD,
+                    Stereo3dType::SideBySideQuincunx => sys::AVStereo3DType_AV_STEREO3D_SIDEBYSIDE_QUINCUNX,
+                    Stereo3dType::Lines => sys::AVStereo3DType_AV_STEREO3D_LINES,
+                    Stereo3dType::Columns => sys::AVStereo3DType_AV_STEREO3D_COLUMNS,
+                };
+                if x.inverted {
+                    raw.flags = sys::AV_STEREO3D_FLAG_INVERT as c_int;
+                }
+                struct_bytes(&raw)
+            },
+            SideData::Spherical(x) => unsafe {
+                let mut raw: sys::AVSphericalMapping = std::mem::zeroed();
+                raw.projection = match x.projection {
+                    Projection::Equirectangular => sys::AVSphericalProjection_AV_SPHERICAL_EQUIRECTANGULAR,
+                    Projection::Cubemap => sys::AVSphericalProjection_AV_SPHERICAL_CUBEMAP,
+                    Projection::EquirectangularTile => sys::AVSphericalProjection_AV_SPHERICAL_EQUIRECTANGULAR_TILE,
+                };
+                let fixed = |x: f64| (x * 65536.0).round() as i32;
+                raw.yaw = fixed(x.yaw);
+                raw.pitch = fixed(x.pitch);
+                raw.roll = fixed(x.roll);
+                raw.bound_left = x.bounds[0];
+                raw.bound_top = x.bounds[1];
+                raw.bound_right = x.bounds[2];
+                raw.bound_bottom = x.bounds[3];
+                raw.padding = x.padding;
+                struct_bytes(&raw)
+            },
+            SideData::MotionVectors(vectors) => vectors
+                .iter()
+                .flat_map(|x| unsafe {
+                    let mut raw: sys::AVMotionVector = std::mem::zeroed();
+                    raw.source = x.source;
+                    raw.w = x.width;
+                    raw.h = x.height;
+                    raw.src_x = x.src_x;
+                    raw.src_y = x.src_y;
+                    raw.dst_x = x.dst_x;
+                    raw.dst_y = x.dst_y;
+                    raw.flags = x.flags;
+                    raw.motion_x = x.motion_x;
+                    raw.motion_y = x.motion_y;
+                    raw.motion_scale = x.motion_scale;
+                    struct_bytes(&raw)
+                })
+                .collect(),
+            SideData::QualityStats(x) => {
+                let count = x.errors.len().min(u8::MAX as usize);
+                let mut output = x.quality.to_le_bytes().to_vec();
+                output.extend_from_slice(&[x.picture_type as u8, count as u8, 0, 0]);
+                for error in &x.errors[..count] {
+                    output.extend_from_slice(&error.to_le_bytes());
+                }
+                output
+            }
+            SideData::SkipSamples(x) => {
+                let mut output = x.start.to_le_bytes().to_vec();
+                output.extend_from_slice(&x.end.to_le_bytes());
+                output.extend_from_slice(&[x.start_reason, x.end_reason]);
+                output
+            }
+            SideData::A53Cc(data) => data.clone(),
+            SideData::Afd(x) => vec![*x],
+            SideData::RawFrame(_, data) | SideData::RawPacket(_, data) => data.clone(),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// STREAMS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Side data of input stream `index`, e.g. the display matrix or HDR
+/// metadata from the container.
+pub fn stream_side_data(input: &InputContext, index: usize) -> Vec<SideData> {
+    unsafe {
+        let stream = &*input.stream_ptr(index);
+        (0..stream.nb_side_data.max(0) as usize)
+            .map(|i| {
+                let entry = &*stream.side_data.add(i);
+                let data = raw_bytes(entry.data, entry.size.max(0) as usize);
+                SideData::from_packet(PacketSideDataType::from_raw(entry.type_), data)
+            })
+            .collect()
+    }
+}
+
+/// Adds side data to output stream `index`, replacing any of that type;
+/// before `write_header`.
+pub fn set_stream_side_data(output: &mut OutputContext, index: usize, side_data: &SideData) -> Result<()> {
+    let kind = side_data.packet_type()
+        .ok_or_else(|| Error::Invalid(format!("{:?} is not stream side data", side_data)))?;
+    let bytes = side_data.to_bytes();
+    unsafe {
+        let data = sys::av_stream_new_side_data(output.stream_ptr(index), kind.to_raw(), bytes.len() as _);
+        if data.is_null() {
+            return Err(Error::Alloc("stream side data"));
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+    }
+    Ok(())
+}
+
+/// Counterclockwise degrees of the first display matrix, e.g. from
+/// `stream_side_data`.
+pub fn rotation(side_data: &[SideData]) -> Option<f64> {
+    side_data.iter().find_map(|x| match x {
+        SideData::DisplayMatrix(matrix) => matrix.rotation(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Through `to_bytes` and back, as both kinds of side data it can be.
+    fn round_trip(side_data: SideData) {
+        let bytes = side_data.to_bytes();
+        if let Some(kind) = side_data.frame_type() {
+            assert_eq!(SideData::from_frame(kind, &bytes), side_data);
+        }
+        if let Some(kind) = side_data.packet_type() {
+            assert_eq!(SideData::from_packet(kind, &bytes), side_data);
+        }
+    }
+
+    #[test]
+    fn display_matrix() {
+        let identity = DisplayMatrix::new(0.0, false, false);
+        assert_eq!(identity.0, [1 << 16, 0, 0, 0, 1 << 16, 0, 0, 0, 1 << 30]);
+        for &angle in &[90.0, -90.0, 180.0] {
+            let matrix = DisplayMatrix::new(angle, false, false);
+            assert!((matrix.rotation().unwrap().abs() - angle.abs()).abs() < 1e-6);
+            round_trip(SideData::DisplayMatrix(matrix));
+        }
+        assert_eq!(DisplayMatrix([0; 9]).rotation(), None);
+        let side_data = [SideData::Afd(8), SideData::DisplayMatrix(DisplayMatrix::new(90.0, false, false))];
+        assert!((rotation(&side_data).unwrap() - 90.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mastering_display() {
+        let display = MasteringDisplay {
+            primaries: Some(Primaries {
+                red: (0.68, 0.32),
+                green: (0.265, 0.69),
+                blue: (0.15, 0.06),
+                white_point: (0.3127, 0.329),
+            }),
+            luminance: Some(Luminance {min: 0.0001, max: 1000.0}),
+        };
+        assert_eq!(
+            display.to_x265_param().unwrap(),
+            "G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)",
+        );
+        let bytes = SideData::MasteringDisplay(display).to_bytes();
+        let raw = unsafe { read_struct::<sys::AVMasteringDisplayMetadata>(&bytes, "test").unwrap() };
+        assert_eq!((raw.display_primaries[0][0].num, raw.display_primaries[0][0].den), (34000, CHROMA_DEN));
+        assert_eq!((raw.white_point[1].num, raw.white_point[1].den), (16450, CHROMA_DEN));
+        assert_eq!((raw.min_luminance.num, raw.min_luminance.den), (1, LUMA_DEN));
+        assert_eq!((raw.max_luminance.num, raw.max_luminance.den), (10000000, LUMA_DEN));
+        round_trip(SideData::MasteringDisplay(display));
+        round_trip(SideData::MasteringDisplay(MasteringDisplay {primaries: None, luminance: None}));
+    }
+
+    #[test]
+    fn content_light_level_and_stereo3d() {
+        round_trip(SideData::ContentLightLevel(ContentLightLevel {max_cll: 1000, max_fall: 400}));
+        round_trip(SideData::Stereo3d(Stereo3d {kind: Stereo3dType::TopBottom, inverted: true}));
+        round_trip(SideData::Stereo3d(Stereo3d {kind: Stereo3dType::TwoD, inverted: false}));
+    }
+
+    #[test]
+    fn spherical() {
+        let spherical = Spherical {
+            projection: Projection::EquirectangularTile,
+            yaw: 90.5,
+            pitch: -45.25,
+            roll: 0.0,
+            bounds: [1, 2, 3, 4],
+            padding: 0,
+        };
+        let bytes = SideData::Spherical(spherical).to_bytes();
+        let raw = unsafe { read_struct::<sys::AVSphericalMapping>(&bytes, "test").unwrap() };
+        assert_eq!((raw.yaw, raw.pitch, raw.roll), (90 * 65536 + 32768, -45 * 65536 - 16384, 0));
+        round_trip(SideData::Spherical(spherical));
+    }
+
+    #[test]
+    fn motion_vectors() {
+        let vector = MotionVector {
+            source: -1,
+            width: 16,
+            height: 8,
+            src_x: 3,
+            src_y: -4,
+            dst_x: 8,
+            dst_y: 0,
+            flags: 0,
+            motion_x: -20,
+            motion_y: 16,
+            motion_scale: 4,
+        };
+        let other = MotionVector {source: 1, dst_x: 24, ..vector};
+        round_trip(SideData::MotionVectors(vec![vector, other]));
+    }
+
+    #[test]
+    fn quality_stats() {
+        let stats = QualityStats {
+            quality: 0x0102_0304,
+            picture_type: sys::AVPictureType_AV_PICTURE_TYPE_I,
+            errors: vec![5, 0x0100],
+        };
+        let mut expected = vec![4, 3, 2, 1, sys::AVPictureType_AV_PICTURE_TYPE_I as u8, 2, 0, 0];
+        expected.extend_from_slice(&[5, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(SideData::QualityStats(stats.clone()).to_bytes(), expected);
+        round_trip(SideData::QualityStats(stats));
+    }
+
+    #[test]
+    fn skip_samples() {
+        let skip = SkipSamples {start: 1024, end: 0x0a0b_0c0d, start_reason: 1, end_reason: 2};
+        assert_eq!(SideData::SkipSamples(skip).to_bytes(), vec![0, 4, 0, 0, 0x0d, 0x0c, 0x0b, 0x0a, 1, 2]);
+        round_trip(SideData::SkipSamples(skip));
+    }
+
+    #[test]
+    fn bytes_and_raw() {
+        round_trip(SideData::A53Cc(vec![0xfc, 0x80, 0x80]));
+        round_trip(SideData::Afd(8));
+        round_trip(SideData::RawFrame(FrameSideDataType::IccProfile, vec![1, 2, 3]));
+        round_trip(SideData::RawPacket(PacketSideDataType::Palette, vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn too_short_is_raw() {
+        assert_eq!(
+            SideData::from_frame(FrameSideDataType::DisplayMatrix, &[0; 8]),
+            SideData::RawFrame(FrameSideDataType::DisplayMatrix, vec![0; 8]),
+        );
+        assert_eq!(
+            SideData::from_frame(FrameSideDataType::Afd, &[]),
+            SideData::RawFrame(FrameSideDataType::Afd, Vec::new()),
+        );
+        assert_eq!(
+            SideData::from_packet(PacketSideDataType::MasteringDisplayMetadata, &[0; 4]),
+            SideData::RawPacket(PacketSideDataType::MasteringDisplayMetadata, vec![0; 4]),
+        );
+        assert_eq!(
+            SideData::from_packet(PacketSideDataType::QualityStats, &[1, 2, 3]),
+            SideData::RawPacket(PacketSideDataType::QualityStats, vec![1, 2, 3]),
+        );
+        assert_eq!(
+            SideData::from_packet(PacketSideDataType::SkipSamples, &[0; 9]),
+            SideData::RawPacket(PacketSideDataType::SkipSamples, vec![0; 9]),
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Subtitle decoding and encoding (`AVSubtitle`), conversion between the
+//! text formats and extraction of embedded subtitle tracks.
+//!
+//! Text events are handled as ASS dialogue lines, which is what the text
+//! decoders produce and the text encoders expect. Bitmap subtitles (DVD,
+//! DVB, PGS) decode to indexed bitmaps and can only be extracted by stream
+//! copy, i.e. to `SubtitleFormat::Matroska`.
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::codec::{CodecContext, CodecParameters};
+use crate::extra::defs;
+use crate::extra::error::{Error, Result, check};
+use crate::extra::format::{InputContext, OutputContext};
+use crate::extra::io::{IoContext, SliceReader};
+use crate::extra::packet::Packet;
+use crate::extra::remux::{self, RemuxOptions, StreamSelector};
+use crate::extra::util::{c_str, from_c_str};
+
+/// A palettized picture, e.g. from DVD or DVB subtitles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    /// Position on the video.
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// `width * height` palette indices.
+    pub pixels: Vec<u8>,
+    /// RGBA.
+    pub palette: Vec<[u8; 4]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubtitleRect {
+    /// Plain text; lines are separated by `\n`.
+    Text(String),
+    /// An ASS dialogue line without timing:
+    /// `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`.
+    Ass(String),
+    Bitmap(Bitmap),
+}
+
+impl SubtitleRect {
+    /// The text without ASS markup; `None` for bitmaps.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            SubtitleRect::Text(x) => Some(x.clone()),
+            SubtitleRect::Ass(x) => Some(strip_ass(x.splitn(9, ',').nth(8).unwrap_or(""))),
+            SubtitleRect::Bitmap(_) => None,
+        }
+    }
+}
+
+/// One event; times are from the start of the input, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subtitle {
+    pub start: f64,
+    pub end: f64,
+    pub rects: Vec<SubtitleRect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubtitleTrack {
+    /// E.g. `subrip` or `dvd_subtitle`.
+    pub codec_name: String,
+    /// The ASS header (`[Script Info]`, styles) for text subtitles.
+    pub header: Option<String>,
+    pub events: Vec<Subtitle>,
+    /// Corrupt packets the decoder rejected; skipped like `ffmpeg` does.
+    pub decode_errors: u64,
+}
+
+/// Standalone subtitle formats this module writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+    Ass,
+    /// MP4 timed text, in an MP4 holding only the subtitle track.
+    MovText,
+    /// Any track copied as it is into a Matroska file holding only it
+    /// (`.mks`); the one target for bitmap subtitles. Encoded events are
+    /// written as ASS.
+    Matroska,
+}
+
+impl SubtitleFormat {
+    pub fn encoder_name(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "subrip",
+            SubtitleFormat::WebVtt => "webvtt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::MovText => "mov_text",
+            SubtitleFormat::Matroska => "ass",
+        }
+    }
+    pub fn muxer_name(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::WebVtt => "webvtt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::MovText => "mp4",
+            SubtitleFormat::Matroska => "matroska",
+        }
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::WebVtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::MovText => "mp4",
+            SubtitleFormat::Matroska => "mks",
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// What `ff_ass_subtitle_header_default` writes, for events without a
+/// header of their own.
+const DEFAULT_ASS_HEADER: &str = "\
+[Script Info]
+; Script generated by FFmpeg/Lavc
+ScriptType: v4.00+
+PlayResX: 384
+PlayResY: 288
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,16,&Hffffff,&Hffffff,&H0,&H0,0,0,0,0,100,100,0,0,1,1,0,2,10,10,10,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+/// Drops `{...}` override blocks and resolves `\N`, `\n` and `\h`.
+fn strip_ass(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_block = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => in_block = true,
+            '}' if in_block => in_block = false,
+            _ if in_block => {}
+            '\\' => match chars.peek() {
+                Some('N') | Some('n') => {
+                    chars.next();
+                    output.push('\n');
+                }
+                Some('h') => {
+                    chars.next();
+                    output.push(' ');
+                }
+                _ => output.push(c),
+            },
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// The dialogue line the text encoders take for `rect`.
+fn ass_line(rect: &SubtitleRect, read_order: usize) -> Result<String> {
+    match rect {
+        SubtitleRect::Ass(x) => Ok(x.clone()),
+        SubtitleRect::Text(x) => {
+            let text = x.replace("\r\n", "\n").replace('\n', "\\N");
+            Ok(format!("{},0,Default,,0,0,0,,{}", read_order, text))
+        }
+        SubtitleRect::Bitmap(_) => Err(Error::Invalid(String::from("bitmap subtitles can't be encoded as text"))),
+    }
+}
+
+unsafe fn rect_of(rect: &sys::AVSubtitleRect) -> Option<SubtitleRect> {
+    match rect.type_ {
+        sys::AVSubtitleType_SUBTITLE_TEXT => from_c_str(rect.text).map(SubtitleRect::Text),
+        sys::AVSubtitleType_SUBTITLE_ASS => from_c_str(rect.ass).map(SubtitleRect::Ass),
+        sys::AVSubtitleType_SUBTITLE_BITMAP => {
+            let (width, height) = (rect.w.max(0) as usize, rect.h.max(0) as usize);
+            let mut pixels = Vec::with_capacity(width * height);
+            for y in 0..height {
+                let line = rect.data[0].add(y * rect.linesize[0] as usize);
+                pixels.extend_from_slice(std::slice::from_raw_parts(line, width));
+            }
+            // AV_PIX_FMT_RGB32, I.E. NATIVE ENDIAN ARGB WORDS
+            let palette = if rect.data[1].is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(rect.data[1] as *const u32, rect.nb_colors.max(0) as usize)
+                    .iter()
+                    .map(|c| [(c >> 16) as u8, (c >> 8) as u8, *c as u8, (c >> 24) as u8])
+                    .collect()
+            };
+            Some(SubtitleRect::Bitmap(Bitmap {
+                x: rect.x,
+                y: rect.y,
+                width: rect.w,
+                height: rect.h,
+                pixels,
+                palette,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// An event whose end may only be known from the next one.
+struct Decoded {
+    start: f64,
+    end: Option<f64>,
+    rects: Vec<SubtitleRect>,
+}
+
+/// Decodes one packet (an empty one drains); `start_time` is the input's,
+/// in seconds. Corrupt packets are counted in `decode_errors` and skipped.
+fn decode_packet(
+    decoder: &mut CodecContext,
+    pkt: &mut Packet,
+    start_time: f64,
+    decode_errors: &mut u64,
+) -> Result<Option<Decoded>> {
+    unsafe {
+        let mut sub: sys::AVSubtitle = std::mem::zeroed();
+        let mut got: c_int = 0;
+        match check(sys::avcodec_decode_subtitle2(decoder.as_mut_ptr(), &mut sub, &mut got, pkt.as_mut_ptr())) {
+            // ONE BAD PACKET SHOULDN'T LOSE THE WHOLE TRACK
+            Err(err) if err.is_invalid_data() => {
+                *decode_errors += 1;
+                return Ok(None);
+            }
+            result => result?,
+        };
+        if got == 0 {
+            return Ok(None);
+        }
+        let base = if sub.pts != defs::av_nopts_value() {
+            sub.pts as f64 / sys::AV_TIME_BASE as f64 - start_time
+        } else {
+            0.0
+        };
+        let rects = (0..sub.num_rects as usize)
+            .filter_map(|i| rect_of(&**sub.rects.add(i)))
+            .collect();
+        // 0 AND UINT32_MAX BOTH MEAN "UNTIL THE NEXT EVENT"
+        let end = match sub.end_display_time {
+            0 | u32::MAX => None,
+            x => Some(base + x as f64 / 1000.0),
+        };
+        let decoded = Decoded {start: base + sub.start_display_time as f64 / 1000.0, end, rects};
+        sys::avsubtitle_free(&mut sub);
+        Ok(Some(decoded))
+    }
+}
+
+fn subtitle_stream(input: &mut InputContext, index: Option<usize>) -> Result<usize> {
+    match index {
+        Some(index) if index < input.nb_streams() => Ok(index),
+        Some(index) => Err(Error::Invalid(format!("no input stream {}", index))),
+        None => unsafe {
+            let index = sys::av_find_best_stream(
+                input.as_mut_ptr(),
+                MediaType::Subtitle.to_raw(),
+                -1,
+                -1,
+                std::ptr::null_mut(),
+                0,
+            );
+            if index < 0 {
+                return Err(Error::NotFound(String::from("subtitle stream")));
+            }
+            Ok(index as usize)
+        },
+    }
+}
+
+fn codec_name(id: sys::AVCodecID) -> String {
+    unsafe { from_c_str(sys::avcodec_get_name(id)).unwrap_or_default() }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DECODING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Decodes subtitle stream `index`, or the main subtitle stream.
+pub fn decode_subtitles(mut input: InputContext, index: Option<usize>) -> Result<SubtitleTrack> {
+    let index = subtitle_stream(&mut input, index)?;
+    let par = input.codec_parameters(index)?;
+    let mut decoder = CodecContext::new_decoder(par.codec_id())?;
+    par.to_context(&mut decoder)?;
+    unsafe {
+        (*decoder.as_mut_ptr()).pkt_timebase = input.stream_time_base(index);
+    }
+    decoder.open(&[])?;
+    let start_time = input.start_time() as f64 / sys::AV_TIME_BASE as f64;
+
+    let mut decoded = Vec::new();
+    let mut decode_errors = 0;
+    let mut pkt = Packet::new()?;
+    while input.read_packet(&mut pkt)? {
+        if pkt.stream_index() == index {
+            decoded.extend(decode_packet(&mut decoder, &mut pkt, start_time, &mut decode_errors)?);
+        }
+        pkt.unref();
+    }
+    let delayed = unsafe {
+        ((*(*decoder.as_ptr()).codec).capabilities & sys::AV_CODEC_CAP_DELAY as c_int) != 0
+    };
+    if delayed {
+        let mut empty = Packet::new()?;
+        while let Some(x) = decode_packet(&mut decoder, &mut empty, start_time, &mut decode_errors)? {
+            decoded.push(x);
+        }
+    }
+
+    // EMPTY EVENTS ONLY CLEAR THE PREVIOUS ONE (E.G. DVD SUBTITLES)
+    let mut events: Vec<Subtitle> = Vec::new();
+    for (i, event) in decoded.iter().enumerate() {
+        if event.rects.is_empty() {
+            continue;
+        }
+        let next = decoded.get(i + 1).map(|x| x.start);
+        let end = event.end.or(next).unwrap_or(event.start);
+        events.push(Subtitle {start: event.start, end, rects: event.rects.clone()});
+    }
+    let header = unsafe {
+        let raw = &*decoder.as_ptr();
+        if raw.subtitle_header.is_null() || raw.subtitle_header_size <= 0 {
+            None
+        } else {
+            let bytes = std::slice::from_raw_parts(raw.subtitle_header, raw.subtitle_header_size as usize);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    };
+    Ok(SubtitleTrack {codec_name: codec_name(par.codec_id()), header, events, decode_errors})
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// ENCODING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Longest encoded event; the text encoders fail rather than truncate.
+const MAX_SUBTITLE_SIZE: usize = 1024 * 1024;
+
+/// Writes text events as a standalone `format` file.
+pub fn encode_subtitles(track: &SubtitleTrack, format: SubtitleFormat) -> Result<Vec<u8>> {
+    let time_base = sys::AVRational {num: 1, den: 1000};
+    let mut encoder = CodecContext::new_encoder_by_name(format.encoder_name())?;
+    unsafe {
+        let raw = &mut *encoder.as_mut_ptr();
+        raw.time_base = time_base;
+        // THE TEXT ENCODERS READ THEIR STYLES FROM IT; FREED WITH THE CONTEXT
+        let header = track.header.as_ref().map(|x| x.as_str()).unwrap_or(DEFAULT_ASS_HEADER);
+        let ptr = sys::av_mallocz(header.len() + 1) as *mut u8;
+        if ptr.is_null() {
+            return Err(Error::Alloc("subtitle header"));
+        }
+        std::ptr::copy_nonoverlapping(header.as_ptr(), ptr, header.len());
+        raw.subtitle_header = ptr;
+        raw.subtitle_header_size = header.len() as c_int;
+    }
+    encoder.open(&[])?;
+
+    let (mut output, file) = OutputContext::in_memory(format.muxer_name())?;
+    let par = CodecParameters::from_context(&encoder)?;
+    output.add_stream(&par, time_base)?;
+    output.write_header(&[])?;
+    let out_time_base = output.stream_time_base(0);
+
+    let mut events = track.events.iter().collect::<Vec<_>>();
+    events.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+    let mut buffer = vec![0u8; MAX_SUBTITLE_SIZE];
+    for (read_order, event) in events.into_iter().enumerate() {
+        let start = (event.start.max(0.0) * 1000.0).round() as i64;
+        let end = (event.end.max(0.0) * 1000.0).round() as i64;
+        let lines = event.rects
+            .iter()
+            .map(|x| c_str(&ass_line(x, read_order)?))
+            .collect::<Result<Vec<_>>>()?;
+        let mut rects = lines
+            .iter()
+            .map(|line| unsafe {
+                let mut rect: sys::AVSubtitleRect = std::mem::zeroed();
+                rect.type_ = sys::AVSubtitleType_SUBTITLE_ASS;
+                // ONLY READ BY THE ENCODER
+                rect.ass = line.as_ptr() as *mut _;
+                rect
+            })
+            .collect::<Vec<_>>();
+        let mut rect_ptrs = rects.iter_mut().map(|x| x as *mut sys::AVSubtitleRect).collect::<Vec<_>>();
+        let size = unsafe {
+            let mut sub: sys::AVSubtitle = std::mem::zeroed();
+            sub.format = 1;
+            sub.end_display_time = (end - start).max(0) as u32;
+            sub.num_rects = rect_ptrs.len() as u32;
+            sub.rects = rect_ptrs.as_mut_ptr();
+            sub.pts = start * (sys::AV_TIME_BASE as i64 / 1000);
+            check(sys::avcodec_encode_subtitle(
+                encoder.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len() as c_int,
+                &sub,
+            ))?
+        };
+        let mut pkt = Packet::from_data(&buffer[..size as usize])?;
+        pkt.set_pts(start);
+        pkt.set_dts(start);
+        pkt.set_duration((end - start).max(0));
+        pkt.rescale_ts(time_base, out_time_base);
+        pkt.set_stream_index(0);
+        output.write_packet(&mut pkt)?;
+    }
+    output.write_trailer()?;
+    drop(output);
+    Ok(file.take())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// CONVERSION
+///////////////////////////////////////////////////////////////////////////////
+
+/// Subtitle stream `index` (or the main one) as a standalone `format`
+/// file. Tracks already in that format, and every track extracted to
+/// `SubtitleFormat::Matroska`, are copied as they are; others are decoded
+/// and re-encoded, which only works for text subtitles.
+pub fn extract_subtitles(mut input: InputContext, index: Option<usize>, format: SubtitleFormat) -> Result<Vec<u8>> {
+    let index = subtitle_stream(&mut input, index)?;
+    let codec = codec_name(input.codec_parameters(index)?.codec_id());
+    if format == SubtitleFormat::Matroska || codec == format.encoder_name() {
+        let (output, file) = OutputContext::in_memory(format.muxer_name())?;
+        let options = RemuxOptions {
+            streams: vec![StreamSelector::Index(index)],
+            // CUES KEEP THEIR TIMES, THE WAY `ffmpeg -c:s copy` WRITES THEM
+            copy_metadata: true,
+            normalize_timestamps: false,
+            ..RemuxOptions::default()
+        };
+        remux::remux_contexts(input, output, &options)?;
+        return Ok(file.take());
+    }
+    encode_subtitles(&decode_subtitles(input, Some(index))?, format)
+}
+
+/// Converts a subtitle file (any format the build demuxes, e.g. SRT,
+/// WebVTT or ASS) to `format`.
+pub fn convert_subtitles(data: &[u8], format: SubtitleFormat) -> Result<Vec<u8>> {
+    // THE IO CONTEXT NEVER OUTLIVES THIS CALL
+    let reader = unsafe { SliceReader::new(data) };
+    let input = InputContext::open_io(IoContext::from_seekable_reader(reader)?, None, &[])?;
+    extract_subtitles(input, None, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ass_drops_overrides() {
+        assert_eq!(strip_ass("{\\i1}Hello{\\i0}\\Nworld"), "Hello\nworld");
+        assert_eq!(strip_ass("a\\hb\\nc"), "a b\nc");
+        assert_eq!(strip_ass("C:\\path"), "C:\\path");
+        assert_eq!(strip_ass("x}y"), "x}y");
+    }
+
+    #[test]
+    fn ass_rect_text() {
+        let rect = SubtitleRect::Ass(String::from("0,0,Default,,0,0,0,,{\\b1}a, b{\\b0}\\Nc"));
+        assert_eq!(rect.text().unwrap(), "a, b\nc");
+        assert_eq!(SubtitleRect::Ass(String::from("0,0")).text().unwrap(), "");
+    }
+
+    #[test]
+    fn ass_line_of_rects() {
+        let text = SubtitleRect::Text(String::from("one\r\ntwo\nthree"));
+        assert_eq!(ass_line(&text, 3).unwrap(), "3,0,Default,,0,0,0,,one\\Ntwo\\Nthree");
+        let ass = SubtitleRect::Ass(String::from("7,1,Top,,0,0,0,,hi"));
+        assert_eq!(ass_line(&ass, 0).unwrap(), "7,1,Top,,0,0,0,,hi");
+        let bitmap = SubtitleRect::Bitmap(Bitmap {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            palette: Vec::new(),
+        });
+        assert!(ass_line(&bitmap, 0).is_err());
+    }
+}
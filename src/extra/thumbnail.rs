@@ -0,0 +1,328 @@
+//! Preview images and poster frames from videos.
+//!
+//! ```ignore
+//! let input = InputContext::open("movie.mp4")?;
+//! let options = ExtractOptions {resize: Some(Resize::fit(320, 320)), ..ExtractOptions::default()};
+//! for frame in extract_frames(input, Selection::Interval(10.0), &options)? {
+//!     let jpeg = frame?.encode(ImageCodec::Jpeg, &EncodeOptions::default())?;
+//! }
+//! ```
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::capabilities::MediaType;
+use crate::extra::decode::StreamDecoder;
+use crate::extra::defs;
+use crate::extra::error::{Error, Result};
+use crate::extra::filter::{FilterGraph, VideoInput};
+use crate::extra::format::{InputContext, SeekMode, Timestamp};
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::image::Image;
+use crate::extra::image::encode::{EncodeOptions, ImageCodec, encode_image};
+use crate::extra::image::transform::{self, Resize, Transform};
+
+/// Which frames `extract_frames` returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    /// The frames shown at these times, in seconds; times past the end
+    /// are skipped.
+    Timestamps(Vec<f64>),
+    /// One frame every so many seconds, starting with the first.
+    Interval(f64),
+    /// The `count` most representative frames, one per equal share of the
+    /// video, chosen by the `thumbnail` filter.
+    Representative {count: usize},
+    /// The first frame and every frame whose scene score (0 to 1) exceeds
+    /// `threshold`, e.g. 0.4.
+    SceneChanges {threshold: f64, max: Option<usize>},
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub resize: Option<Resize>,
+    /// Rotate frames as the stream's display matrix says, like players do.
+    pub autorotate: bool,
+    /// Decoder options, e.g. `threads`.
+    pub decoder_options: Vec<(String, String)>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            resize: None,
+            autorotate: true,
+            decoder_options: Vec::new(),
+        }
+    }
+}
+
+pub struct ExtractedFrame {
+    pub image: Image,
+    /// Presentation time in seconds, from the start of the input.
+    pub time: f64,
+}
+
+impl ExtractedFrame {
+    pub fn encode(&self, codec: ImageCodec, options: &EncodeOptions) -> Result<Vec<u8>> {
+        encode_image(&self.image, codec, options)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// HELPERS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Counterclockwise degrees from the stream's display matrix, 0 if none.
+unsafe fn display_rotation(stream: *const sys::AVStream) -> f64 {
+    let mut size: c_int = 0;
+    let matrix = sys::av_stream_get_side_data(
+        stream,
+        sys::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX,
+        &mut size,
+    );
+    if matrix.is_null() || (size as usize) < 9 * 4 {
+        return 0.0;
+    }
+    let rotation = sys::av_display_rotation_get(matrix as *const i32);
+    if rotation.is_nan() { 0.0 } else { rotation }
+}
+
+/// The filters `ffmpeg` inserts for `-autorotate`.
+fn rotation_filters(rotation: f64) -> Option<&'static str> {
+    // THE MATRIX IS COUNTERCLOCKWISE, THE FILTERS NAME THE CORRECTION
+    let theta = (-rotation).rem_euclid(360.0).round();
+    if (theta - 90.0).abs() < 1.0 {
+        Some("transpose=clock")
+    } else if (theta - 180.0).abs() < 1.0 {
+        Some("hflip,vflip")
+    } else if (theta - 270.0).abs() < 1.0 {
+        Some("transpose=cclock")
+    } else {
+        None
+    }
+}
+
+/// Rough frame count, for sizing `thumbnail` batches.
+unsafe fn estimated_frames(input: &InputContext, index: usize) -> Option<i64> {
+    let stream = &*input.stream_ptr(index);
+    if stream.nb_frames > 0 {
+        return Some(stream.nb_frames);
+    }
+    let duration = (*input.as_ptr()).duration;
+    let rate = stream.avg_frame_rate;
+    if duration <= 0 || duration == defs::av_nopts_value() || rate.num <= 0 || rate.den <= 0 {
+        return None;
+    }
+    Some(duration * rate.num as i64 / rate.den as i64 / sys::AV_TIME_BASE as i64)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// EXTRACTION
+///////////////////////////////////////////////////////////////////////////////
+
+/// Yields the selected frames, see `extract_frames`.
+pub struct Frames {
+    decoder: StreamDecoder,
+    graph: FilterGraph,
+    resize: Option<Resize>,
+    /// Remaining `Selection::Timestamps`, in reverse; `None` when the
+    /// selection is done by the graph.
+    timestamps: Option<Vec<f64>>,
+    remaining: Option<usize>,
+    start_time: f64,
+    eof: bool,
+}
+
+impl Frames {
+    fn to_extracted(&self, frame: Frame) -> Result<ExtractedFrame> {
+        let time_base = self.graph.output_time_base("out")?;
+        let time = frame.pts() as f64 * time_base.num as f64 / time_base.den as f64 - self.start_time;
+        let mut image = Image::from_frame(&frame)?;
+        if let Some(resize) = self.resize {
+            let options = Transform {resize: Some(resize), pad_to_even: false, ..Transform::default()};
+            image = transform::transform(&image, &options)?;
+        }
+        Ok(ExtractedFrame {image, time})
+    }
+    /// Next frame out of the graph, decoding as much as needed.
+    fn next_filtered(&mut self) -> Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.graph.pull("out")? {
+                return Ok(Some(frame));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.decoder.next_frame()? {
+                Some(frame) => self.graph.push("in", &frame)?,
+                None => {
+                    self.graph.push_eof("in")?;
+                    self.eof = true;
+                }
+            }
+        }
+    }
+    /// Next frame at one of the requested timestamps.
+    fn next_seeked(&mut self) -> Result<Option<Frame>> {
+        while let Some(time) = self.timestamps.as_mut().and_then(|x| x.pop()) {
+            self.decoder.seek(Timestamp::Seconds(time), SeekMode::Exact)?;
+            // `None` IS PAST THE END
+            if let Some(frame) = self.decoder.next_frame()? {
+                // STATELESS FILTERS ONLY, SO ONE FRAME IN IS ONE FRAME OUT
+                self.graph.push("in", &frame)?;
+                return self.graph.pull("out");
+            }
+        }
+        Ok(None)
+    }
+    fn next_extracted(&mut self) -> Result<Option<ExtractedFrame>> {
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        let frame = if self.timestamps.is_some() {
+            self.next_seeked()?
+        } else {
+            self.next_filtered()?
+        };
+        match frame {
+            Some(frame) => {
+                self.remaining = self.remaining.map(|x| x - 1);
+                Ok(Some(self.to_extracted(frame)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<ExtractedFrame>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_extracted() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => None,
+            Err(err) => {
+                self.remaining = Some(0);
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// The decoder and what the selection filters need to know about its
+/// stream.
+struct Source {
+    decoder: StreamDecoder,
+    rotation: f64,
+    frame_count: Option<i64>,
+    start_time: f64,
+}
+
+fn open_source(input: InputContext, options: &ExtractOptions) -> Result<Source> {
+    let decoder_options = options.decoder_options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    let decoder = StreamDecoder::best(input, MediaType::Video, &decoder_options)?;
+    let index = decoder.stream_index();
+    unsafe {
+        let input = decoder.input();
+        let start_time = input.start_time() as f64 / sys::AV_TIME_BASE as f64;
+        let rotation = display_rotation(input.stream_ptr(index));
+        let frame_count = estimated_frames(input, index);
+        Ok(Source {decoder, rotation, frame_count, start_time})
+    }
+}
+
+fn into_frames(
+    source: Source,
+    mut filters: Vec<String>,
+    timestamps: Option<Vec<f64>>,
+    remaining: Option<usize>,
+    options: &ExtractOptions,
+) -> Result<Frames> {
+    if options.autorotate {
+        filters.extend(rotation_filters(source.rotation).map(String::from));
+    }
+    if filters.is_empty() {
+        filters.push(String::from("null"));
+    }
+    let decoder = source.decoder;
+    let (format, sample_aspect_ratio, frame_rate) = unsafe {
+        let raw = &*decoder.decoder().as_ptr();
+        let stream = &*decoder.input().stream_ptr(decoder.stream_index());
+        let format = PictureFormat {width: raw.width, height: raw.height, pix_fmt: raw.pix_fmt};
+        (format, raw.sample_aspect_ratio, stream.avg_frame_rate)
+    };
+    let graph = FilterGraph::new_video(
+        &filters.join(","),
+        VideoInput {
+            format,
+            time_base: decoder.time_base(),
+            sample_aspect_ratio,
+            frame_rate: Some(frame_rate).filter(|x| x.num > 0 && x.den > 0),
+        },
+        &[],
+    )?;
+    Ok(Frames {
+        decoder,
+        graph,
+        resize: options.resize,
+        timestamps,
+        remaining,
+        start_time: source.start_time,
+        eof: false,
+    })
+}
+
+/// The `thumbnail` filter holds a whole batch of frames, so long videos
+/// are subsampled first rather than analysed in huge batches.
+const MAX_THUMBNAIL_BATCH: i64 = 100;
+
+/// Decodes the main video stream of `input` and yields the selected frames
+/// as images, rotated and resized per `options`.
+pub fn extract_frames(input: InputContext, selection: Selection, options: &ExtractOptions) -> Result<Frames> {
+    let source = open_source(input, options)?;
+    let mut filters = Vec::new();
+    match selection {
+        Selection::Timestamps(mut times) => {
+            times.reverse();
+            into_frames(source, filters, Some(times), None, options)
+        }
+        Selection::Interval(seconds) => {
+            if !(seconds > 0.0) {
+                return Err(Error::Invalid(format!("frame interval {}", seconds)));
+            }
+            filters.push(format!("select='isnan(prev_selected_t)+gte(t-prev_selected_t\\,{})'", seconds));
+            into_frames(source, filters, None, None, options)
+        }
+        Selection::Representative {count} => {
+            if count == 0 {
+                return Err(Error::Invalid(String::from("zero representative frames")));
+            }
+            let per_frame = source.frame_count.map(|x| x / count as i64).unwrap_or(MAX_THUMBNAIL_BATCH);
+            let batch = per_frame.min(MAX_THUMBNAIL_BATCH).max(1);
+            let step = (per_frame / batch).max(1);
+            if step > 1 {
+                filters.push(format!("select='not(mod(n\\,{}))'", step));
+            }
+            filters.push(format!("thumbnail=n={}", batch));
+            into_frames(source, filters, None, Some(count), options)
+        }
+        Selection::SceneChanges {threshold, max} => {
+            filters.push(format!("select='eq(n\\,0)+gt(scene\\,{})'", threshold));
+            into_frames(source, filters, None, max, options)
+        }
+    }
+}
+
+/// The most representative of the first hundred or so frames, e.g. for a
+/// video's poster image, without decoding the whole video.
+pub fn poster_frame(input: InputContext, options: &ExtractOptions) -> Result<Image> {
+    let source = open_source(input, options)?;
+    let batch = source.frame_count.unwrap_or(MAX_THUMBNAIL_BATCH).min(MAX_THUMBNAIL_BATCH).max(1);
+    let filters = vec![format!("thumbnail=n={}", batch)];
+    let mut frames = into_frames(source, filters, None, Some(1), options)?;
+    match frames.next() {
+        Some(frame) => Ok(frame?.image),
+        None => Err(Error::Invalid(String::from("no video frame could be decoded"))),
+    }
+}
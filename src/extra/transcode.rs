@@ -0,0 +1,560 @@
+//! Decode → filter → encode → mux, with a plan per input stream.
+//!
+//! ```ignore
+//! let input = InputContext::open("in.mkv")?;
+//! let output = OutputContext::new("out.mp4", None)?;
+//! let mut transcoder = Transcoder::new(input, output)?;
+//! let mut video = EncoderSettings::new("mpeg4");
+//! video.filter = Some(String::from("scale=640:-2"));
+//! transcoder.set_plan_for(MediaType::Video, StreamPlan::Transcode(video));
+//! transcoder.on_progress(|x| println!("{:.1}s", x.time));
+//! transcoder.run()?;
+//! ```
+use std::os::raw::c_int;
+use crate::sys;
+use crate::extra::audio_fifo::AudioFifo;
+use crate::extra::capabilities::{CodecInfo, MediaType};
+use crate::extra::codec::{CodecContext, CodecParameters};
+use crate::extra::defs;
+use crate::extra::error::{Error, Result};
+use crate::extra::filter::{AudioInput, AudioOutput, FilterGraph, VideoInput};
+use crate::extra::format::{AV_TIME_BASE_Q, InputContext, OutputContext};
+use crate::extra::frame::{Frame, PictureFormat};
+use crate::extra::packet::Packet;
+use crate::extra::remux::{self, CopiedStream};
+use crate::extra::resample::{AudioFormat, normalize_channel_layout};
+
+/// What happens to one input stream.
+#[derive(Debug, Clone)]
+pub enum StreamPlan {
+    /// Packets are copied without decoding, as `remux` does.
+    Copy,
+    Transcode(EncoderSettings),
+    /// Left out of the output.
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct EncoderSettings {
+    /// Encoder name, e.g. `mpeg4` or `aac`.
+    pub encoder: String,
+    /// Filtergraph between decoder and encoder, e.g. `scale=640:-2,fps=30`.
+    /// Pixel/sample format conversion to what the encoder takes is added
+    /// automatically.
+    pub filter: Option<String>,
+    pub bit_rate: Option<i64>,
+    /// Audio only; otherwise the input rate, if the encoder supports it.
+    pub sample_rate: Option<i32>,
+    /// Encoder options, e.g. `("g", "50")` or codec private ones.
+    pub options: Vec<(String, String)>,
+}
+
+impl EncoderSettings {
+    pub fn new(encoder: &str) -> Self {
+        EncoderSettings {
+            encoder: String::from(encoder),
+            filter: None,
+            bit_rate: None,
+            sample_rate: None,
+            options: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// Input position in seconds, from the last packet read.
+    pub time: f64,
+    /// Input duration in seconds, if known.
+    pub duration: Option<f64>,
+    pub packets_written: u64,
+    /// Corrupt packets the decoders rejected; skipped like `ffmpeg` does.
+    pub decode_errors: u64,
+}
+
+impl Progress {
+    /// From 0 to 1, if the duration is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.duration
+            .filter(|x| *x > 0.0)
+            .map(|x| (self.time / x).max(0.0).min(1.0))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TRANSCODED STREAMS
+///////////////////////////////////////////////////////////////////////////////
+
+struct TranscodedStream {
+    out_index: usize,
+    media_type: MediaType,
+    decoder: CodecContext,
+    graph: FilterGraph,
+    encoder: CodecContext,
+    /// Re-chunks audio for encoders with a fixed frame size.
+    fifo: Option<AudioFifo>,
+    /// Audio: pts of the next sample leaving the FIFO. Video: pts of the
+    /// last frame sent, in the encoder time base.
+    next_pts: i64,
+    decode_errors: u64,
+}
+
+enum OutputStream {
+    Copied(CopiedStream),
+    Transcoded(Box<TranscodedStream>),
+}
+
+fn is_valid(x: sys::AVRational) -> bool {
+    x.num > 0 && x.den > 0
+}
+
+fn open_decoder(input: &mut InputContext, index: usize, par: &CodecParameters) -> Result<CodecContext> {
+    let mut decoder = CodecContext::new_decoder(par.codec_id())?;
+    par.to_context(&mut decoder)?;
+    unsafe {
+        let raw = &mut *decoder.as_mut_ptr();
+        raw.pkt_timebase = input.stream_time_base(index);
+        if par.codec_type() == sys::AVMediaType_AVMEDIA_TYPE_VIDEO {
+            raw.framerate = sys::av_guess_frame_rate(
+                input.as_mut_ptr(),
+                input.stream_ptr(index),
+                std::ptr::null_mut(),
+            );
+        }
+    }
+    decoder.open(&[])?;
+    Ok(decoder)
+}
+
+fn video_graph(
+    decoder: &CodecContext,
+    in_time_base: sys::AVRational,
+    settings: &EncoderSettings,
+    encoder: &mut CodecContext,
+) -> Result<FilterGraph> {
+    let codec = unsafe { CodecInfo::from_raw((*encoder.as_ptr()).codec) };
+    let (format, sample_aspect_ratio, frame_rate) = unsafe {
+        let raw = &*decoder.as_ptr();
+        let format = PictureFormat {width: raw.width, height: raw.height, pix_fmt: raw.pix_fmt};
+        (format, raw.sample_aspect_ratio, raw.framerate)
+    };
+    let graph = FilterGraph::new_video(
+        settings.filter.as_ref().map(|x| x.as_str()).unwrap_or("null"),
+        VideoInput {
+            format,
+            time_base: in_time_base,
+            sample_aspect_ratio,
+            frame_rate: Some(frame_rate).filter(|x| is_valid(*x)),
+        },
+        &codec.pix_fmts,
+    )?;
+    let format = graph.output_picture_format("out")?;
+    let mut frame_rate = graph.output_frame_rate("out")?;
+    if !is_valid(frame_rate) {
+        frame_rate = sys::AVRational {num: 25, den: 1};
+    }
+    unsafe {
+        let raw = &mut *encoder.as_mut_ptr();
+        raw.width = format.width;
+        raw.height = format.height;
+        raw.pix_fmt = format.pix_fmt;
+        raw.sample_aspect_ratio = graph.output_sample_aspect_ratio("out")?;
+        raw.framerate = frame_rate;
+        raw.time_base = sys::AVRational {num: frame_rate.den, den: frame_rate.num};
+    }
+    Ok(graph)
+}
+
+fn audio_graph(
+    decoder: &CodecContext,
+    in_time_base: sys::AVRational,
+    settings: &EncoderSettings,
+    encoder: &mut CodecContext,
+) -> Result<FilterGraph> {
+    let codec = unsafe { CodecInfo::from_raw((*encoder.as_ptr()).codec) };
+    let format = unsafe {
+        let raw = &*decoder.as_ptr();
+        AudioFormat {
+            sample_fmt: raw.sample_fmt,
+            sample_rate: raw.sample_rate,
+            channel_layout: normalize_channel_layout(raw.channel_layout, raw.channels),
+        }
+    };
+    let sample_rates = match settings.sample_rate {
+        Some(sample_rate) => vec![sample_rate],
+        None if codec.sample_rates.contains(&format.sample_rate) => vec![format.sample_rate],
+        None => codec.sample_rates.clone(),
+    };
+    let graph = FilterGraph::new_audio(
+        settings.filter.as_ref().map(|x| x.as_str()).unwrap_or("anull"),
+        AudioInput {format, time_base: in_time_base},
+        AudioOutput {
+            sample_fmts: codec.sample_fmts.clone(),
+            sample_rates,
+            channel_layouts: codec.channel_layouts.clone(),
+        },
+    )?;
+    let format = graph.output_audio_format("out")?;
+    unsafe {
+        let raw = &mut *encoder.as_mut_ptr();
+        raw.sample_fmt = format.sample_fmt;
+        raw.sample_rate = format.sample_rate;
+        raw.channel_layout = format.channel_layout;
+        raw.channels = format.channels();
+        raw.time_base = sys::AVRational {num: 1, den: format.sample_rate};
+    }
+    Ok(graph)
+}
+
+impl TranscodedStream {
+    fn new(
+        input: &mut InputContext,
+        index: usize,
+        output: &mut OutputContext,
+        settings: &EncoderSettings,
+    ) -> Result<Self> {
+        let par = input.codec_parameters(index)?;
+        let media_type = MediaType::from_raw(par.codec_type());
+        let in_time_base = input.stream_time_base(index);
+        let decoder = open_decoder(input, index, &par)?;
+        let mut encoder = CodecContext::new_encoder_by_name(&settings.encoder)?;
+        let graph = match media_type {
+            MediaType::Video => video_graph(&decoder, in_time_base, settings, &mut encoder)?,
+            MediaType::Audio => audio_graph(&decoder, in_time_base, settings, &mut encoder)?,
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "stream {} is {}, only audio and video can be transcoded",
+                    index,
+                    media_type.name(),
+                )));
+            }
+        };
+        unsafe {
+            let raw = &mut *encoder.as_mut_ptr();
+            if let Some(bit_rate) = settings.bit_rate {
+                raw.bit_rate = bit_rate;
+            }
+            if (*(*output.as_ptr()).oformat).flags & sys::AVFMT_GLOBALHEADER as c_int != 0 {
+                raw.flags |= sys::AV_CODEC_FLAG_GLOBAL_HEADER as c_int;
+            }
+        }
+        let options = settings.options
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        encoder.open(&options)?;
+        let (time_base, fifo) = unsafe {
+            let raw = &*encoder.as_ptr();
+            let variable = (*raw.codec).capabilities & sys::AV_CODEC_CAP_VARIABLE_FRAME_SIZE as c_int != 0;
+            let fifo = if media_type == MediaType::Audio && !variable && raw.frame_size > 0 {
+                Some(AudioFifo::new(graph.output_audio_format("out")?)?)
+            } else {
+                None
+            };
+            (raw.time_base, fifo)
+        };
+        let out_index = output.add_stream(&CodecParameters::from_context(&encoder)?, time_base)?;
+        Ok(TranscodedStream {
+            out_index,
+            media_type,
+            decoder,
+            graph,
+            encoder,
+            fifo,
+            next_pts: unsafe { defs::av_nopts_value() },
+            decode_errors: 0,
+        })
+    }
+    fn encoder_time_base(&self) -> sys::AVRational {
+        unsafe { (*self.encoder.as_ptr()).time_base }
+    }
+    fn frame_size(&self) -> usize {
+        unsafe { (*self.encoder.as_ptr()).frame_size as usize }
+    }
+    fn send_packet(&mut self, output: &mut OutputContext, pkt: &Packet) -> Result<u64> {
+        match self.decoder.send_packet(pkt) {
+            Err(err) if err.is_invalid_data() => self.decode_errors += 1,
+            result => result?,
+        }
+        self.receive_decoded(output)
+    }
+    fn receive_decoded(&mut self, output: &mut OutputContext) -> Result<u64> {
+        let mut written = 0;
+        let mut frame = Frame::new()?;
+        loop {
+            match self.decoder.receive_frame(&mut frame) {
+                Ok(true) => {}
+                Ok(false) => break,
+                // ONE BAD PACKET SHOULDN'T END THE WHOLE JOB
+                Err(err) if err.is_invalid_data() => {
+                    self.decode_errors += 1;
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+            let pts = frame.best_effort_timestamp();
+            frame.set_pts(pts);
+            self.graph.push("in", &frame)?;
+            written += self.receive_filtered(output)?;
+        }
+        Ok(written)
+    }
+    fn receive_filtered(&mut self, output: &mut OutputContext) -> Result<u64> {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let graph_time_base = self.graph.output_time_base("out")?;
+        let encoder_time_base = self.encoder_time_base();
+        let mut written = 0;
+        while let Some(mut frame) = self.graph.pull("out")? {
+            if frame.pts() != nopts {
+                let pts = unsafe { sys::av_rescale_q(frame.pts(), graph_time_base, encoder_time_base) };
+                frame.set_pts(pts);
+            }
+            if self.fifo.is_some() {
+                written += self.buffer_audio(output, &frame)?;
+                continue;
+            }
+            if self.media_type == MediaType::Video && frame.pts() != nopts {
+                // VARIABLE FRAME RATE INPUT CAN MAP TWO FRAMES ONTO ONE TICK
+                if self.next_pts != nopts && frame.pts() <= self.next_pts {
+                    continue;
+                }
+                self.next_pts = frame.pts();
+            }
+            unsafe {
+                // LET THE ENCODER PICK ITS OWN FRAME TYPES
+                (*frame.as_mut_ptr()).pict_type = sys::AVPictureType_AV_PICTURE_TYPE_NONE;
+            }
+            self.encoder.send_frame(&frame)?;
+            written += self.receive_encoded(output)?;
+        }
+        Ok(written)
+    }
+    /// Queues `frame` and encodes every full `frame_size` chunk.
+    fn buffer_audio(&mut self, output: &mut OutputContext, frame: &Frame) -> Result<u64> {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let fifo = self.fifo.as_mut().expect("audio FIFO");
+        if fifo.is_empty() && frame.pts() != nopts {
+            self.next_pts = frame.pts();
+        } else if self.next_pts == nopts {
+            self.next_pts = 0;
+        }
+        fifo.write(frame)?;
+        let frame_size = self.frame_size();
+        let mut written = 0;
+        while self.fifo.as_ref().map(|x| x.len() >= frame_size).unwrap_or(false) {
+            written += self.encode_fifo(output, frame_size)?;
+        }
+        Ok(written)
+    }
+    fn encode_fifo(&mut self, output: &mut OutputContext, nb_samples: usize) -> Result<u64> {
+        let chunk = self.fifo.as_mut().and_then(|x| x.read(nb_samples).transpose());
+        match chunk {
+            Some(chunk) => {
+                let mut chunk = chunk?;
+                chunk.set_pts(self.next_pts);
+                self.next_pts += chunk.nb_samples() as i64;
+                self.encoder.send_frame(&chunk)?;
+                self.receive_encoded(output)
+            }
+            None => Ok(0),
+        }
+    }
+    fn receive_encoded(&mut self, output: &mut OutputContext) -> Result<u64> {
+        let time_base = self.encoder_time_base();
+        let mut written = 0;
+        let mut pkt = Packet::new()?;
+        while self.encoder.receive_packet(&mut pkt)? {
+            pkt.set_stream_index(self.out_index);
+            pkt.rescale_ts(time_base, output.stream_time_base(self.out_index));
+            output.write_packet(&mut pkt)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+    /// Flushes the decoder, graph, FIFO and encoder, in that order.
+    fn finish(&mut self, output: &mut OutputContext) -> Result<u64> {
+        self.decoder.send_eof()?;
+        let mut written = self.receive_decoded(output)?;
+        self.graph.push_eof("in")?;
+        written += self.receive_filtered(output)?;
+        let frame_size = self.frame_size();
+        // THE LAST CHUNK MAY BE SHORT
+        while self.fifo.as_ref().map(|x| !x.is_empty()).unwrap_or(false) {
+            written += self.encode_fifo(output, frame_size)?;
+        }
+        self.encoder.send_eof()?;
+        written += self.receive_encoded(output)?;
+        Ok(written)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TRANSCODER
+///////////////////////////////////////////////////////////////////////////////
+
+pub struct Transcoder {
+    input: InputContext,
+    output: OutputContext,
+    plans: Vec<StreamPlan>,
+    muxer_options: Vec<(String, String)>,
+    progress: Option<Box<dyn FnMut(&Progress)>>,
+}
+
+impl Transcoder {
+    /// Starts out copying every audio, video and subtitle stream the output
+    /// format accepts, and dropping the rest.
+    pub fn new(input: InputContext, output: OutputContext) -> Result<Self> {
+        let mut plans = Vec::new();
+        for index in 0..input.nb_streams() {
+            let par = input.codec_parameters(index)?;
+            let copyable = match MediaType::from_raw(par.codec_type()) {
+                MediaType::Video | MediaType::Audio | MediaType::Subtitle => unsafe {
+                    // 0 MEANS KNOWN TO BE UNSUPPORTED, < 0 MEANS UNKNOWN
+                    sys::avformat_query_codec((*output.as_ptr()).oformat, par.codec_id(), 0) != 0
+                },
+                _ => false,
+            };
+            plans.push(if copyable { StreamPlan::Copy } else { StreamPlan::Drop });
+        }
+        Ok(Transcoder {
+            input,
+            output,
+            plans,
+            muxer_options: Vec::new(),
+            progress: None,
+        })
+    }
+    pub fn plan(&self, index: usize) -> Option<&StreamPlan> {
+        self.plans.get(index)
+    }
+    pub fn set_plan(&mut self, index: usize, plan: StreamPlan) -> Result<()> {
+        match self.plans.get_mut(index) {
+            Some(x) => {
+                *x = plan;
+                Ok(())
+            }
+            None => Err(Error::Invalid(format!("no input stream {}", index))),
+        }
+    }
+    /// Applies `plan` to every input stream of `media_type`.
+    pub fn set_plan_for(&mut self, media_type: MediaType, plan: StreamPlan) {
+        for index in 0..self.plans.len() {
+            let codec_type = unsafe { (*(*self.input.stream_ptr(index)).codecpar).codec_type };
+            if MediaType::from_raw(codec_type) == media_type {
+                self.plans[index] = plan.clone();
+            }
+        }
+    }
+    /// E.g. `movflags=+faststart`.
+    pub fn set_muxer_options(&mut self, options: &[(&str, &str)]) {
+        self.muxer_options = options
+            .iter()
+            .map(|(k, v)| (String::from(*k), String::from(*v)))
+            .collect();
+    }
+    /// Called after every input packet.
+    pub fn on_progress<F: FnMut(&Progress) + 'static>(&mut self, f: F) {
+        self.progress = Some(Box::new(f));
+    }
+    fn setup(&mut self) -> Result<Vec<Option<OutputStream>>> {
+        let mut streams = Vec::new();
+        for index in 0..self.plans.len() {
+            let stream = match &self.plans[index] {
+                StreamPlan::Copy => {
+                    let par = self.input.codec_parameters(index)?;
+                    let in_time_base = self.input.stream_time_base(index);
+                    let stream = remux::add_copied_stream(&mut self.output, &par, in_time_base)?;
+                    Some(OutputStream::Copied(stream))
+                }
+                StreamPlan::Transcode(settings) => {
+                    let stream = TranscodedStream::new(&mut self.input, index, &mut self.output, settings)?;
+                    Some(OutputStream::Transcoded(Box::new(stream)))
+                }
+                StreamPlan::Drop => None,
+            };
+            streams.push(stream);
+        }
+        if self.output.nb_streams() == 0 {
+            return Err(Error::Invalid(String::from("every stream is dropped")));
+        }
+        Ok(streams)
+    }
+    /// `pkt` has to be shifted already, see `run`.
+    fn progress_at(&self, pkt: &Packet) -> Progress {
+        let nopts = unsafe { defs::av_nopts_value() };
+        let time_base = self.input.stream_time_base(pkt.stream_index());
+        let duration = unsafe { (*self.input.as_ptr()).duration };
+        let ts = if pkt.dts() != nopts { pkt.dts() } else { pkt.pts() };
+        let mut time = 0.0;
+        if ts != nopts {
+            time = ts as f64 * time_base.num as f64 / time_base.den as f64;
+        }
+        Progress {
+            time: time.max(0.0),
+            duration: Some(duration)
+                .filter(|x| *x != nopts && *x > 0)
+                .map(|x| x as f64 / sys::AV_TIME_BASE as f64),
+            packets_written: 0,
+            decode_errors: 0,
+        }
+    }
+    /// Writes the header, every packet and the trailer. Like `ffmpeg`
+    /// without `-copyts`, timestamps are shifted by the input's start time.
+    pub fn run(mut self) -> Result<()> {
+        let mut streams = self.setup()?;
+        let muxer_options = self.muxer_options
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        self.output.write_header(&muxer_options)?;
+        let nopts = unsafe { defs::av_nopts_value() };
+        let start_time = self.input.start_time();
+        let mut packets_written = 0;
+        let mut pkt = Packet::new()?;
+        while self.input.read_packet(&mut pkt)? {
+            if start_time != 0 {
+                let time_base = self.input.stream_time_base(pkt.stream_index());
+                let shift = unsafe { sys::av_rescale_q(start_time, AV_TIME_BASE_Q, time_base) };
+                if pkt.pts() != nopts {
+                    pkt.set_pts(pkt.pts() - shift);
+                }
+                if pkt.dts() != nopts {
+                    pkt.set_dts(pkt.dts() - shift);
+                }
+            }
+            let progress = self.progress_at(&pkt);
+            match streams.get_mut(pkt.stream_index()) {
+                Some(Some(OutputStream::Copied(stream))) => {
+                    remux::copy_packet(&mut self.output, stream, &mut pkt)?;
+                    packets_written += 1;
+                }
+                Some(Some(OutputStream::Transcoded(stream))) => {
+                    packets_written += stream.send_packet(&mut self.output, &pkt)?;
+                }
+                _ => {}
+            }
+            if self.progress.is_some() {
+                let decode_errors = streams
+                    .iter()
+                    .filter_map(|x| match x {
+                        Some(OutputStream::Transcoded(stream)) => Some(stream.decode_errors),
+                        _ => None,
+                    })
+                    .sum();
+                if let Some(f) = self.progress.as_mut() {
+                    f(&Progress {packets_written, decode_errors, ..progress});
+                }
+            }
+        }
+        for stream in streams.iter_mut().filter_map(|x| x.as_mut()) {
+            match stream {
+                OutputStream::Copied(stream) => remux::finish_copied(&mut self.output, stream)?,
+                OutputStream::Transcoded(stream) => {
+                    stream.finish(&mut self.output)?;
+                }
+            }
+        }
+        self.output.write_trailer()?;
+        Ok(())
+    }
+}
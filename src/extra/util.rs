@@ -0,0 +1,63 @@
+//! Internal FFI helpers.
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use crate::sys;
+use crate::extra::error::{Error, Result, check};
+
+pub(crate) fn c_str(s: &str) -> Result<CString> {
+    CString::new(s).map_err(Error::from)
+}
+
+/// Copies a (possibly null) C string.
+pub(crate) unsafe fn from_c_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+/// Builds an `AVDictionary` from key/value pairs; the caller must free it
+/// with `av_dict_free`.
+pub(crate) unsafe fn to_av_dict(pairs: &[(&str, &str)]) -> Result<*mut sys::AVDictionary> {
+    let mut dict: *mut sys::AVDictionary = std::ptr::null_mut();
+    for (key, value) in pairs {
+        let set = |dict: &mut *mut sys::AVDictionary| -> Result<()> {
+            let key = c_str(key)?;
+            let value = c_str(value)?;
+            check(sys::av_dict_set(dict, key.as_ptr(), value.as_ptr(), 0))?;
+            Ok(())
+        };
+        if let Err(err) = set(&mut dict) {
+            sys::av_dict_free(&mut dict);
+            return Err(err);
+        }
+    }
+    Ok(dict)
+}
+
+/// Copies every entry of an `AVDictionary`.
+pub(crate) unsafe fn from_av_dict(dict: *const sys::AVDictionary) -> Vec<(String, String)> {
+    let mut output = Vec::new();
+    let mut entry: *mut sys::AVDictionaryEntry = std::ptr::null_mut();
+    let empty = b"\0".as_ptr() as *const c_char;
+    loop {
+        entry = sys::av_dict_get(dict, empty, entry, sys::AV_DICT_IGNORE_SUFFIX as i32);
+        if entry.is_null() {
+            break;
+        }
+        let key = from_c_str((*entry).key).unwrap_or_default();
+        let value = from_c_str((*entry).value).unwrap_or_default();
+        output.push((key, value));
+    }
+    output
+}
+
+/// Borrows a (possibly null) buffer.
+pub(crate) unsafe fn raw_bytes<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, size)
+    }
+}
@@ -0,0 +1,288 @@
+//! Waveform peaks and rendered waveform/spectrogram images of an audio
+//! stream, e.g. for audio players and editors.
+use crate::extra::audio::{self, AudioTarget, SampleType, Samples};
+use crate::extra::capabilities::MediaType;
+use crate::extra::decode::StreamDecoder;
+use crate::extra::error::{Error, Result};
+use crate::extra::filter::{AudioInput, FilterGraph};
+use crate::extra::format::InputContext;
+use crate::extra::image::Image;
+
+/// How the audio is split into peaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buckets {
+    /// One peak per this many samples (per channel).
+    Samples(usize),
+    /// About this many peaks for the whole stream, fewer for very short
+    /// streams.
+    Count(usize),
+}
+
+/// Sample statistics of one bucket, from -1.0 to 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    pub sample_rate: i32,
+    pub channel_layout: u64,
+    /// Average length of a bucket, in seconds.
+    pub bucket_duration: f64,
+    /// The peaks of each channel.
+    pub channels: Vec<Vec<Peak>>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PEAKS
+///////////////////////////////////////////////////////////////////////////////
+
+/// Granularity `Buckets::Count` measures at before merging.
+const FINE_BUCKET: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    min: f32,
+    max: f32,
+    sum_squares: f64,
+    count: usize,
+}
+
+impl Accumulator {
+    const EMPTY: Accumulator = Accumulator {min: 0.0, max: 0.0, sum_squares: 0.0, count: 0};
+    fn add(&mut self, sample: f32) {
+        if self.count == 0 {
+            self.min = sample;
+            self.max = sample;
+        } else {
+            self.min = self.min.min(sample);
+            self.max = self.max.max(sample);
+        }
+        self.sum_squares += sample as f64 * sample as f64;
+        self.count += 1;
+    }
+    fn merge(&mut self, other: &Accumulator) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum_squares += other.sum_squares;
+        self.count += other.count;
+    }
+    fn peak(&self) -> Peak {
+        let rms = if self.count == 0 { 0.0 } else { (self.sum_squares / self.count as f64).sqrt() };
+        Peak {min: self.min, max: self.max, rms: rms as f32}
+    }
+}
+
+/// Merges consecutive accumulators into `count` about equal groups.
+fn regroup(fine: &[Accumulator], count: usize) -> Vec<Accumulator> {
+    if fine.len() <= count {
+        return fine.to_vec();
+    }
+    (0..count)
+        .map(|i| {
+            let mut merged = Accumulator::EMPTY;
+            for x in &fine[i * fine.len() / count..(i + 1) * fine.len() / count] {
+                merged.merge(x);
+            }
+            merged
+        })
+        .collect()
+}
+
+/// Decodes the main audio stream of `input` and measures its peaks per
+/// channel. `channel_layout` downmixes first, e.g. `AV_CH_LAYOUT_MONO` for
+/// a single combined waveform.
+pub fn waveform_peaks(input: InputContext, buckets: Buckets, channel_layout: Option<u64>) -> Result<Waveform> {
+    let bucket_size = match buckets {
+        Buckets::Samples(0) | Buckets::Count(0) => {
+            return Err(Error::Invalid(String::from("zero waveform buckets")));
+        }
+        Buckets::Samples(x) => x,
+        Buckets::Count(_) => FINE_BUCKET,
+    };
+    let target = AudioTarget {
+        channel_layout,
+        sample_type: SampleType::F32,
+        planar: true,
+        ..AudioTarget::default()
+    };
+    let mut format: Option<(i32, u64)> = None;
+    let mut accumulators: Vec<Vec<Accumulator>> = Vec::new();
+    for buffer in audio::decode_audio_chunks(input, &target)? {
+        let buffer = buffer?;
+        if format.is_none() {
+            format = Some((buffer.sample_rate, buffer.channel_layout));
+            accumulators = vec![Vec::new(); buffer.channels];
+        }
+        let nb_samples = buffer.nb_samples();
+        let samples = match &buffer.samples {
+            Samples::F32(x) => x,
+            Samples::I16(_) => unreachable!("requested f32 samples"),
+        };
+        // WITHOUT `channel_layout`, CHANNELS ADDED MID-STREAM ARE IGNORED
+        for (channel, output) in accumulators.iter_mut().enumerate().take(buffer.channels) {
+            for &sample in &samples[channel * nb_samples..(channel + 1) * nb_samples] {
+                match output.last_mut() {
+                    Some(last) if last.count < bucket_size => last.add(sample),
+                    _ => {
+                        let mut next = Accumulator::EMPTY;
+                        next.add(sample);
+                        output.push(next);
+                    }
+                }
+            }
+        }
+    }
+    let (sample_rate, channel_layout) = format
+        .ok_or_else(|| Error::Invalid(String::from("no audio could be decoded")))?;
+    let total = accumulators.first().map(|x| x.iter().map(|x| x.count).sum::<usize>()).unwrap_or(0);
+    if let Buckets::Count(count) = buckets {
+        accumulators = accumulators.iter().map(|x| regroup(x, count)).collect();
+    }
+    let nb_buckets = accumulators.first().map(|x| x.len()).unwrap_or(0).max(1);
+    Ok(Waveform {
+        sample_rate,
+        channel_layout,
+        bucket_duration: total as f64 / nb_buckets as f64 / sample_rate as f64,
+        channels: accumulators
+            .iter()
+            .map(|x| x.iter().map(Accumulator::peak).collect())
+            .collect(),
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// IMAGES
+///////////////////////////////////////////////////////////////////////////////
+
+/// Amplitude scale of `showwavespic`/`showspectrumpic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmplitudeScale {
+    Linear,
+    Sqrt,
+    Cbrt,
+    Log,
+}
+
+impl AmplitudeScale {
+    fn name(self) -> &'static str {
+        match self {
+            AmplitudeScale::Linear => "lin",
+            AmplitudeScale::Sqrt => "sqrt",
+            AmplitudeScale::Cbrt => "cbrt",
+            AmplitudeScale::Log => "log",
+        }
+    }
+}
+
+/// Options of the `showwavespic` filter.
+#[derive(Debug, Clone)]
+pub struct WaveformImage {
+    pub width: i32,
+    pub height: i32,
+    /// One row per channel instead of all channels drawn over each other.
+    pub split_channels: bool,
+    /// `|`-separated colors, one per channel, e.g. `white|0x808080`.
+    pub colors: Option<String>,
+    pub scale: AmplitudeScale,
+}
+
+impl Default for WaveformImage {
+    fn default() -> Self {
+        WaveformImage {
+            width: 600,
+            height: 240,
+            split_channels: false,
+            colors: None,
+            scale: AmplitudeScale::Linear,
+        }
+    }
+}
+
+/// Options of the `showspectrumpic` filter.
+#[derive(Debug, Clone)]
+pub struct SpectrumImage {
+    /// Size of the spectrogram itself, excluding the legend.
+    pub width: i32,
+    pub height: i32,
+    /// Draw time and frequency axes and the color scale around it.
+    pub legend: bool,
+    /// One spectrogram per channel, stacked, instead of a combined one.
+    pub separate_channels: bool,
+    /// Color scheme, e.g. `intensity` or `viridis`.
+    pub color: Option<String>,
+    pub scale: AmplitudeScale,
+}
+
+impl Default for SpectrumImage {
+    fn default() -> Self {
+        SpectrumImage {
+            width: 1024,
+            height: 512,
+            legend: true,
+            separate_channels: false,
+            color: None,
+            scale: AmplitudeScale::Log,
+        }
+    }
+}
+
+/// Runs the main audio stream of `input` through `filter`, a filter that
+/// outputs a single picture at the end of its input.
+fn render(input: InputContext, filter: &str) -> Result<Image> {
+    let mut decoder = StreamDecoder::best(input, MediaType::Audio, &[])?;
+    let mut graph = FilterGraph::new()?;
+    graph.add_audio_input("in", AudioInput {format: decoder.audio_format(), time_base: decoder.time_base()})?;
+    graph.add_video_output("out", &[])?;
+    graph.parse(filter)?;
+    graph.configure()?;
+    while let Some(frame) = decoder.next_frame()? {
+        graph.push("in", &frame)?;
+    }
+    graph.push_eof("in")?;
+    match graph.pull("out")? {
+        Some(frame) => Image::from_frame(&frame),
+        None => Err(Error::Invalid(String::from("no audio could be decoded"))),
+    }
+}
+
+/// Draws the waveform of the main audio stream of `input`.
+pub fn render_waveform(input: InputContext, options: &WaveformImage) -> Result<Image> {
+    let mut filter = format!(
+        "showwavespic=s={}x{}:split_channels={}:scale={}",
+        options.width,
+        options.height,
+        options.split_channels as i32,
+        options.scale.name(),
+    );
+    if let Some(colors) = &options.colors {
+        filter.push_str(&format!(":colors='{}'", colors));
+    }
+    render(input, &filter)
+}
+
+/// Draws the spectrogram of the main audio stream of `input`.
+pub fn render_spectrum(input: InputContext, options: &SpectrumImage) -> Result<Image> {
+    let mut filter = format!(
+        "showspectrumpic=s={}x{}:legend={}:mode={}:scale={}",
+        options.width,
+        options.height,
+        options.legend as i32,
+        if options.separate_channels { "separate" } else { "combined" },
+        options.scale.name(),
+    );
+    if let Some(color) = &options.color {
+        filter.push_str(&format!(":color={}", color));
+    }
+    render(input, &filter)
+}
@@ -1,8 +1,679 @@
-#![allow(unused)]
-pub mod sys;
-pub mod api;
-pub mod extra;
+//! `ffmpeg-dev` command line tool.
+//!
+//! Inspects and processes media with exactly the FFmpeg build this crate
+//! bundles, rather than whatever `ffmpeg` happens to be installed.
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::str::FromStr;
+use ffmpeg_dev::sys;
+use ffmpeg_dev::extra::audio::analysis::{self, AnalyzeOptions};
+use ffmpeg_dev::extra::capabilities::{self, FilterPad, MediaType};
+use ffmpeg_dev::extra::decode::StreamDecoder;
+use ffmpeg_dev::extra::detect::{self, DetectOptions};
+use ffmpeg_dev::extra::format::{InputContext, OutputContext};
+use ffmpeg_dev::extra::frame::Frame;
+use ffmpeg_dev::extra::hash::{self, HashAlgorithm, HashOptions, HashSource};
+use ffmpeg_dev::extra::image::Image;
+use ffmpeg_dev::extra::image::encode::{EncodeOptions, ImageCodec, encode_image};
+use ffmpeg_dev::extra::image::transform::{Resize, ResizeMode};
+use ffmpeg_dev::extra::metadata::{self, CoverArt, FfMetadata, MetadataEdit};
+use ffmpeg_dev::extra::probe;
+use ffmpeg_dev::extra::remux::{self, RemuxOptions, StreamSelector};
+use ffmpeg_dev::extra::subtitle::{self, SubtitleFormat};
+use ffmpeg_dev::extra::thumbnail::{self, ExtractOptions, Selection};
+use ffmpeg_dev::extra::transcode::{EncoderSettings, StreamPlan, Transcoder};
+use ffmpeg_dev::extra::waveform::{self, SpectrumImage, WaveformImage};
+
+type CliResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "\
+usage: ffmpeg-dev <command> [arguments]
+
+commands:
+    probe <input>
+        show the container, streams, chapters and programs
+    remux <input> <output> [--streams video,audio,eng,0] [--start S] [--end S]
+          [--faststart] [--fragmented]
+        copy the streams into the container given by the output extension,
+        cutting on keyframes
+    decode-frames <input> <dir> [--format jpg] [--every N] [--max N]
+        decode the video frames into numbered images
+    thumbnail <input> <output> [--at S] [--size WxH] [--mode fit|fill|crop] [--quality 1-100]
+        decode the video frame at S seconds (default: a representative one
+        near the start) into a resized, upright image
+    transcode <input> <output> [--vcodec mpeg4] [--acodec aac] [--size WxH]
+              [--video-bitrate N] [--audio-bitrate N]
+        re-encode the audio and video streams; `copy` as the codec keeps
+        them as they are
+    waveform <input> <output> [--size WxH] [--spectrum]
+        draw the waveform, or spectrogram, of the audio into an image
+    analyze-audio <input> [--silence-threshold dB] [--silence-duration S]
+        measure the loudness (EBU R128), levels and silences of the audio
+    detect <input> [--scene-threshold 0-1]
+        find the scene cuts, black and frozen segments and the crop of the
+        video
+    hash <input> [--algorithm adler32|crc32|md5|sha256|...] [--packets] [--streams]
+        print FATE-style checksums of every decoded frame (or packet with
+        --packets), or of each whole stream with --streams
+    subtitles <input> <output> [--stream N]
+        extract the subtitle track into an .srt, .vtt, .ass or .mp4 file,
+        converting text subtitles as needed, or copy any track (bitmap ones
+        included) into an .mks file
+    metadata <input> [<output>] [--ffmetadata FILE] [--title T] [--cover IMAGE]
+             [--extract-cover FILE]
+        print the tags and chapters as an ffmetadata file, or copy the
+        streams into <output> with those of FILE, a new title or cover art
+    list-codecs
+    list-formats
+    list-protocols
+    list-filters
+    list-bsfs
+    buildconf
+        show the FFmpeg version and configure flags
+";
+
+///////////////////////////////////////////////////////////////////////////////
+// ARGUMENTS
+///////////////////////////////////////////////////////////////////////////////
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Args {
+    /// `--name value` options, except for the given value-less `switches`.
+    fn parse(args: &[String], switches: &[&str]) -> CliResult<Self> {
+        let mut output = Args {
+            positional: Vec::new(),
+            options: HashMap::new(),
+            switches: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                output.positional.push(arg.clone());
+            } else if switches.contains(&arg.as_str()) {
+                output.switches.push(arg.clone());
+            } else {
+                let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                output.options.insert(arg.clone(), value.clone());
+            }
+        }
+        Ok(output)
+    }
+    fn positional(&self, index: usize, name: &str) -> CliResult<&str> {
+        match self.positional.get(index) {
+            Some(x) => Ok(x.as_str()),
+            None => Err(format!("missing <{}>", name).into()),
+        }
+    }
+    fn option<T: FromStr>(&self, name: &str) -> CliResult<Option<T>> {
+        match self.options.get(name) {
+            Some(x) => match x.parse() {
+                Ok(x) => Ok(Some(x)),
+                Err(_) => Err(format!("invalid value for {}: {:?}", name, x).into()),
+            },
+            None => Ok(None),
+        }
+    }
+    fn has(&self, switch: &str) -> bool {
+        self.switches.iter().any(|x| x == switch)
+    }
+}
+
+/// `WxH`, e.g. `320x240`.
+fn parse_size(size: &str) -> CliResult<(i32, i32)> {
+    let mut parts = size.splitn(2, 'x').map(|x| x.parse::<i32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("invalid size {:?}, expected WxH", size).into()),
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn image_codec(extension: &str) -> CliResult<ImageCodec> {
+    match extension {
+        "jpg" | "jpeg" => Ok(ImageCodec::Jpeg),
+        "gif" => Ok(ImageCodec::Gif),
+        "tif" | "tiff" => Ok(ImageCodec::Tiff),
+        "bmp" => Ok(ImageCodec::Bmp),
+        "pam" => Ok(ImageCodec::Pam),
+        _ => Err(format!("unsupported image format {:?}", extension).into()),
+    }
+}
+
+unsafe fn c_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// DECODING
+///////////////////////////////////////////////////////////////////////////////
+
+/// Hands every decoded frame to `f`, until it returns `false`.
+fn decode_video<F: FnMut(&Frame) -> CliResult<bool>>(decoder: &mut StreamDecoder, mut f: F) -> CliResult<()> {
+    while let Some(frame) = decoder.next_frame()? {
+        if !f(&frame)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PROBE / REMUX / IMAGES
+///////////////////////////////////////////////////////////////////////////////
+
+fn probe_command(args: &Args) -> CliResult<()> {
+    let info = probe::probe(args.positional(0, "input")?)?;
+    let format = &info.format;
+    println!(
+        "format: {} ({})",
+        format.format_name,
+        format.format_long_name.clone().unwrap_or_default(),
+    );
+    if let Some(duration) = format.duration {
+        println!("duration: {:.3}s", duration);
+    }
+    if let Some(bit_rate) = format.bit_rate {
+        println!("bit rate: {} kb/s", bit_rate / 1000);
+    }
+    for (key, value) in &format.tags {
+        println!("    {}: {}", key, value);
+    }
+    for stream in &info.streams {
+        let mut details = vec![stream.codec_name.clone()];
+        details.extend(stream.profile.clone());
+        if let (Some(width), Some(height)) = (stream.width, stream.height) {
+            details.push(format!("{}x{}", width, height));
+        }
+        details.extend(stream.pix_fmt.clone());
+        if let Some(rate) = stream.avg_frame_rate {
+            details.push(format!("{:.3} fps", rate.to_f64()));
+        }
+        if let Some(sample_rate) = stream.sample_rate {
+            details.push(format!("{} Hz", sample_rate));
+        }
+        details.extend(stream.channel_layout.clone());
+        details.extend(stream.sample_fmt.clone());
+        println!(
+            "stream #{} {}{}: {}",
+            stream.index,
+            stream.codec_type.clone().unwrap_or_default(),
+            stream.language.as_ref().map(|x| format!(" ({})", x)).unwrap_or_default(),
+            details.join(", "),
+        );
+        for side_data in &stream.side_data_list {
+            match side_data.rotation {
+                Some(rotation) => println!("    {}: rotation {:.2}", side_data.side_data_type, rotation),
+                None => println!("    {}", side_data.side_data_type),
+            }
+        }
+    }
+    for chapter in &info.chapters {
+        let title = chapter.tags.get("title").cloned().unwrap_or_default();
+        println!("chapter #{}: {:.3}s - {:.3}s {}", chapter.id, chapter.start_time, chapter.end_time, title);
+    }
+    for program in &info.programs {
+        let indexes = program.streams.iter().map(|x| x.index).collect::<Vec<_>>();
+        println!("program {}: streams {:?}", program.program_num, indexes);
+    }
+    Ok(())
+}
+
+/// `video`, `audio`, `subtitle`, a stream index or a language tag.
+fn stream_selector(x: &str) -> StreamSelector {
+    match x {
+        "video" => StreamSelector::Type(MediaType::Video),
+        "audio" => StreamSelector::Type(MediaType::Audio),
+        "subtitle" => StreamSelector::Type(MediaType::Subtitle),
+        _ => match x.parse() {
+            Ok(index) => StreamSelector::Index(index),
+            Err(_) => StreamSelector::Language(String::from(x)),
+        },
+    }
+}
+
+fn remux_command(args: &Args) -> CliResult<()> {
+    let mut movflags = String::new();
+    if args.has("--faststart") {
+        movflags.push_str("+faststart");
+    }
+    if args.has("--fragmented") {
+        movflags.push_str("+frag_keyframe+empty_moov+default_base_moof");
+    }
+    let mut options = RemuxOptions {
+        start: args.option("--start")?,
+        end: args.option("--end")?,
+        ..RemuxOptions::default()
+    };
+    if let Some(streams) = args.option::<String>("--streams")? {
+        options.streams = streams.split(',').map(stream_selector).collect();
+    }
+    if !movflags.is_empty() {
+        options.muxer_options.push((String::from("movflags"), movflags));
+    }
+    remux::remux(args.positional(0, "input")?, args.positional(1, "output")?, &options)?;
+    Ok(())
+}
+
+fn decode_frames_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let mut decoder = StreamDecoder::best(input, MediaType::Video, &[])?;
+    let dir = Path::new(args.positional(1, "dir")?);
+    let format = args.option::<String>("--format")?.unwrap_or_else(|| String::from("jpg"));
+    let codec = image_codec(&format)?;
+    let every = args.option::<usize>("--every")?.unwrap_or(1).max(1);
+    let max = args.option::<usize>("--max")?;
+    std::fs::create_dir_all(dir)?;
+    let mut index = 0;
+    let mut written = 0;
+    decode_video(&mut decoder, |frame| {
+        if index % every == 0 {
+            let image = Image::from_frame(frame)?;
+            let data = encode_image(&image, codec, &EncodeOptions::default())?;
+            written += 1;
+            std::fs::write(dir.join(format!("frame-{:06}.{}", written, format)), data)?;
+        }
+        index += 1;
+        Ok(max.map(|max| written < max).unwrap_or(true))
+    })?;
+    println!("wrote {} frames", written);
+    Ok(())
+}
+
+fn thumbnail_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let output = args.positional(1, "output")?;
+    let codec = image_codec(&extension(output))?;
+    let (width, height) = match args.option::<String>("--size")? {
+        Some(size) => parse_size(&size)?,
+        None => (320, 320),
+    };
+    let mode = match args.option::<String>("--mode")?.as_ref().map(|x| x.as_str()) {
+        None | Some("fit") => ResizeMode::Fit,
+        Some("fill") => ResizeMode::Fill,
+        Some("crop") => ResizeMode::Crop,
+        Some(x) => return Err(format!("invalid --mode {:?}", x).into()),
+    };
+    let options = ExtractOptions {
+        resize: Some(Resize {width, height, mode, upscale: false}),
+        ..ExtractOptions::default()
+    };
+    let image = match args.option::<f64>("--at")? {
+        Some(at) => {
+            let mut frames = thumbnail::extract_frames(input, Selection::Timestamps(vec![at]), &options)?;
+            frames.next().ok_or("no video frame at that time")??.image
+        }
+        None => thumbnail::poster_frame(input, &options)?,
+    };
+    let options = EncodeOptions {
+        quality: args.option::<u8>("--quality")?,
+        ..EncodeOptions::default()
+    };
+    std::fs::write(output, encode_image(&image, codec, &options)?)?;
+    Ok(())
+}
+
+fn waveform_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let output = args.positional(1, "output")?;
+    let codec = image_codec(&extension(output))?;
+    let size = match args.option::<String>("--size")? {
+        Some(size) => Some(parse_size(&size)?),
+        None => None,
+    };
+    let image = if args.has("--spectrum") {
+        let mut options = SpectrumImage::default();
+        if let Some((width, height)) = size {
+            options.width = width;
+            options.height = height;
+        }
+        waveform::render_spectrum(input, &options)?
+    } else {
+        let mut options = WaveformImage::default();
+        if let Some((width, height)) = size {
+            options.width = width;
+            options.height = height;
+        }
+        waveform::render_waveform(input, &options)?
+    };
+    std::fs::write(output, encode_image(&image, codec, &EncodeOptions::default())?)?;
+    Ok(())
+}
+
+fn optional_value(value: Option<f64>, unit: &str) -> String {
+    match value {
+        Some(x) => format!("{:.1} {}", x, unit),
+        None => String::from("unknown"),
+    }
+}
+
+fn analyze_audio_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let mut options = AnalyzeOptions::default();
+    if let Some(threshold) = args.option("--silence-threshold")? {
+        options.silence_threshold = threshold;
+    }
+    if let Some(duration) = args.option("--silence-duration")? {
+        options.silence_min_duration = duration;
+    }
+    let analysis = analysis::analyze_audio(input, &options)?;
+    println!("duration: {:.3} s", analysis.duration);
+    println!("integrated loudness: {}", optional_value(analysis.integrated_loudness, "LUFS"));
+    println!("loudness range: {}", optional_value(analysis.loudness_range, "LU"));
+    println!("true peak: {}", optional_value(analysis.true_peak, "dBTP"));
+    println!("rms level: {}", optional_value(analysis.rms_level, "dBFS"));
+    println!("peak level: {}", optional_value(analysis.peak_level, "dBFS"));
+    for (index, channel) in analysis.channels.iter().enumerate() {
+        println!("channel {}: rms {:.1} dBFS, peak {:.1} dBFS", index, channel.rms_level, channel.peak_level);
+    }
+    for silence in &analysis.silences {
+        println!("silence: {:.3} - {:.3} s", silence.start, silence.end);
+    }
+    Ok(())
+}
+
+fn detect_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let mut options = DetectOptions::default();
+    if let Some(threshold) = args.option("--scene-threshold")? {
+        options.scene_threshold = Some(threshold);
+    }
+    let analysis = detect::analyze_video(input, &options)?;
+    println!("duration: {:.3} s", analysis.duration);
+    for scene in &analysis.scene_changes {
+        println!("scene change: {:.3} s (score {:.3})", scene.time, scene.score);
+    }
+    for segment in &analysis.black_segments {
+        println!("black: {:.3} - {:.3} s", segment.start, segment.end);
+    }
+    for segment in &analysis.freeze_segments {
+        println!("freeze: {:.3} - {:.3} s", segment.start, segment.end);
+    }
+    if let Some(crop) = analysis.crop {
+        println!("crop: {}", crop.filter());
+    }
+    Ok(())
+}
+
+fn hash_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let mut options = HashOptions::default();
+    if let Some(algorithm) = args.option::<String>("--algorithm")? {
+        options.algorithm = match algorithm.to_lowercase().as_str() {
+            "adler32" | "crc" => HashAlgorithm::Adler32,
+            "crc32" => HashAlgorithm::Crc32,
+            "md5" => HashAlgorithm::Md5,
+            "sha256" => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Named(algorithm),
+        };
+    }
+    if args.has("--packets") {
+        options.source = HashSource::Packets;
+    }
+    let text = if args.has("--streams") {
+        hash::stream_hashes(input, &options)?.text
+    } else {
+        hash::frame_hashes(input, &options)?.text
+    };
+    print!("{}", text);
+    Ok(())
+}
+
+fn subtitles_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let output = args.positional(1, "output")?;
+    let format = match extension(output).as_str() {
+        "srt" => SubtitleFormat::Srt,
+        "vtt" => SubtitleFormat::WebVtt,
+        "ass" | "ssa" => SubtitleFormat::Ass,
+        "mp4" | "m4s" => SubtitleFormat::MovText,
+        "mks" | "mkv" => SubtitleFormat::Matroska,
+        x => return Err(format!("unsupported subtitle format {:?}", x).into()),
+    };
+    let data = subtitle::extract_subtitles(input, args.option("--stream")?, format)?;
+    std::fs::write(output, data)?;
+    Ok(())
+}
+
+fn metadata_command(args: &Args) -> CliResult<()> {
+    let input_path = args.positional(0, "input")?;
+    let input = InputContext::open(input_path)?;
+    if let Some(path) = args.option::<String>("--extract-cover")? {
+        let cover = metadata::cover_art(&input).into_iter().next().ok_or("no cover art")?;
+        std::fs::write(path, cover.data)?;
+    }
+    let output = match args.positional.get(1) {
+        Some(x) => x,
+        None => {
+            print!("{}", FfMetadata::of_input(&input).to_text()?);
+            return Ok(());
+        }
+    };
+    let mut edit = match args.option::<String>("--ffmetadata")? {
+        Some(path) => FfMetadata::parse(&std::fs::read_to_string(path)?)?.to_edit(&input),
+        None => MetadataEdit::default(),
+    };
+    if let Some(title) = args.option::<String>("--title")? {
+        edit.global.push((String::from("title"), title));
+    }
+    if let Some(path) = args.option::<String>("--cover")? {
+        edit.remove_cover_art = true;
+        edit.cover_art.push(CoverArt::new(std::fs::read(path)?));
+    }
+    drop(input);
+    let options = RemuxOptions {
+        keep_data: true,
+        edit,
+        ..RemuxOptions::default()
+    };
+    remux::remux(input_path, output, &options)?;
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// TRANSCODE
+///////////////////////////////////////////////////////////////////////////////
+
+/// `copy` as in `ffmpeg -c copy`, otherwise an encoder name.
+fn stream_plan(codec: &str, filter: Option<String>, bit_rate: Option<i64>) -> StreamPlan {
+    if codec == "copy" {
+        return StreamPlan::Copy;
+    }
+    let mut settings = EncoderSettings::new(codec);
+    settings.filter = filter;
+    settings.bit_rate = bit_rate;
+    StreamPlan::Transcode(settings)
+}
+
+fn transcode_command(args: &Args) -> CliResult<()> {
+    let input = InputContext::open(args.positional(0, "input")?)?;
+    let output = OutputContext::new(args.positional(1, "output")?, None)?;
+    let mut transcoder = Transcoder::new(input, output)?;
+    let video_filter = match args.option::<String>("--size")? {
+        Some(size) => {
+            let (width, height) = parse_size(&size)?;
+            Some(format!("scale={}:{}", width, height))
+        }
+        None => None,
+    };
+    let vcodec = args.option::<String>("--vcodec")?.unwrap_or_else(|| String::from("mpeg4"));
+    let acodec = args.option::<String>("--acodec")?.unwrap_or_else(|| String::from("aac"));
+    transcoder.set_plan_for(
+        MediaType::Video,
+        stream_plan(&vcodec, video_filter, args.option("--video-bitrate")?),
+    );
+    transcoder.set_plan_for(
+        MediaType::Audio,
+        stream_plan(&acodec, None, args.option("--audio-bitrate")?),
+    );
+    let mut last_percent = None;
+    transcoder.on_progress(move |progress| {
+        let percent = progress.fraction().map(|x| (x * 100.0) as u32);
+        if percent.is_some() && percent != last_percent {
+            eprint!("\r{:>3}%", percent.unwrap_or_default());
+            last_percent = percent;
+        }
+    });
+    transcoder.run()?;
+    eprintln!();
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// BUILD INFO
+///////////////////////////////////////////////////////////////////////////////
+
+fn list_codecs_command() -> CliResult<()> {
+    println!("D. = decoder, .E = encoder; V/A/S = video/audio/subtitle");
+    for codec in capabilities::codecs() {
+        let kind = match codec.media_type {
+            MediaType::Video => 'V',
+            MediaType::Audio => 'A',
+            MediaType::Subtitle => 'S',
+            _ => '?',
+        };
+        println!(
+            " {}{}{} {:<24} {}",
+            if codec.is_decoder() { 'D' } else { '.' },
+            if codec.is_encoder { 'E' } else { '.' },
+            kind,
+            codec.name,
+            codec.long_name.unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+fn list_formats_command() -> CliResult<()> {
+    println!("D. = demuxer, .E = muxer");
+    for format in capabilities::demuxers() {
+        println!(" D. {:<24} {}", format.name, format.long_name.unwrap_or_default());
+    }
+    for format in capabilities::muxers() {
+        println!(" .E {:<24} {}", format.name, format.long_name.unwrap_or_default());
+    }
+    Ok(())
+}
+
+fn list_protocols_command() -> CliResult<()> {
+    println!("I. = input, .O = output");
+    for protocol in capabilities::protocols() {
+        println!(
+            " {}{} {}",
+            if protocol.input { 'I' } else { '.' },
+            if protocol.output { 'O' } else { '.' },
+            protocol.name,
+        );
+    }
+    Ok(())
+}
+
+fn list_filters_command() -> CliResult<()> {
+    for filter in capabilities::filters() {
+        let pads = |pads: &[FilterPad], dynamic: bool| {
+            let mut output = pads
+                .iter()
+                .map(|x| match x.media_type {
+                    MediaType::Video => "V",
+                    MediaType::Audio => "A",
+                    _ => "?",
+                })
+                .collect::<String>();
+            if dynamic {
+                output.push('N');
+            }
+            if output.is_empty() {
+                output.push('|');
+            }
+            output
+        };
+        println!(
+            " {:<24} {:>4}->{:<4} {}",
+            filter.name,
+            pads(&filter.inputs, filter.has_dynamic_inputs()),
+            pads(&filter.outputs, filter.has_dynamic_outputs()),
+            filter.description.clone().unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+fn list_bsfs_command() -> CliResult<()> {
+    for filter in capabilities::bitstream_filters() {
+        println!(" {}", filter.name);
+    }
+    Ok(())
+}
+
+fn buildconf_command() -> CliResult<()> {
+    unsafe {
+        println!("FFmpeg {}", c_string(sys::av_version_info()));
+        println!("license: {}", c_string(sys::avutil_license()));
+        println!("configuration:");
+        for flag in c_string(sys::avutil_configuration()).split_whitespace() {
+            println!("    {}", flag);
+        }
+    }
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// MAIN
+///////////////////////////////////////////////////////////////////////////////
+
+fn run(command: &str, args: &[String]) -> CliResult<()> {
+    match command {
+        "probe" => probe_command(&Args::parse(args, &[])?),
+        "remux" => remux_command(&Args::parse(args, &["--faststart", "--fragmented"])?),
+        "decode-frames" => decode_frames_command(&Args::parse(args, &[])?),
+        "thumbnail" => thumbnail_command(&Args::parse(args, &[])?),
+        "transcode" => transcode_command(&Args::parse(args, &[])?),
+        "waveform" => waveform_command(&Args::parse(args, &["--spectrum"])?),
+        "analyze-audio" => analyze_audio_command(&Args::parse(args, &[])?),
+        "detect" => detect_command(&Args::parse(args, &[])?),
+        "hash" => hash_command(&Args::parse(args, &["--packets", "--streams"])?),
+        "subtitles" => subtitles_command(&Args::parse(args, &[])?),
+        "metadata" => metadata_command(&Args::parse(args, &[])?),
+        "list-codecs" => list_codecs_command(),
+        "list-formats" => list_formats_command(),
+        "list-protocols" => list_protocols_command(),
+        "list-filters" => list_filters_command(),
+        "list-bsfs" => list_bsfs_command(),
+        "buildconf" => buildconf_command(),
+        _ => Err(format!("unknown command {:?}\n\n{}", command, USAGE).into()),
+    }
+}
 
 fn main() {
-    
-}
\ No newline at end of file
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let command = match args.first() {
+        Some(x) if x != "help" && x != "-h" && x != "--help" => x.clone(),
+        _ => {
+            print!("{}", USAGE);
+            return;
+        }
+    };
+    unsafe {
+        sys::av_log_set_level(sys::AV_LOG_ERROR as c_int);
+    }
+    if let Err(err) = run(&command, &args[1..]) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,102 @@
+//! Golden-file checks of decoder and demuxer output, the way FATE does them.
+use std::io::Cursor;
+use ffmpeg_dev::extra::format::{InputContext, OutputContext};
+use ffmpeg_dev::extra::hash::{FrameHashes, HashAlgorithm, HashOptions, HashSource, frame_hashes};
+use ffmpeg_dev::extra::image::{ImageHint, decode_image};
+use ffmpeg_dev::extra::io::IoContext;
+use ffmpeg_dev::extra::remux::{self, RemuxOptions};
+
+// 2 FRAMES OF 16x16 YUV420P AT 25 FPS
+const GRADIENT: &[u8] = include_bytes!("fixtures/gradient.y4m");
+// 3000 SAMPLES OF A 440 HZ TONE, MONO S16LE AT 8 KHZ
+const TONE: &[u8] = include_bytes!("fixtures/tone.wav");
+
+fn open(data: &[u8]) -> InputContext {
+    let io = IoContext::from_seekable_reader(Cursor::new(data.to_vec())).unwrap();
+    InputContext::open_io(io, None, &[]).unwrap()
+}
+
+/// Without the comment lines, which name the library version.
+fn frame_lines(text: &str) -> Vec<&str> {
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+fn hashes(data: &[u8], algorithm: HashAlgorithm, source: HashSource) -> FrameHashes {
+    frame_hashes(open(data), &HashOptions {algorithm, source}).unwrap()
+}
+
+fn decoded() -> HashSource {
+    HashSource::Decoded {audio_codec: String::from("pcm_s16le")}
+}
+
+/// `av_adler32_update(0, ...)`, as `framecrc` computes it.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+    for x in data {
+        a = (a + *x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// `data` remuxed into NUT, with every timestamp moved by `offset` seconds.
+fn shifted_nut(data: &[u8], offset: f64) -> Vec<u8> {
+    let (output, file) = OutputContext::in_memory("nut").unwrap();
+    let options = RemuxOptions {
+        normalize_timestamps: false,
+        offset,
+        ..RemuxOptions::default()
+    };
+    remux::remux_contexts(open(data), output, &options).unwrap();
+    file.take()
+}
+
+#[test]
+fn packets_framecrc() {
+    let hashes = hashes(GRADIENT, HashAlgorithm::Adler32, HashSource::Packets);
+    assert_eq!(frame_lines(&hashes.text), frame_lines(include_str!("fixtures/gradient.framecrc")));
+    assert_eq!(hashes.frames.len(), 2);
+    assert_eq!(hashes.frames[1].pts, 1);
+}
+
+#[test]
+fn decoded_video_framemd5() {
+    let hashes = hashes(GRADIENT, HashAlgorithm::Md5, decoded());
+    assert_eq!(frame_lines(&hashes.text), frame_lines(include_str!("fixtures/gradient.framemd5")));
+}
+
+#[test]
+fn decoded_audio_framemd5() {
+    let hashes = hashes(TONE, HashAlgorithm::Md5, decoded());
+    assert_eq!(frame_lines(&hashes.text), frame_lines(include_str!("fixtures/tone.framemd5")));
+}
+
+#[test]
+fn packets_audio_framemd5() {
+    let hashes = hashes(TONE, HashAlgorithm::Md5, HashSource::Packets);
+    assert_eq!(frame_lines(&hashes.text), frame_lines(include_str!("fixtures/tone.framemd5")));
+}
+
+#[test]
+fn decode_image_matches_framecrc() {
+    let image = decode_image(GRADIENT, &ImageHint::Auto).unwrap();
+    let golden = frame_lines(include_str!("fixtures/gradient.framecrc"));
+    let fields = golden[0].split(',').map(str::trim).collect::<Vec<_>>();
+    assert_eq!((image.width(), image.height()), (16, 16));
+    assert_eq!(image.data().len().to_string(), fields[4]);
+    assert_eq!(format!("0x{:08x}", adler32(image.data())), fields[5]);
+}
+
+#[test]
+fn start_time_is_subtracted() {
+    let reference = shifted_nut(GRADIENT, 0.0);
+    let shifted = shifted_nut(GRADIENT, 10.0);
+    for source in vec![HashSource::Packets, decoded()] {
+        let expected = hashes(&reference, HashAlgorithm::Md5, source.clone());
+        let actual = hashes(&shifted, HashAlgorithm::Md5, source);
+        assert_eq!(frame_lines(&actual.text), frame_lines(&expected.text));
+        assert_eq!(actual.frames[0].pts, 0);
+    }
+}